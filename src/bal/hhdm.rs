@@ -3,5 +3,9 @@ use lazy_static::lazy_static;
 use crate::hal::memory::VirtLv1PageAddress;
 
 lazy_static! {
-    pub static ref HHDM_OFFSET: VirtLv1PageAddress = super::bootloader::hhdm::get_hhdm_start();
+    ///Copied on first use, has to be used before the bootloader memory is reclaimed
+    pub static ref HHDM_OFFSET: VirtLv1PageAddress = {
+        super::check_bootloader_memory();
+        super::bootloader::hhdm::get_hhdm_start()
+    };
 }
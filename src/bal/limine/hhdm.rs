@@ -1,3 +1,16 @@
+use limine::request::HhdmRequest;
+
 use crate::hal::memory::VirtLv1PageAddress;
 
-pub fn get_hhdm_start() -> VirtLv1PageAddress {}
+#[used]
+static HHDM_REQUEST: HhdmRequest = HhdmRequest::new();
+
+pub fn get_hhdm_start() -> VirtLv1PageAddress {
+    VirtLv1PageAddress::new(
+        HHDM_REQUEST
+            .get_response()
+            .expect("LIMINE ERROR: NO HHDM RESPONSE")
+            .offset(),
+    )
+    .expect("LIMINE ERROR: INVALID HHDM OFFSET")
+}
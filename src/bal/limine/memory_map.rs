@@ -0,0 +1,41 @@
+use limine::{memory_map::EntryType, request::MemoryMapRequest};
+
+use crate::{
+    bal::memory_map::{MemoryMapEntry, MemoryMapEntryType},
+    hal::memory::PhysAddress,
+};
+
+#[used]
+static MEMORY_MAP_REQUEST: MemoryMapRequest = MemoryMapRequest::new();
+
+pub fn get_memory_map_entry_count() -> usize {
+    MEMORY_MAP_REQUEST
+        .get_response()
+        .expect("LIMINE ERROR: NO MEMORY MAP RESPONSE")
+        .entries()
+        .len()
+}
+
+pub fn get_memory_map_entry(index: usize) -> Option<MemoryMapEntry> {
+    let entry = MEMORY_MAP_REQUEST.get_response()?.entries().get(index)?;
+
+    Some(MemoryMapEntry {
+        base: PhysAddress::new_maskoff(entry.base),
+        length: entry.length,
+        entry_type: decode_entry_type(entry.entry_type),
+    })
+}
+
+fn decode_entry_type(entry_type: EntryType) -> MemoryMapEntryType {
+    match entry_type {
+        EntryType::USABLE => MemoryMapEntryType::Usable,
+        EntryType::ACPI_RECLAIMABLE => MemoryMapEntryType::AcpiReclaimable,
+        EntryType::ACPI_NVS => MemoryMapEntryType::AcpiNvs,
+        EntryType::BAD_MEMORY => MemoryMapEntryType::BadMemory,
+        EntryType::BOOTLOADER_RECLAIMABLE => MemoryMapEntryType::BootloaderReclaimable,
+        EntryType::EXECUTABLE_AND_MODULES => MemoryMapEntryType::KernelAndModules,
+        EntryType::FRAMEBUFFER => MemoryMapEntryType::Framebuffer,
+        //Unknown types are never touched
+        _ => MemoryMapEntryType::Reserved,
+    }
+}
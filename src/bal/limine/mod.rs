@@ -1,3 +1,5 @@
 //Limine
 
 pub(super) mod hhdm;
+pub(super) mod memory_map;
+mod paging_mode;
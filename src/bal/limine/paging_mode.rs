@@ -0,0 +1,6 @@
+use limine::{paging::Mode, request::PagingModeRequest};
+
+//Asks for 5 level paging, Limine falls back to 4 level paging if the cpu has no LA57
+//The hal reads the active mode from CR4 so no response is needed
+#[used]
+static PAGING_MODE_REQUEST: PagingModeRequest = PagingModeRequest::new().with_mode(Mode::FIVE_LEVEL);
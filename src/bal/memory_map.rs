@@ -0,0 +1,49 @@
+use crate::hal::memory::{MemoryAddressErrors, PhysAddress, PhysRange};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MemoryMapEntryType {
+    Usable,
+    Reserved,
+    AcpiReclaimable,
+    AcpiNvs,
+    BadMemory,
+    BootloaderReclaimable,
+    KernelAndModules,
+    Framebuffer,
+}
+
+///Bootloader independent copy of a single memory map entry
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryMapEntry {
+    pub base: PhysAddress,
+    pub length: u64,
+    pub entry_type: MemoryMapEntryType,
+}
+
+impl MemoryMapEntry {
+    ///Returns the first address after the entry
+    #[inline]
+    pub fn get_end(&self) -> u64 {
+        self.base.get_u64() + self.length
+    }
+
+    #[inline]
+    pub fn get_range(&self) -> Result<PhysRange, MemoryAddressErrors> {
+        PhysRange::with_size(self.base.get_u64(), self.length)
+    }
+}
+
+pub fn get_memory_map_entry_count() -> usize {
+    super::check_bootloader_memory();
+    super::bootloader::memory_map::get_memory_map_entry_count()
+}
+
+pub fn get_memory_map_entry(index: usize) -> Option<MemoryMapEntry> {
+    super::check_bootloader_memory();
+    super::bootloader::memory_map::get_memory_map_entry(index)
+}
+
+///Iterates over the memory map in the order given by the bootloader (Limine: sorted by base address, non overlapping)
+pub fn iter_memory_map() -> impl Iterator<Item = MemoryMapEntry> {
+    (0..get_memory_map_entry_count()).filter_map(get_memory_map_entry)
+}
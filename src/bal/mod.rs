@@ -5,3 +5,29 @@
 mod bootloader;
 
 pub mod hhdm;
+pub mod memory_map;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+static BOOTLOADER_MEMORY_RECLAIMED: AtomicBool = AtomicBool::new(false);
+
+///Called once the bootloader reclaimable memory is handed to the PMM \
+///Everything needed from the bootloader has to be copied before, every accessor of the bal panics afterwards
+pub fn set_bootloader_memory_reclaimed() {
+    if BOOTLOADER_MEMORY_RECLAIMED.swap(true, Ordering::AcqRel) {
+        panic!("BAL ERROR: BOOTLOADER MEMORY ALREADY RECLAIMED");
+    }
+}
+
+#[inline]
+pub fn is_bootloader_memory_reclaimed() -> bool {
+    BOOTLOADER_MEMORY_RECLAIMED.load(Ordering::Acquire)
+}
+
+//the bootloader responses are gone after the reclamation
+#[inline]
+fn check_bootloader_memory() {
+    if is_bootloader_memory_reclaimed() {
+        panic!("BAL ERROR: BOOTLOADER MEMORY ACCESSED AFTER RECLAMATION");
+    }
+}
@@ -0,0 +1,12 @@
+//Early text output of the kernel, needs no other OS services
+//x86_64: COM1 serial port
+
+///Called early in OS Boot, before anything is written to the console
+pub fn init_console() {
+    super::arch::serial::init_serial();
+}
+
+///Not synchronized, use the kernel log instead of calling this directly
+pub(crate) fn write_console(bytes: &[u8]) {
+    super::arch::serial::write_serial(bytes);
+}
@@ -1 +1,9 @@
 //implements the cpu local data structure (its more than just a wrapper around arch/cpu.rs) maybe need a better name
+
+pub const MAX_CPUS: usize = super::arch::cpu::MAX_CPUS;
+
+///Index of the executing cpu, smaller than MAX_CPUS
+#[inline]
+pub fn get_cpu_id() -> u32 {
+    super::arch::cpu::get_cpu_id()
+}
@@ -7,13 +7,20 @@ pub(crate) const MASK_ALL: IrqLevel = super::arch::interrupt::MASK_ALL;
 
 
 ///Is reentrant safe as the IrqLevel is a core local hardware mechanism
-pub(super) unsafe fn set_irq_level(value: IrqLevel){
+pub(crate) unsafe fn set_irq_level(value: IrqLevel){
     super::arch::interrupt::set_irq_level(value);
 }
 
 ///Is reentrant safe as the IrqLevel is a core local hardware mechanism \
 ///Increases the IrqLevel to the requested level if the current Level is lower \
 ///returns the old IrqLevel
-pub(super) unsafe  fn bump_irq_level(value: IrqLevel) -> IrqLevel{
+pub(crate) unsafe fn bump_irq_level(value: IrqLevel) -> IrqLevel{
     super::arch::interrupt::bump_irq_level(value)
-}
\ No newline at end of file
+}
+
+///Has to be called by busy waits, the IPIs of other cpus may be masked by the IrqLevel \
+///and those cpus can wait for the executing cpu to handle them
+#[inline]
+pub(crate) fn service_pending_ipis() {
+    super::arch::interrupt::service_pending_ipis();
+}
@@ -1,7 +1,11 @@
 use core::{
+    fmt,
+    hash::Hash,
+    marker::PhantomData,
     mem::{size_of, transmute_copy},
     num::NonZeroU64,
-    ptr::{read_volatile, write_volatile},
+    ptr::{null_mut, read_volatile, write_volatile},
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering},
 };
 
 use bit_field::BitField;
@@ -16,14 +20,187 @@ pub enum MemoryAddressErrors {
     NULLPTR,
     Invalid,
     OutOfBounds,
+    UnsupportedPageLevel, //the platform has no pages of the level
+}
+
+//All physical memory is accessed through the HHDM, replacing its offset redirects every access
+static HHDM_OFFSET_OVERRIDDEN: AtomicBool = AtomicBool::new(false);
+static HHDM_OFFSET_OVERRIDE: AtomicU64 = AtomicU64::new(0);
+
+///Redirects every physical memory access and every phys/virt conversion of this module to >offset< + physical address \
+///Used by the unit tests to back physical memory with a simulated arena (see simulated_memory.rs) \
+///Safety: every physical address that is accessed afterwards has to be backed by memory at >offset< + address
+pub unsafe fn set_hhdm_offset_override(offset: u64) {
+    HHDM_OFFSET_OVERRIDE.store(offset, Ordering::Release);
+    HHDM_OFFSET_OVERRIDDEN.store(true, Ordering::Release);
+}
+
+///Returns to the HHDM of the bootloader
+pub unsafe fn clear_hhdm_offset_override() {
+    HHDM_OFFSET_OVERRIDDEN.store(false, Ordering::Release);
+}
+
+///True while physical memory is redirected by set_hhdm_offset_override \
+///Page tables in redirected memory can not be loaded by a cpu, so their changes need no TLB invalidation
+#[inline(always)]
+pub(in crate::hal) fn is_physical_memory_simulated() -> bool {
+    HHDM_OFFSET_OVERRIDDEN.load(Ordering::Acquire)
+}
+
+#[inline(always)]
+fn get_hhdm_offset() -> u64 {
+    if HHDM_OFFSET_OVERRIDDEN.load(Ordering::Acquire) {
+        return HHDM_OFFSET_OVERRIDE.load(Ordering::Acquire);
+    }
+
+    HHDM_OFFSET.address.get_u64()
 }
 
-//Set Bits are allowed to be used
-//CANNONICAL_BIT has to be the highes valid address bit
 lazy_static! {
-    static ref BIT_MASK_PHYS_ADDRESS: u64 = super::arch::memory::get_max_supported_phy_address_as_bit_mask();
-    static ref BIT_MASK_VIRT_ADDRESS: u64 = super::arch::memory::get_max_supported_virt_address_as_bit_mask();
-    static ref CANNONICAL_BIT: Option<u8> = super::arch::memory::get_cannonical_bit_number(); //Bitnumber that is used for sign extension
+    static ref PLATFORM_PARAMETERS: PlatformParameters =
+        get_platform_parameters_override().unwrap_or_else(PlatformParameters::from_arch);
+}
+
+///Address space constrains of the platform, the address types and page sizes are derived from them
+#[derive(Clone, Copy, Debug)]
+pub struct PlatformParameters {
+    pub phys_address_mask: u64,     //set bits can be used
+    pub virt_address_mask: u64,     //set bits can be used
+    pub cannonical_bit: Option<u8>, //bit that is used for sign extension, has to be the highest bit of virt_address_mask
+    pub lv1_page_mask: u64,
+    pub lv2_page_mask: u64, //0 if not supported
+    pub lv3_page_mask: u64, //0 if not supported
+}
+
+impl PlatformParameters {
+    ///Reads the parameters of the executing cpu
+    pub fn from_arch() -> PlatformParameters {
+        PlatformParameters {
+            phys_address_mask: super::arch::memory::get_max_supported_phy_address_as_bit_mask(),
+            virt_address_mask: super::arch::memory::get_max_supported_virt_address_as_bit_mask(),
+            cannonical_bit: super::arch::memory::get_cannonical_bit_number(),
+            lv1_page_mask: super::arch::memory::get_lv1_page_size_mask(),
+            lv2_page_mask: super::arch::memory::get_lv2_page_size_mask(),
+            lv3_page_mask: super::arch::memory::get_lv3_page_size_mask(),
+        }
+    }
+
+    ///Checks the constrains of a physical address
+    #[inline]
+    pub fn check_phys(&self, value: u64) -> Result<u64, MemoryAddressErrors> {
+        if value & !self.phys_address_mask != 0 {
+            return Err(MemoryAddressErrors::InvalidBits);
+        }
+
+        Ok(value)
+    }
+
+    ///masks of unsupported bits
+    #[inline]
+    pub fn maskoff_phys(&self, value: u64) -> u64 {
+        value & self.phys_address_mask
+    }
+
+    ///Checks the constrains of a virtual address, the bits above the cannonical bit have to be copies of it
+    #[inline]
+    pub fn check_virt(&self, value: u64) -> Result<NonZeroU64, MemoryAddressErrors> {
+        let masked_value: u64 = value & !self.virt_address_mask;
+
+        //Checks if a cannonical bit is used and set
+        //Note: Reodering the if branches doesnt decrease the amount of branches on average (currently 2-3-3)
+        if let Some(cannonical_bit_value) = self.cannonical_bit
+            && value.get_bit(cannonical_bit_value as usize)
+        {
+            if masked_value != !self.virt_address_mask {
+                return Err(MemoryAddressErrors::NonCannonical);
+            }
+        } else if masked_value != 0 {
+            if self.cannonical_bit.is_none() {
+                return Err(MemoryAddressErrors::InvalidBits);
+            }
+
+            return Err(MemoryAddressErrors::NonCannonical);
+        }
+
+        NonZeroU64::new(value).ok_or(MemoryAddressErrors::NULLPTR)
+    }
+
+    ///masks of unsupported bits, the bits above the cannonical bit are replaced by copies of it
+    #[inline]
+    pub fn maskoff_virt(&self, value: u64) -> Result<NonZeroU64, MemoryAddressErrors> {
+        if let Some(cannonical_bit_value) = self.cannonical_bit
+            && value.get_bit(cannonical_bit_value as usize)
+        {
+            return NonZeroU64::new((value & self.virt_address_mask) | !self.virt_address_mask)
+                .ok_or(MemoryAddressErrors::NULLPTR);
+        }
+
+        NonZeroU64::new(value & self.virt_address_mask).ok_or(MemoryAddressErrors::NULLPTR)
+    }
+
+    ///Virtual address of >phys< in a HHDM that starts at >hhdm_offset<
+    #[inline]
+    pub fn phys_to_virt(
+        &self,
+        phys: u64,
+        hhdm_offset: u64,
+    ) -> Result<NonZeroU64, MemoryAddressErrors> {
+        self.check_virt(
+            phys.checked_add(hhdm_offset)
+                .ok_or(MemoryAddressErrors::OutOfBounds)?,
+        )
+    }
+
+    ///Physical address behind >virt< in a HHDM that starts at >hhdm_offset<
+    #[inline]
+    pub fn virt_to_phys(&self, virt: u64, hhdm_offset: u64) -> Result<u64, MemoryAddressErrors> {
+        self.check_phys(
+            virt.checked_sub(hhdm_offset)
+                .ok_or(MemoryAddressErrors::Invalid)?,
+        )
+    }
+}
+
+///>address< moved by >count< elements of >element_size< bytes
+#[inline]
+fn offset_address(address: u64, element_size: u64, count: i64) -> Result<u64, MemoryAddressErrors> {
+    (element_size as i64)
+        .checked_mul(count)
+        .and_then(|offset| address.checked_add_signed(offset))
+        .ok_or(MemoryAddressErrors::OutOfBounds)
+}
+
+static PLATFORM_PARAMETERS_OVERRIDE: AtomicPtr<PlatformParameters> = AtomicPtr::new(null_mut());
+
+///Replaces the parameters read from the cpu, for example to run the address types off-target or to emulate 4 and 5 level paging \
+///Has to be called before the first address is created, the parameters are read only once
+pub fn set_platform_parameters(parameters: &'static PlatformParameters) {
+    if PLATFORM_PARAMETERS_OVERRIDE
+        .compare_exchange(
+            null_mut(),
+            parameters as *const _ as *mut _,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        )
+        .is_err()
+    {
+        panic!("MEMORY ERROR: PLATFORM PARAMETERS ALREADY SET");
+    }
+}
+
+fn get_platform_parameters_override() -> Option<PlatformParameters> {
+    unsafe {
+        PLATFORM_PARAMETERS_OVERRIDE
+            .load(Ordering::Acquire)
+            .as_ref()
+    }
+    .copied()
+}
+
+///Number of usable virtual address bits, the paging code derives the number of table levels from it
+#[inline]
+pub(in crate::hal) fn get_virt_address_bits() -> u32 {
+    PLATFORM_PARAMETERS.virt_address_mask.count_ones()
 }
 
 ///Ensures that plattform Constrains are fullfilled (for example max 52bit on x86_64)
@@ -37,24 +214,22 @@ pub struct PhysAddress {
 impl PhysAddress {
     #[inline]
     pub fn new(value: u64) -> Result<Self, MemoryAddressErrors> {
-        if value & (!*BIT_MASK_PHYS_ADDRESS) != 0 {
-            return Err(MemoryAddressErrors::InvalidBits);
-        }
-
-        Ok(Self { address: value })
+        Ok(Self {
+            address: PLATFORM_PARAMETERS.check_phys(value)?,
+        })
     }
 
     ///masks of unsupported bits
     #[inline]
     pub fn new_maskoff(value: u64) -> Self {
         Self {
-            address: value & *BIT_MASK_PHYS_ADDRESS,
+            address: PLATFORM_PARAMETERS.maskoff_phys(value),
         }
     }
 
     ///Caller has to ensure that >value< fullfills platform address space constrains
     #[inline]
-    pub unsafe fn new_unchecked(value: u64) -> Self {
+    pub const unsafe fn new_unchecked(value: u64) -> Self {
         Self { address: value }
     }
 
@@ -65,20 +240,20 @@ impl PhysAddress {
 
     #[inline]
     pub fn offset<T>(&self, count: i64) -> Result<Self, MemoryAddressErrors> {
-        Self::new(
-            self.get_u64()
-                .checked_add_signed(size_of::<T>() as i64 * count)
-                .ok_or(MemoryAddressErrors::OutOfBounds)?,
-        )
+        Self::new(offset_address(
+            self.get_u64(),
+            size_of::<T>() as u64,
+            count,
+        )?)
     }
 
     #[inline]
     pub fn offset_maskoff<T>(&self, count: i64) -> Result<Self, MemoryAddressErrors> {
-        Ok(Self::new_maskoff(
-            self.get_u64()
-                .checked_add_signed(size_of::<T>() as i64 * count)
-                .ok_or(MemoryAddressErrors::OutOfBounds)?,
-        ))
+        Ok(Self::new_maskoff(offset_address(
+            self.get_u64(),
+            size_of::<T>() as u64,
+            count,
+        )?))
     }
 
     ///Caller has to ensure that the new address fullfills platform address space constrains
@@ -92,22 +267,28 @@ impl PhysAddress {
 
     ///Converts the physical Address into a virtual Address via the HHDM
     #[inline]
-    pub fn to_virt(&self) -> Result<VirtAddress, MemoryAddressErrors> {
-        VirtAddress::new(self.get_u64() + (*HHDM_OFFSET).address.get_u64())
+    pub fn to_virt(self) -> Result<VirtAddress, MemoryAddressErrors> {
+        Ok(VirtAddress {
+            address: PLATFORM_PARAMETERS.phys_to_virt(self.get_u64(), get_hhdm_offset())?,
+        })
     }
 
     ///Converts the physical Address into a virtual Address via the HHDM \
     ///masks of unsupported bits
     #[inline]
-    pub fn to_virt_maskoff(&self) -> Result<VirtAddress, MemoryAddressErrors> {
-        VirtAddress::new_maskoff(self.get_u64() + (*HHDM_OFFSET).address.get_u64())
+    pub fn to_virt_maskoff(self) -> Result<VirtAddress, MemoryAddressErrors> {
+        VirtAddress::new_maskoff(
+            self.get_u64()
+                .checked_add(get_hhdm_offset())
+                .ok_or(MemoryAddressErrors::OutOfBounds)?,
+        )
     }
 
     ///Converts the physical Address into a virtual Address via the HHDM \
     ///Caller has to ensure that >value< fullfills platform address space constrains
     #[inline]
-    pub unsafe fn to_virt_unchecked(&self) -> VirtAddress {
-        VirtAddress::new_unchecked(self.get_u64() + (*HHDM_OFFSET).address.get_u64())
+    pub unsafe fn to_virt_unchecked(self) -> VirtAddress {
+        VirtAddress::new_unchecked(self.get_u64() + get_hhdm_offset())
     }
 
     ///Performs a volatile Read from physical Memory via the HHDM \
@@ -152,7 +333,8 @@ impl PhysAddress {
     where
         [(); size_of::<T>()]:,
     {
-        Ok(self.to_virt()?.write::<T>(value))
+        self.to_virt()?.write::<T>(value);
+        Ok(())
     }
 
     ///Performs a volatile Write to physical Memory via the HHDM \
@@ -163,7 +345,8 @@ impl PhysAddress {
     where
         [(); size_of::<T>()]:,
     {
-        Ok(self.to_virt_maskoff()?.write::<T>(value))
+        self.to_virt_maskoff()?.write::<T>(value);
+        Ok(())
     }
 
     ///Performs a volatile Write to physical Memory via the HHDM \
@@ -178,7 +361,7 @@ impl PhysAddress {
     }
 }
 
-////Wrapper around a u64 that ensures that plattform constrains for the address size are met
+///Wrapper around a u64 that ensures that plattform constrains for the address size are met
 #[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
 #[repr(transparent)]
 pub struct VirtAddress {
@@ -188,49 +371,16 @@ pub struct VirtAddress {
 impl VirtAddress {
     #[inline]
     pub fn new(value: u64) -> Result<Self, MemoryAddressErrors> {
-        let masked_value: u64 = value & (!*BIT_MASK_VIRT_ADDRESS);
-
-        //Checks if a cannonical bit is used and set
-        //Note: Reodering the if branches doesnt decrease the amount of branches on average (currently 2-3-3)
-        if let Some(cannonical_bit_value) = *CANNONICAL_BIT
-            && value.get_bit(cannonical_bit_value as usize)
-        {
-            if masked_value != !*BIT_MASK_VIRT_ADDRESS {
-                return Err(MemoryAddressErrors::NonCannonical);
-            }
-        } else {
-            if masked_value != 0 {
-                if (*CANNONICAL_BIT).is_none() {
-                    return Err(MemoryAddressErrors::InvalidBits);
-                }
-
-                return Err(MemoryAddressErrors::NonCannonical);
-            }
-        }
-
         Ok(Self {
-            address: NonZeroU64::new(value).ok_or(MemoryAddressErrors::NULLPTR)?,
+            address: PLATFORM_PARAMETERS.check_virt(value)?,
         })
     }
 
     ///masks of unsupported bits
     #[inline]
     pub fn new_maskoff(value: u64) -> Result<Self, MemoryAddressErrors> {
-        //Checks if a cannonical bit is used and set
-        if let Some(cannonical_bit_value) = *CANNONICAL_BIT
-            && (value as u64).get_bit(cannonical_bit_value as usize)
-        {
-            return Ok(Self {
-                address: NonZeroU64::new(
-                    (value & *BIT_MASK_VIRT_ADDRESS) | !*BIT_MASK_VIRT_ADDRESS,
-                )
-                .ok_or(MemoryAddressErrors::NULLPTR)?,
-            });
-        }
-
         Ok(Self {
-            address: NonZeroU64::new(value & *BIT_MASK_VIRT_ADDRESS)
-                .ok_or(MemoryAddressErrors::NULLPTR)?,
+            address: PLATFORM_PARAMETERS.maskoff_virt(value)?,
         })
     }
 
@@ -249,25 +399,25 @@ impl VirtAddress {
 
     #[inline]
     pub fn get_non_zero_u64(&self) -> NonZeroU64 {
-        self.address.into()
+        self.address
     }
 
     #[inline]
     pub fn offset<T>(&self, count: i64) -> Result<Self, MemoryAddressErrors> {
-        Self::new(
-            self.get_u64()
-                .checked_add_signed(size_of::<T>() as i64 * count)
-                .ok_or(MemoryAddressErrors::OutOfBounds)?,
-        )
+        Self::new(offset_address(
+            self.get_u64(),
+            size_of::<T>() as u64,
+            count,
+        )?)
     }
 
     #[inline]
     pub fn offset_maskoff<T>(&self, count: i64) -> Result<Self, MemoryAddressErrors> {
-        Self::new_maskoff(
-            self.get_u64()
-                .checked_add_signed(size_of::<T>() as i64 * count)
-                .ok_or(MemoryAddressErrors::OutOfBounds)?,
-        )
+        Self::new_maskoff(offset_address(
+            self.get_u64(),
+            size_of::<T>() as u64,
+            count,
+        )?)
     }
 
     ///Caller has to ensure that the new address fullfills platform address space constrains
@@ -281,30 +431,28 @@ impl VirtAddress {
 
     ///Converts the virtual Address into a physical Address via the HHDM
     #[inline]
-    pub fn to_phys(&self) -> Result<PhysAddress, MemoryAddressErrors> {
-        if self.get_u64() < (*HHDM_OFFSET).address.get_u64() {
-            return Err(MemoryAddressErrors::Invalid);
-        }
-
-        PhysAddress::new(self.get_u64() - (*HHDM_OFFSET).address.get_u64())
+    pub fn to_phys(self) -> Result<PhysAddress, MemoryAddressErrors> {
+        Ok(PhysAddress {
+            address: PLATFORM_PARAMETERS.virt_to_phys(self.get_u64(), get_hhdm_offset())?,
+        })
     }
 
     ///Converts the virtual Address into a physical Address via the HHDM \
     ///masks of unsupported bits
     #[inline]
-    pub fn to_phys_maskoff(&self) -> PhysAddress {
-        if self.get_u64() < (*HHDM_OFFSET).address.get_u64() {
+    pub fn to_phys_maskoff(self) -> PhysAddress {
+        if self.get_u64() < get_hhdm_offset() {
             return PhysAddress::new_maskoff(0);
         }
 
-        PhysAddress::new_maskoff(self.get_u64() - (*HHDM_OFFSET).address.get_u64())
+        PhysAddress::new_maskoff(self.get_u64() - get_hhdm_offset())
     }
 
     ///Converts the virtual Address into a physical Address via the HHDM \
     ///Caller has to ensure that >value< fullfills platform address space constrains
     #[inline]
-    pub unsafe fn to_phys_unchecked(&self) -> PhysAddress {
-        PhysAddress::new_unchecked(self.get_u64() - (*HHDM_OFFSET).address.get_u64())
+    pub unsafe fn to_phys_unchecked(self) -> PhysAddress {
+        PhysAddress::new_unchecked(self.get_u64() - get_hhdm_offset())
     }
 
     ///Performs a volatile Read to virtual Memory \
@@ -340,9 +488,9 @@ impl VirtAddress {
 //A value of 0 indicates no support for a given pagesize
 //if LV2 is not supported LV3 is also not supported
 lazy_static! {
-    pub static ref LV1_PAGE_MASK: u64 = super::arch::memory::get_lv1_page_size_mask();
-    pub static ref LV2_PAGE_MASK: u64 = super::arch::memory::get_lv2_page_size_mask();
-    pub static ref LV3_PAGE_MASK: u64 = super::arch::memory::get_lv3_page_size_mask();
+    pub static ref LV1_PAGE_MASK: u64 = PLATFORM_PARAMETERS.lv1_page_mask;
+    pub static ref LV2_PAGE_MASK: u64 = PLATFORM_PARAMETERS.lv2_page_mask;
+    pub static ref LV3_PAGE_MASK: u64 = PLATFORM_PARAMETERS.lv3_page_mask;
     pub static ref LV1_PAGE_SIZE: u64 = (!(*LV1_PAGE_MASK)) + 1;
     pub static ref LV2_PAGE_SIZE: u64 = (!(*LV2_PAGE_MASK)).wrapping_add(1); //If Pagesize isnt supported the u64 would overflow to 0, this prevent a panic
     pub static ref LV3_PAGE_SIZE: u64 = (!(*LV3_PAGE_MASK)).wrapping_add(1); //If Pagesize isnt supported the u64 would overflow to 0, this prevent a panic
@@ -350,98 +498,311 @@ lazy_static! {
     pub static ref LV3_PAGE_SUPPORTED: bool = (*LV3_PAGE_MASK > 0) && *LV2_PAGE_SUPPORTED;
 }
 
-//Wrapper that ensures that a address is always aligned to the base page size and a valid address
+//Page level addresses are a PhysAddress/VirtAddress that is aligned to the page size of the level
+//Everything that does not depend on the address space is implemented once for both
+
+///Physical or virtual address space, used as marker for PageAddress
+pub trait AddressSpace: Clone + Copy + Hash + Eq + Ord {
+    type Address: Clone + Copy + Hash + Eq + Ord;
+    const NAME: &'static str;
+
+    fn get_u64(address: Self::Address) -> u64;
+    fn new_address(value: u64) -> Result<Self::Address, MemoryAddressErrors>;
+    ///Caller has to ensure that >value< fullfills platform address space constrains
+    unsafe fn new_address_unchecked(value: u64) -> Self::Address;
+}
+
+///Page level, used as marker for PageAddress
+pub trait PageLevel: Clone + Copy + Hash + Eq + Ord {
+    const LEVEL: u8;
+
+    fn get_mask() -> u64;
+    fn get_size() -> u64;
+    fn is_supported() -> bool;
+}
+
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Phys;
+
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Virt;
+
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Lv1;
+
 #[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Lv2;
+
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Lv3;
+
+impl AddressSpace for Phys {
+    type Address = PhysAddress;
+    const NAME: &'static str = "Phys";
+
+    #[inline(always)]
+    fn get_u64(address: PhysAddress) -> u64 {
+        address.get_u64()
+    }
+
+    #[inline(always)]
+    fn new_address(value: u64) -> Result<PhysAddress, MemoryAddressErrors> {
+        PhysAddress::new(value)
+    }
+
+    #[inline(always)]
+    unsafe fn new_address_unchecked(value: u64) -> PhysAddress {
+        PhysAddress::new_unchecked(value)
+    }
+}
+
+impl AddressSpace for Virt {
+    type Address = VirtAddress;
+    const NAME: &'static str = "Virt";
+
+    #[inline(always)]
+    fn get_u64(address: VirtAddress) -> u64 {
+        address.get_u64()
+    }
+
+    #[inline(always)]
+    fn new_address(value: u64) -> Result<VirtAddress, MemoryAddressErrors> {
+        VirtAddress::new(value)
+    }
+
+    #[inline(always)]
+    unsafe fn new_address_unchecked(value: u64) -> VirtAddress {
+        VirtAddress::new_unchecked(value)
+    }
+}
+
+impl PageLevel for Lv1 {
+    const LEVEL: u8 = 1;
+
+    #[inline(always)]
+    fn get_mask() -> u64 {
+        *LV1_PAGE_MASK
+    }
+
+    #[inline(always)]
+    fn get_size() -> u64 {
+        *LV1_PAGE_SIZE
+    }
+
+    #[inline(always)]
+    fn is_supported() -> bool {
+        true
+    }
+}
+
+impl PageLevel for Lv2 {
+    const LEVEL: u8 = 2;
+
+    #[inline(always)]
+    fn get_mask() -> u64 {
+        *LV2_PAGE_MASK
+    }
+
+    #[inline(always)]
+    fn get_size() -> u64 {
+        *LV2_PAGE_SIZE
+    }
+
+    #[inline(always)]
+    fn is_supported() -> bool {
+        *LV2_PAGE_SUPPORTED
+    }
+}
+
+impl PageLevel for Lv3 {
+    const LEVEL: u8 = 3;
+
+    #[inline(always)]
+    fn get_mask() -> u64 {
+        *LV3_PAGE_MASK
+    }
+
+    #[inline(always)]
+    fn get_size() -> u64 {
+        *LV3_PAGE_SIZE
+    }
+
+    #[inline(always)]
+    fn is_supported() -> bool {
+        *LV3_PAGE_SUPPORTED
+    }
+}
+
+///Wrapper that ensures that a address is always aligned to the page size of >L< and a valid address of >S<
+#[derive(Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd)]
 #[repr(transparent)]
-pub struct PhysLv1PageAddress {
-    address: PhysAddress,
+pub struct PageAddress<S: AddressSpace, L: PageLevel> {
+    address: S::Address,
+    level: PhantomData<L>,
+}
+
+pub type PhysLv1PageAddress = PageAddress<Phys, Lv1>;
+pub type PhysLv2PageAddress = PageAddress<Phys, Lv2>;
+pub type PhysLv3PageAddress = PageAddress<Phys, Lv3>;
+pub type VirtLv1PageAddress = PageAddress<Virt, Lv1>;
+pub type VirtLv2PageAddress = PageAddress<Virt, Lv2>;
+pub type VirtLv3PageAddress = PageAddress<Virt, Lv3>;
+
+impl<S: AddressSpace, L: PageLevel> fmt::Debug for PageAddress<S, L> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}Lv{}PageAddress({:#x})",
+            S::NAME,
+            L::LEVEL,
+            S::get_u64(self.address)
+        )
+    }
 }
 
-impl PhysLv1PageAddress {
+impl<S: AddressSpace, L: PageLevel> PageAddress<S, L> {
     #[inline]
     pub fn new(value: u64) -> Result<Self, MemoryAddressErrors> {
-        if value & (!*LV1_PAGE_MASK) != 0 {
+        if !L::is_supported() {
+            return Err(MemoryAddressErrors::UnsupportedPageLevel);
+        }
+
+        if value & (!L::get_mask()) != 0 {
             return Err(MemoryAddressErrors::InvalidBits);
         }
 
         Ok(Self {
-            address: PhysAddress::new(value)?,
+            address: S::new_address(value)?,
+            level: PhantomData,
         })
     }
 
-    //masks of unsupported bits
-    #[inline]
-    pub fn new_maskoff(value: u64) -> Self {
-        Self {
-            address: PhysAddress::new_maskoff(value & *LV1_PAGE_MASK),
-        }
-    }
-
     ///Caller has to ensure that >value< fullfills platform address space constrains
     #[inline]
     pub unsafe fn new_unchecked(value: u64) -> Self {
         Self {
-            address: PhysAddress::new_unchecked(value),
+            address: S::new_address_unchecked(value),
+            level: PhantomData,
         }
     }
 
     #[inline]
-    pub fn get_address(&self) -> PhysAddress {
+    pub fn get_address(&self) -> S::Address {
         self.address
     }
 
     #[inline]
     pub fn offset(&self, count: i64) -> Result<Self, MemoryAddressErrors> {
-        Self::new(
-            self.get_address()
-                .get_u64()
-                .checked_add_signed(*LV1_PAGE_SIZE as i64 * count)
-                .ok_or(MemoryAddressErrors::OutOfBounds)?,
-        )
-    }
-
-    #[inline]
-    pub fn offset_maskoff(&self, count: i64) -> Result<Self, MemoryAddressErrors> {
-        Ok(Self::new_maskoff(
-            self.get_address()
-                .get_u64()
-                .checked_add_signed(*LV1_PAGE_SIZE as i64 * count)
-                .ok_or(MemoryAddressErrors::OutOfBounds)?,
-        ))
+        Self::new(offset_address(
+            S::get_u64(self.address),
+            L::get_size(),
+            count,
+        )?)
     }
 
     ///Caller has to ensure that the new address fullfills platform address space constrains
     #[inline]
     pub unsafe fn offset_unchecked(&self, count: i64) -> Self {
         Self::new_unchecked(
-            self.get_address()
-                .get_u64()
-                .wrapping_add_signed(*LV1_PAGE_SIZE as i64 * count),
+            S::get_u64(self.address).wrapping_add_signed(L::get_size() as i64 * count),
         )
     }
 
+    ///The page of level >T< that contains this page, lossless if >T< is not above >L< \
+    ///Fails if the platform has no pages of level >T<
+    #[inline]
+    pub fn align_down<T: PageLevel>(&self) -> Result<PageAddress<S, T>, MemoryAddressErrors> {
+        if !T::is_supported() {
+            return Err(MemoryAddressErrors::UnsupportedPageLevel);
+        }
+
+        PageAddress::<S, T>::new(S::get_u64(self.address) & T::get_mask())
+    }
+
+    ///The first page of level >T< that starts at or after this page \
+    ///Fails if the platform has no pages of level >T<
+    #[inline]
+    pub fn align_up<T: PageLevel>(&self) -> Result<PageAddress<S, T>, MemoryAddressErrors> {
+        //the size of an unsupported level is 0
+        if !T::is_supported() {
+            return Err(MemoryAddressErrors::UnsupportedPageLevel);
+        }
+
+        let value = S::get_u64(self.address)
+            .checked_add(T::get_size() - 1)
+            .ok_or(MemoryAddressErrors::OutOfBounds)?;
+
+        PageAddress::<S, T>::new(value & T::get_mask())
+    }
+
+    ///The lv2 page that contains this page
+    #[inline]
+    pub fn containing_lv2(&self) -> Result<PageAddress<S, Lv2>, MemoryAddressErrors> {
+        self.align_down::<Lv2>()
+    }
+
+    ///The lv1 page at the start of this page, never fails as every page is lv1 aligned
+    #[inline]
+    pub fn to_lv1(self) -> PageAddress<S, Lv1> {
+        PageAddress {
+            address: self.address,
+            level: PhantomData,
+        }
+    }
+
+    ///Iterates over the lv1 pages that make up this page
+    #[inline]
+    pub fn iter_lv1_pages(&self) -> impl Iterator<Item = PageAddress<S, Lv1>> {
+        let first = self.to_lv1();
+
+        (0..L::get_size() / Lv1::get_size())
+            .map(move |index| unsafe { first.offset_unchecked(index as i64) })
+    }
+}
+
+impl<L: PageLevel> PageAddress<Phys, L> {
+    ///masks of unsupported bits
+    #[inline]
+    pub fn new_maskoff(value: u64) -> Self {
+        Self {
+            address: PhysAddress::new_maskoff(value & L::get_mask()),
+            level: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn offset_maskoff(&self, count: i64) -> Result<Self, MemoryAddressErrors> {
+        Ok(Self::new_maskoff(offset_address(
+            self.address.get_u64(),
+            L::get_size(),
+            count,
+        )?))
+    }
+
     ///Converts the physical Address into a virtual Address via the HHDM
     #[inline]
-    pub fn to_virt(&self) -> Result<VirtLv1PageAddress, MemoryAddressErrors> {
-        VirtLv1PageAddress::new(self.get_address().get_u64() + (*HHDM_OFFSET).address.get_u64())
+    pub fn to_virt(self) -> Result<PageAddress<Virt, L>, MemoryAddressErrors> {
+        let virt = PLATFORM_PARAMETERS.phys_to_virt(self.address.get_u64(), get_hhdm_offset())?;
+        PageAddress::<Virt, L>::new(virt.get())
     }
 
     ///Converts the physical Address into a virtual Address via the HHDM \
     ///masks of unsupported bits
     #[inline]
-    pub fn to_virt_maskoff(&self) -> Result<VirtLv1PageAddress, MemoryAddressErrors> {
-        VirtLv1PageAddress::new_maskoff(
-            self.get_address().get_u64() + (*HHDM_OFFSET).address.get_u64(),
+    pub fn to_virt_maskoff(self) -> Result<PageAddress<Virt, L>, MemoryAddressErrors> {
+        PageAddress::<Virt, L>::new_maskoff(
+            self.address
+                .get_u64()
+                .checked_add(get_hhdm_offset())
+                .ok_or(MemoryAddressErrors::OutOfBounds)?,
         )
     }
 
     ///Converts the physical Address into a virtual Address via the HHDM \
     ///Caller has to ensure that >value< fullfills platform address space constrains
     #[inline]
-    pub unsafe fn to_virt_unchecked(&self) -> VirtLv1PageAddress {
-        VirtLv1PageAddress::new_unchecked(
-            self.get_address().get_u64() + (*HHDM_OFFSET).address.get_u64(),
-        )
+    pub unsafe fn to_virt_unchecked(self) -> PageAddress<Virt, L> {
+        PageAddress::<Virt, L>::new_unchecked(self.address.get_u64() + get_hhdm_offset())
     }
 
     ///Performs a volatile Read to physical Memory via the HHDM \
@@ -486,7 +847,8 @@ impl PhysLv1PageAddress {
     where
         [(); size_of::<T>()]:,
     {
-        Ok(self.to_virt()?.write::<T>(value))
+        self.to_virt()?.write::<T>(value);
+        Ok(())
     }
 
     ///Performs a volatile Write to physical Memory via the HHDM \
@@ -497,7 +859,8 @@ impl PhysLv1PageAddress {
     where
         [(); size_of::<T>()]:,
     {
-        Ok(self.to_virt_maskoff()?.write::<T>(value))
+        self.to_virt_maskoff()?.write::<T>(value);
+        Ok(())
     }
 
     ///Performs a volatile Write to physical Memory via the HHDM \
@@ -512,745 +875,1043 @@ impl PhysLv1PageAddress {
     }
 }
 
-//Wrapper that ensures that a address is always aligned to the page size one level above the base page size
-#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
-#[repr(transparent)]
-pub struct PhysLv2PageAddress {
-    address: PhysAddress,
-}
-
-impl PhysLv2PageAddress {
+impl<L: PageLevel> PageAddress<Virt, L> {
+    ///masks of unsupported bits
     #[inline]
-    pub fn new(value: u64) -> Result<Self, MemoryAddressErrors> {
-        if value & (!*LV2_PAGE_MASK) != 0 {
-            return Err(MemoryAddressErrors::InvalidBits);
-        }
-
+    pub fn new_maskoff(value: u64) -> Result<Self, MemoryAddressErrors> {
         Ok(Self {
-            address: PhysAddress::new(value)?,
+            address: VirtAddress::new_maskoff(value & L::get_mask())?,
+            level: PhantomData,
         })
     }
 
-    ///masks of unsupported bits
-    #[inline]
-    pub fn new_maskoff(value: u64) -> Self {
-        Self {
-            address: PhysAddress::new_maskoff(value & *LV2_PAGE_MASK),
-        }
-    }
-
-    ///Caller has to ensure that >value< fullfills platform address space constrains
-    #[inline]
-    pub unsafe fn new_unchecked(value: u64) -> Self {
-        Self {
-            address: PhysAddress::new_unchecked(value),
-        }
-    }
-
-    #[inline]
-    pub fn get_address(&self) -> PhysAddress {
-        self.address
-    }
-
-    #[inline]
-    pub fn offset(&self, count: i64) -> Result<Self, MemoryAddressErrors> {
-        Self::new(
-            self.get_address()
-                .get_u64()
-                .checked_add_signed(*LV2_PAGE_SIZE as i64 * count)
-                .ok_or(MemoryAddressErrors::OutOfBounds)?,
-        )
-    }
-
     #[inline]
     pub fn offset_maskoff(&self, count: i64) -> Result<Self, MemoryAddressErrors> {
-        Ok(Self::new_maskoff(
-            self.get_address()
-                .get_u64()
-                .checked_add_signed(*LV2_PAGE_SIZE as i64 * count)
-                .ok_or(MemoryAddressErrors::OutOfBounds)?,
-        ))
+        Self::new_maskoff(offset_address(
+            self.address.get_u64(),
+            L::get_size(),
+            count,
+        )?)
     }
 
-    ///Caller has to ensure that the new address fullfills platform address space constrains
-    #[inline]
-    pub unsafe fn offset_unchecked(&self, count: i64) -> Self {
-        Self::new_unchecked(
-            self.get_address()
-                .get_u64()
-                .wrapping_add_signed(*LV2_PAGE_SIZE as i64 * count),
-        )
-    }
-
-    ///Converts the physical Address into a virtual Address via the HHDM
+    ///Converts the virtual Address into a physical Address via the HHDM
     #[inline]
-    pub fn to_virt(&self) -> Result<VirtLv2PageAddress, MemoryAddressErrors> {
-        VirtLv2PageAddress::new(self.get_address().get_u64() + (*HHDM_OFFSET).address.get_u64())
+    pub fn to_phys(self) -> Result<PageAddress<Phys, L>, MemoryAddressErrors> {
+        let phys = PLATFORM_PARAMETERS.virt_to_phys(self.address.get_u64(), get_hhdm_offset())?;
+        PageAddress::<Phys, L>::new(phys)
     }
 
-    ///Converts the physical Address into a virtual Address via the HHDM \
+    ///Converts the virtual Address into a physical Address via the HHDM \
     ///masks of unsupported bits
     #[inline]
-    pub fn to_virt_maskoff(&self) -> Result<VirtLv2PageAddress, MemoryAddressErrors> {
-        VirtLv2PageAddress::new_maskoff(
-            self.get_address().get_u64() + (*HHDM_OFFSET).address.get_u64(),
-        )
+    pub fn to_phys_maskoff(self) -> PageAddress<Phys, L> {
+        if self.address.get_u64() < get_hhdm_offset() {
+            return PageAddress::<Phys, L>::new_maskoff(0);
+        }
+
+        PageAddress::<Phys, L>::new_maskoff(self.address.get_u64() - get_hhdm_offset())
     }
 
-    ///Converts the physical Address into a virtual Address via the HHDM \
+    ///Converts the virtual Address into a physical Address via the HHDM \
     ///Caller has to ensure that >value< fullfills platform address space constrains
     #[inline]
-    pub unsafe fn to_virt_unchecked(&self) -> VirtLv2PageAddress {
-        VirtLv2PageAddress::new_unchecked(
-            self.get_address().get_u64() + (*HHDM_OFFSET).address.get_u64(),
-        )
+    pub unsafe fn to_phys_unchecked(self) -> PageAddress<Phys, L> {
+        PageAddress::<Phys, L>::new_unchecked(self.address.get_u64() - get_hhdm_offset())
     }
 
-    ///Performs a volatile Read to physical Memory via the HHDM \
+    ///Performs a volatile Read from virtual Memory \
     ///The caller has to ensure that the target page is mapped with the needed permissions \
     ///The caller has to properly handle invalid instances of T returned by this function
     #[inline(always)]
-    pub unsafe fn read<T>(&self) -> Result<T, MemoryAddressErrors>
+    pub unsafe fn read<T>(&self) -> T
     where
         [(); size_of::<T>()]:,
     {
-        Ok(self.to_virt()?.read::<T>())
+        self.address.read::<T>()
     }
 
-    ///Performs a volatile Read to physical Memory via the HHDM \
-    ///masks of unsupported bits \
-    ///The caller has to ensure that the target page is mapped with the needed permissions \
-    ///The caller has to properly handle invalid instances of T returned by this function \
+    ///Performs a volatile Write to virtual Memory \
+    ///The caller has to ensure that the target page is mapped with the needed permissions
     #[inline(always)]
-    pub unsafe fn read_maskoff<T>(&self) -> Result<T, MemoryAddressErrors>
+    pub unsafe fn write<T>(&self, value: &T)
     where
         [(); size_of::<T>()]:,
     {
-        Ok(self.to_virt_maskoff()?.read::<T>())
+        self.address.write::<T>(value)
     }
+}
 
-    ///Performs a volatile Read to physical Memory via the HHDM \
-    ///Caller has to ensure that >value< fullfills platform address space constrains \
-    ///The caller has to ensure that the target page is mapped with the needed permissions \
-    ///The caller has to properly handle invalid instances of T returned by this function
-    #[inline(always)]
-    pub unsafe fn read_unchecked<T>(&self) -> T
-    where
-        [(); size_of::<T>()]:,
-    {
-        self.to_virt_unchecked().read::<T>()
-    }
+//Ranges are stored as start and size so that a range can end at the top of the address space
+//Every address of a non empty range is a valid address of the address space, so its pages can be created unchecked
 
-    ///Performs a volatile Write to physical Memory via the HHDM \
-    ///The caller has to ensure that the target page is mapped with the needed permissions
-    #[inline(always)]
-    pub unsafe fn write<T>(&self, value: &T) -> Result<(), MemoryAddressErrors>
-    where
-        [(); size_of::<T>()]:,
-    {
-        Ok(self.to_virt()?.write::<T>(value))
-    }
+///Contiguous range of addresses of >S<, the start is inclusive and the end exclusive
+#[derive(Clone, Copy, Hash, Eq, PartialEq)]
+pub struct AddressRange<S: AddressSpace> {
+    start: u64,
+    size: u64,
+    space: PhantomData<S>,
+}
 
-    ///Performs a volatile Write to physical Memory via the HHDM \
-    ///masks of unsupported bits \
-    ///The caller has to ensure that the target page is mapped with the needed permissions
-    #[inline(always)]
-    pub unsafe fn write_maskoff<T>(&self, value: &T) -> Result<(), MemoryAddressErrors>
-    where
-        [(); size_of::<T>()]:,
-    {
-        Ok(self.to_virt_maskoff()?.write::<T>(value))
+pub type PhysRange = AddressRange<Phys>;
+pub type VirtRange = AddressRange<Virt>;
+
+///Page of a range split into the largest aligned pages, see AddressRange::split_into_pages
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub enum RangePage<S: AddressSpace> {
+    Lv1(PageAddress<S, Lv1>),
+    Lv2(PageAddress<S, Lv2>),
+    Lv3(PageAddress<S, Lv3>),
+}
+
+impl<S: AddressSpace> RangePage<S> {
+    #[inline]
+    pub fn get_start(&self) -> u64 {
+        match self {
+            RangePage::Lv1(page) => S::get_u64(page.get_address()),
+            RangePage::Lv2(page) => S::get_u64(page.get_address()),
+            RangePage::Lv3(page) => S::get_u64(page.get_address()),
+        }
     }
 
-    ///Performs a volatile Write to physical Memory via the HHDM \
-    ///Caller has to ensure that >value< fullfills platform address space constrains \
-    ///The caller has to ensure that the target page is mapped with the needed permissions
-    #[inline(always)]
-    pub unsafe fn write_unchecked<T>(&self, value: &T)
-    where
-        [(); size_of::<T>()]:,
-    {
-        self.to_virt_unchecked().write::<T>(value)
+    #[inline]
+    pub fn get_size(&self) -> u64 {
+        match self {
+            RangePage::Lv1(_) => Lv1::get_size(),
+            RangePage::Lv2(_) => Lv2::get_size(),
+            RangePage::Lv3(_) => Lv3::get_size(),
+        }
     }
 }
 
-//Wrapper that ensures that a address is always aligned to the page size two level above the base page size
-#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
-#[repr(transparent)]
-pub struct PhysLv3PageAddress {
-    address: PhysAddress,
+impl<S: AddressSpace> fmt::Debug for AddressRange<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}Range({:#x}, {:#x} bytes)",
+            S::NAME,
+            self.start,
+            self.size
+        )
+    }
 }
 
-impl PhysLv3PageAddress {
+impl<S: AddressSpace> AddressRange<S> {
+    ///Range from >start< up to but excluding >end<
     #[inline]
-    pub fn new(value: u64) -> Result<Self, MemoryAddressErrors> {
-        if value & (!*LV3_PAGE_MASK) != 0 {
-            return Err(MemoryAddressErrors::InvalidBits);
+    pub fn new(start: u64, end: u64) -> Result<Self, MemoryAddressErrors> {
+        if end < start {
+            return Err(MemoryAddressErrors::Invalid);
         }
 
-        Ok(Self {
-            address: PhysAddress::new(value)?,
-        })
+        Self::with_size(start, end - start)
     }
 
-    //masks of unsupported bits
+    ///Both ends have to be valid addresses of >S< and on the same side of the cannonical hole
     #[inline]
-    pub fn new_maskoff(value: u64) -> Self {
-        Self {
-            address: PhysAddress::new_maskoff(value & *LV3_PAGE_MASK),
+    pub fn with_size(start: u64, size: u64) -> Result<Self, MemoryAddressErrors> {
+        if size != 0 {
+            let last = start
+                .checked_add(size - 1)
+                .ok_or(MemoryAddressErrors::OutOfBounds)?;
+
+            S::new_address(start)?;
+            S::new_address(last)?;
+
+            if (start as i64).is_negative() != (last as i64).is_negative() {
+                return Err(MemoryAddressErrors::NonCannonical);
+            }
         }
+
+        Ok(Self {
+            start,
+            size,
+            space: PhantomData,
+        })
     }
 
-    ///Caller has to ensure that >value< fullfills platform address space constrains
+    ///Caller has to ensure that every address of the range fullfills platform address space constrains
     #[inline]
-    pub unsafe fn new_unchecked(value: u64) -> Self {
+    pub const unsafe fn with_size_unchecked(start: u64, size: u64) -> Self {
         Self {
-            address: PhysAddress::new_unchecked(value),
+            start,
+            size,
+            space: PhantomData,
         }
     }
 
+    ///Range that is covered by >number_of_pages< pages of level >L< starting at >first<
     #[inline]
-    pub fn get_address(&self) -> PhysAddress {
-        self.address
-    }
-
-    #[inline]
-    pub fn offset(&self, count: i64) -> Result<Self, MemoryAddressErrors> {
-        Self::new(
-            self.get_address()
-                .get_u64()
-                .checked_add_signed(*LV3_PAGE_SIZE as i64 * count)
+    pub fn from_pages<L: PageLevel>(
+        first: PageAddress<S, L>,
+        number_of_pages: u64,
+    ) -> Result<Self, MemoryAddressErrors> {
+        Self::with_size(
+            S::get_u64(first.get_address()),
+            number_of_pages
+                .checked_mul(L::get_size())
                 .ok_or(MemoryAddressErrors::OutOfBounds)?,
         )
     }
 
     #[inline]
-    pub fn offset_maskoff(&self, count: i64) -> Result<Self, MemoryAddressErrors> {
-        Ok(Self::new_maskoff(
-            self.get_address()
-                .get_u64()
-                .checked_add_signed(*LV3_PAGE_SIZE as i64 * count)
-                .ok_or(MemoryAddressErrors::OutOfBounds)?,
-        ))
+    pub const fn empty() -> Self {
+        Self {
+            start: 0,
+            size: 0,
+            space: PhantomData,
+        }
     }
 
-    ///Caller has to ensure that the new address fullfills platform address space constrains
     #[inline]
-    pub unsafe fn offset_unchecked(&self, count: i64) -> Self {
-        Self::new_unchecked(
-            self.get_address()
-                .get_u64()
-                .wrapping_add_signed(*LV3_PAGE_SIZE as i64 * count),
-        )
+    pub fn get_start(&self) -> u64 {
+        self.start
     }
 
-    ///Converts the physical Address into a virtual Address via the HHDM
+    ///Returns the first address after the range, 0 if the range ends at the top of the address space
     #[inline]
-    pub fn to_virt(&self) -> Result<VirtLv3PageAddress, MemoryAddressErrors> {
-        VirtLv3PageAddress::new(self.get_address().get_u64() + (*HHDM_OFFSET).address.get_u64())
+    pub fn get_end(&self) -> u64 {
+        self.start.wrapping_add(self.size)
     }
 
-    ///Converts the physical Address into a virtual Address via the HHDM \
-    ///masks of unsupported bits
+    ///Returns the last address of the range, None if the range is empty
     #[inline]
-    pub fn to_virt_maskoff(&self) -> Result<VirtLv3PageAddress, MemoryAddressErrors> {
-        VirtLv3PageAddress::new_maskoff(
-            self.get_address().get_u64() + (*HHDM_OFFSET).address.get_u64(),
-        )
+    pub fn get_last(&self) -> Option<u64> {
+        (!self.is_empty()).then(|| self.start + (self.size - 1))
     }
 
-    ///Converts the physical Address into a virtual Address via the HHDM \
-    ///Caller has to ensure that >value< fullfills platform address space constrains
     #[inline]
-    pub unsafe fn to_virt_unchecked(&self) -> VirtLv3PageAddress {
-        VirtLv3PageAddress::new_unchecked(
-            self.get_address().get_u64() + (*HHDM_OFFSET).address.get_u64(),
-        )
+    pub fn get_size(&self) -> u64 {
+        self.size
     }
 
-    ///Performs a volatile Read to physical Memory via the HHDM \
-    ///The caller has to ensure that the target page is mapped with the needed permissions \
-    ///The caller has to properly handle invalid instances of T returned by this function
-    #[inline(always)]
-    pub unsafe fn read<T>(&self) -> Result<T, MemoryAddressErrors>
-    where
-        [(); size_of::<T>()]:,
-    {
-        Ok(self.to_virt()?.read::<T>())
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
     }
 
-    ///Performs a volatile Read to physical Memory via the HHDM \
-    ///masks of unsupported bits \
-    ///The caller has to ensure that the target page is mapped with the needed permissions \
-    ///The caller has to properly handle invalid instances of T returned by this function \
-    #[inline(always)]
-    pub unsafe fn read_maskoff<T>(&self) -> Result<T, MemoryAddressErrors>
-    where
-        [(); size_of::<T>()]:,
-    {
-        Ok(self.to_virt_maskoff()?.read::<T>())
+    #[inline]
+    pub fn contains_address(&self, address: u64) -> bool {
+        address.wrapping_sub(self.start) < self.size
     }
 
-    ///Performs a volatile Read to physical Memory via the HHDM \
-    ///Caller has to ensure that >value< fullfills platform address space constrains \
-    ///The caller has to ensure that the target page is mapped with the needed permissions \
-    ///The caller has to properly handle invalid instances of T returned by this function
-    #[inline(always)]
-    pub unsafe fn read_unchecked<T>(&self) -> T
-    where
-        [(); size_of::<T>()]:,
-    {
-        self.to_virt_unchecked().read::<T>()
+    ///An empty range is contained in every range
+    #[inline]
+    pub fn contains(&self, other: &Self) -> bool {
+        match other.get_last() {
+            Some(last) => self.contains_address(other.start) && self.contains_address(last),
+            None => true,
+        }
     }
 
-    ///Performs a volatile Write to physical Memory via the HHDM \
-    ///The caller has to ensure that the target page is mapped with the needed permissions
-    #[inline(always)]
-    pub unsafe fn write<T>(&self, value: &T) -> Result<(), MemoryAddressErrors>
-    where
-        [(); size_of::<T>()]:,
-    {
-        Ok(self.to_virt()?.write::<T>(value))
+    #[inline]
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.intersection(other).is_some()
     }
 
-    ///Performs a volatile Write to physical Memory via the HHDM \
-    ///masks of unsupported bits \
-    ///The caller has to ensure that the target page is mapped with the needed permissions
-    #[inline(always)]
-    pub unsafe fn write_maskoff<T>(&self, value: &T) -> Result<(), MemoryAddressErrors>
-    where
-        [(); size_of::<T>()]:,
-    {
-        Ok(self.to_virt_maskoff()?.write::<T>(value))
-    }
+    ///Returns the addresses that are part of both ranges, None if there are none
+    #[inline]
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let start = self.start.max(other.start);
+        let last = self.get_last()?.min(other.get_last()?);
 
-    ///Performs a volatile Write to physical Memory via the HHDM \
-    ///Caller has to ensure that >value< fullfills platform address space constrains \
-    ///The caller has to ensure that the target page is mapped with the needed permissions
-    #[inline(always)]
-    pub unsafe fn write_unchecked<T>(&self, value: &T)
-    where
-        [(); size_of::<T>()]:,
-    {
-        self.to_virt_unchecked().write::<T>(value)
+        (start <= last).then(|| unsafe { Self::with_size_unchecked(start, last - start + 1) })
     }
-}
 
-//Wrapper that ensures that a address is always aligned to the base page size
-#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
-#[repr(transparent)]
-pub struct VirtLv1PageAddress {
-    address: VirtAddress,
-}
-
-impl VirtLv1PageAddress {
+    ///Returns the range that covers both ranges, None if there is a gap between them
     #[inline]
-    pub fn new(value: u64) -> Result<Self, MemoryAddressErrors> {
-        if value & (!*LV1_PAGE_MASK) != 0 {
-            return Err(MemoryAddressErrors::InvalidBits);
+    pub fn join(&self, other: &Self) -> Option<Self> {
+        let (Some(last), Some(other_last)) = (self.get_last(), other.get_last()) else {
+            return Some(if self.is_empty() { *other } else { *self });
+        };
+
+        //the ranges touch if one starts at most one address after the other ends
+        if other.start > last.saturating_add(1) || self.start > other_last.saturating_add(1) {
+            return None;
         }
 
-        Ok(Self {
-            address: VirtAddress::new(value)?,
-        })
+        let start = self.start.min(other.start);
+        Some(unsafe { Self::with_size_unchecked(start, last.max(other_last) - start + 1) })
     }
 
-    //masks of unsupported bits
-    #[inline]
-    pub fn new_maskoff(value: u64) -> Result<Self, MemoryAddressErrors> {
-        Ok(Self {
-            address: VirtAddress::new_maskoff(value & *LV1_PAGE_MASK)?,
-        })
-    }
+    ///Returns the parts of the range below and above >other<, None for parts that are empty
+    pub fn subtract(&self, other: &Self) -> (Option<Self>, Option<Self>) {
+        let (Some(last), Some(other_last)) = (self.get_last(), other.get_last()) else {
+            return ((!self.is_empty()).then_some(*self), None);
+        };
 
-    ///Caller has to ensure that >value< fullfills platform address space constrains
-    #[inline]
-    pub unsafe fn new_unchecked(value: u64) -> Self {
-        Self {
-            address: VirtAddress::new_unchecked(value),
-        }
-    }
+        let below = (self.start < other.start).then(|| {
+            let last = last.min(other.start - 1);
+            unsafe { Self::with_size_unchecked(self.start, last - self.start + 1) }
+        });
 
-    #[inline]
-    pub fn get_address(&self) -> VirtAddress {
-        self.address
-    }
+        let above = (other_last < last).then(|| {
+            let start = self.start.max(other_last + 1);
+            unsafe { Self::with_size_unchecked(start, last - start + 1) }
+        });
 
-    #[inline]
-    pub fn offset(&self, count: i64) -> Result<Self, MemoryAddressErrors> {
-        Self::new(
-            self.get_address()
-                .get_u64()
-                .checked_add_signed(*LV1_PAGE_SIZE as i64 * count)
-                .ok_or(MemoryAddressErrors::OutOfBounds)?,
-        )
+        (below, above)
     }
 
     #[inline]
-    pub fn offset_maskoff(&self, count: i64) -> Result<Self, MemoryAddressErrors> {
-        Self::new_maskoff(
-            self.get_address()
-                .get_u64()
-                .checked_add_signed(*LV1_PAGE_SIZE as i64 * count)
-                .ok_or(MemoryAddressErrors::OutOfBounds)?,
-        )
+    pub fn is_aligned<L: PageLevel>(&self) -> bool {
+        L::is_supported() && self.start & !L::get_mask() == 0 && self.size & !L::get_mask() == 0
     }
 
-    ///Caller has to ensure that the new address fullfills platform address space constrains
-    #[inline]
-    pub unsafe fn offset_unchecked(&self, count: i64) -> Self {
-        Self::new_unchecked(
-            self.get_address()
-                .get_u64()
-                .wrapping_add_signed(*LV1_PAGE_SIZE as i64 * count),
-        )
+    ///Shrinks the range to the pages of level >L< that are completely inside of it, the result can be empty
+    pub fn align_inward<L: PageLevel>(&self) -> Self {
+        if !L::is_supported() {
+            return Self::empty();
+        }
+
+        let head = self.start.wrapping_neg() & !L::get_mask();
+        if head >= self.size {
+            return Self::empty();
+        }
+
+        unsafe { Self::with_size_unchecked(self.start + head, (self.size - head) & L::get_mask()) }
     }
 
-    ///Converts the virtual Address into a physical Address via the HHDM
-    #[inline]
-    pub fn to_phys(&self) -> Result<PhysLv1PageAddress, MemoryAddressErrors> {
-        if self.get_address().get_u64() < (*HHDM_OFFSET).address.get_u64() {
+    ///Grows the range to the pages of level >L< that overlap it
+    pub fn align_outward<L: PageLevel>(&self) -> Result<Self, MemoryAddressErrors> {
+        if !L::is_supported() {
             return Err(MemoryAddressErrors::Invalid);
         }
 
-        PhysLv1PageAddress::new(self.get_address().get_u64() - (*HHDM_OFFSET).address.get_u64())
+        let Some(last) = self.get_last() else {
+            return Ok(*self);
+        };
+
+        let start = self.start & L::get_mask();
+        Self::with_size(start, (last | !L::get_mask()) - start + 1)
     }
 
-    ///Converts the virtual Address into a physical Address via the HHDM \
-    ///masks of unsupported bits
+    ///Returns the number of pages of level >L< that are completely inside of the range
     #[inline]
-    pub fn to_phys_maskoff(&self) -> PhysLv1PageAddress {
-        if self.get_address().get_u64() < (*HHDM_OFFSET).address.get_u64() {
-            return PhysLv1PageAddress::new_maskoff(0);
+    pub fn get_number_of_pages<L: PageLevel>(&self) -> u64 {
+        if !L::is_supported() {
+            return 0;
         }
 
-        PhysLv1PageAddress::new_maskoff(
-            self.get_address().get_u64() - (*HHDM_OFFSET).address.get_u64(),
-        )
+        self.align_inward::<L>().size / L::get_size()
     }
 
-    ///Converts the virtual Address into a physical Address via the HHDM \
-    ///Caller has to ensure that >value< fullfills platform address space constrains
-    #[inline]
-    pub unsafe fn to_phys_unchecked(&self) -> PhysLv1PageAddress {
-        PhysLv1PageAddress::new_unchecked(
-            self.get_address().get_u64() - (*HHDM_OFFSET).address.get_u64(),
-        )
-    }
+    ///Iterates over the pages of level >L< that are completely inside of the range
+    pub fn iter_pages<L: PageLevel>(&self) -> impl Iterator<Item = PageAddress<S, L>> {
+        let range = self.align_inward::<L>();
 
-    ///Performs a volatile Read from virtual Memory \
-    ///The caller has to ensure that the target page is mapped with the needed permissions \
-    ///The caller has to properly handle invalid instances of T returned by this function
-    #[inline(always)]
-    pub unsafe fn read<T>(&self) -> T
-    where
-        [(); size_of::<T>()]:,
-    {
-        self.address.read::<T>()
+        (0..self.get_number_of_pages::<L>()).map(move |index| unsafe {
+            PageAddress::<S, L>::new_unchecked(range.start + index * L::get_size())
+        })
     }
 
-    ///Performs a volatile Write to virtual Memory \
-    ///The caller has to ensure that the target page is mapped with the needed permissions
-    #[inline(always)]
-    pub unsafe fn write<T>(&self, value: &T)
-    where
-        [(); size_of::<T>()]:,
-    {
-        self.address.write::<T>(value)
-    }
-}
+    ///Splits the lv1 pages of the range into the largest aligned pages, lv3 before lv2 before lv1 \
+    ///Parts of the range at the ends that do not cover a whole lv1 page are left out
+    pub fn split_into_pages(&self) -> impl Iterator<Item = RangePage<S>> {
+        let range = self.align_inward::<Lv1>();
+        let mut address = range.start;
+        let mut remaining = range.size;
 
-//Wrapper that ensures that a address is always aligned to the page size one level above the base page size
-#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
-#[repr(transparent)]
-pub struct VirtLv2PageAddress {
-    address: VirtAddress,
-}
+        core::iter::from_fn(move || {
+            if remaining == 0 {
+                return None;
+            }
 
-impl VirtLv2PageAddress {
-    #[inline]
-    pub fn new(value: u64) -> Result<Self, MemoryAddressErrors> {
-        if value & (!*LV2_PAGE_MASK) != 0 {
-            return Err(MemoryAddressErrors::InvalidBits);
-        }
+            let fits = |size: u64| address % size == 0 && remaining >= size;
 
-        Ok(Self {
-            address: VirtAddress::new(value)?,
+            let page = unsafe {
+                if *LV3_PAGE_SUPPORTED && fits(Lv3::get_size()) {
+                    RangePage::Lv3(PageAddress::new_unchecked(address))
+                } else if *LV2_PAGE_SUPPORTED && fits(Lv2::get_size()) {
+                    RangePage::Lv2(PageAddress::new_unchecked(address))
+                } else {
+                    RangePage::Lv1(PageAddress::new_unchecked(address))
+                }
+            };
+
+            //the last page of the address space wraps the address to 0 together with remaining
+            address = address.wrapping_add(page.get_size());
+            remaining -= page.get_size();
+
+            Some(page)
         })
     }
+}
 
+impl AddressRange<Phys> {
+    ///Range of every physical address the platform supports
     #[inline]
-    //masks of unsupported bits
-    pub fn new_maskoff(value: u64) -> Result<Self, MemoryAddressErrors> {
-        Ok(Self {
-            address: VirtAddress::new_maskoff(value & *LV2_PAGE_MASK)?,
-        })
+    pub fn get_address_space() -> PhysRange {
+        unsafe { Self::with_size_unchecked(0, PLATFORM_PARAMETERS.phys_address_mask + 1) }
     }
+}
 
+impl AddressRange<Virt> {
+    ///Lower half of the virtual address space without the null page, every root has its own
     #[inline]
-    ///Caller has to ensure that >value< fullfills platform address space constrains
-    pub unsafe fn new_unchecked(value: u64) -> Self {
-        Self {
-            address: VirtAddress::new_unchecked(value),
-        }
+    pub fn get_user_half() -> VirtRange {
+        let half_size = (PLATFORM_PARAMETERS.virt_address_mask >> 1) + 1;
+        unsafe { Self::with_size_unchecked(*LV1_PAGE_SIZE, half_size - *LV1_PAGE_SIZE) }
     }
 
+    ///Part of the upper half that kernel segments are placed in, the upper half is shared by all roots
     #[inline]
-    pub fn get_address(&self) -> VirtAddress {
-        self.address
+    pub fn get_kernel_segment_window() -> VirtRange {
+        unsafe {
+            Self::with_size_unchecked(
+                super::arch::memory::SEGMENT_WINDOW_START,
+                super::arch::memory::SEGMENT_WINDOW_SIZE,
+            )
+        }
     }
+}
 
-    #[inline]
-    pub fn offset(&self, count: i64) -> Result<Self, MemoryAddressErrors> {
-        Self::new(
-            self.get_address()
-                .get_u64()
-                .checked_add_signed(*LV2_PAGE_SIZE as i64 * count)
-                .ok_or(MemoryAddressErrors::OutOfBounds)?,
-        )
+///General Purpose Optimized Memory Functions for larger Memory Operations
+pub mod utility {
+    use crate::hal::arch;
+
+    use super::{
+        PhysLv1PageAddress, PhysLv2PageAddress, PhysLv3PageAddress, PhysRange, RangePage,
+        VirtAddress, LV1_PAGE_SIZE, LV2_PAGE_SIZE, LV3_PAGE_SIZE,
+    };
+
+    ///Fills >size< bytes at >address< with >value< \
+    ///The caller has to ensure that the range is mapped writable
+    #[inline(always)]
+    pub unsafe fn memset(address: VirtAddress, value: u8, size: usize) {
+        arch::memory::memset(address.get_u64() as *mut u8, value, size)
     }
 
-    #[inline]
-    pub fn offset_maskoff(&self, count: i64) -> Result<Self, MemoryAddressErrors> {
-        Self::new_maskoff(
-            self.get_address()
-                .get_u64()
-                .checked_add_signed(*LV2_PAGE_SIZE as i64 * count)
-                .ok_or(MemoryAddressErrors::OutOfBounds)?,
+    ///Copies >size< bytes from >source< to >destination<, the two ranges must not overlap \
+    ///The caller has to ensure that both ranges are mapped with the needed permissions
+    #[inline(always)]
+    pub unsafe fn memcpy(destination: VirtAddress, source: VirtAddress, size: usize) {
+        arch::memory::memcpy(
+            destination.get_u64() as *mut u8,
+            source.get_u64() as *const u8,
+            size,
         )
     }
 
-    ///Caller has to ensure that the new address fullfills platform address space constrains
-    #[inline]
-    pub unsafe fn offset_unchecked(&self, count: i64) -> Self {
-        Self::new_unchecked(
-            self.get_address()
-                .get_u64()
-                .wrapping_add_signed(*LV2_PAGE_SIZE as i64 * count),
+    ///Zeroes the page through the HHDM, the caller has to own the page
+    #[inline(always)]
+    pub unsafe fn zero_lv1(page: PhysLv1PageAddress) {
+        arch::memory::zero_pages(
+            page.to_virt_unchecked().get_address().get_u64() as *mut u8,
+            *LV1_PAGE_SIZE as usize,
         )
     }
 
-    #[inline]
-    pub fn to_phys(&self) -> Result<PhysLv2PageAddress, MemoryAddressErrors> {
-        if self.get_address().get_u64() < (*HHDM_OFFSET).address.get_u64() {
-            return Err(MemoryAddressErrors::Invalid);
-        }
-
-        PhysLv2PageAddress::new(self.get_address().get_u64() - (*HHDM_OFFSET).address.get_u64())
+    ///Zeroes the page through the HHDM, the caller has to own the page
+    #[inline(always)]
+    pub unsafe fn zero_lv2(page: PhysLv2PageAddress) {
+        arch::memory::zero_pages(
+            page.to_virt_unchecked().get_address().get_u64() as *mut u8,
+            *LV2_PAGE_SIZE as usize,
+        )
     }
 
-    ///Converts the virtual Address into a physical Address via the HHDM \
-    ///masks of unsupported bits
-    #[inline]
-    pub fn to_phys_maskoff(&self) -> PhysLv2PageAddress {
-        if self.get_address().get_u64() < (*HHDM_OFFSET).address.get_u64() {
-            return PhysLv2PageAddress::new_maskoff(0);
-        }
-
-        PhysLv2PageAddress::new_maskoff(
-            self.get_address().get_u64() - (*HHDM_OFFSET).address.get_u64(),
+    ///Zeroes the page through the HHDM, the caller has to own the page
+    #[inline(always)]
+    pub unsafe fn zero_lv3(page: PhysLv3PageAddress) {
+        arch::memory::zero_pages(
+            page.to_virt_unchecked().get_address().get_u64() as *mut u8,
+            *LV3_PAGE_SIZE as usize,
         )
     }
 
-    ///Converts the virtual Address into a physical Address via the HHDM \
-    ///Caller has to ensure that >value< fullfills platform address space constrains
-    #[inline]
-    pub unsafe fn to_phys_unchecked(&self) -> PhysLv2PageAddress {
-        PhysLv2PageAddress::new_unchecked(
-            self.get_address().get_u64() - (*HHDM_OFFSET).address.get_u64(),
+    ///Zeroes the page through the HHDM without evicting the cache, the caller has to own the page \
+    ///Huge pages are rarely accessed as a whole right after the allocation, so zeroing them through the cache only evicts useful lines
+    #[inline(always)]
+    pub unsafe fn zero_lv2_nontemporal(page: PhysLv2PageAddress) {
+        arch::memory::zero_pages_nontemporal(
+            page.to_virt_unchecked().get_address().get_u64() as *mut u8,
+            *LV2_PAGE_SIZE as usize,
         )
     }
 
-    ///Performs a volatile Read to virtual Memory \
-    ///The caller has to ensure that the target page is mapped with the needed permissions \
-    ///The caller has to properly handle invalid instances of T returned by this function
+    ///Zeroes the page through the HHDM without evicting the cache, the caller has to own the page
     #[inline(always)]
-    pub unsafe fn read<T>(&self) -> T
-    where
-        [(); size_of::<T>()]:,
-    {
-        self.address.read::<T>()
+    pub unsafe fn zero_lv3_nontemporal(page: PhysLv3PageAddress) {
+        arch::memory::zero_pages_nontemporal(
+            page.to_virt_unchecked().get_address().get_u64() as *mut u8,
+            *LV3_PAGE_SIZE as usize,
+        )
     }
 
-    ///Performs a volatile Write to virtual Memory \
-    ///The caller has to ensure that the target page is mapped with the needed permissions
-    #[inline(always)]
-    pub unsafe fn write<T>(&self, value: &T)
-    where
-        [(); size_of::<T>()]:,
-    {
-        self.address.write::<T>(value)
+    ///Zeroes the lv1 pages of the range through the HHDM, the parts that make up lv2 and lv3 pages are zeroed non temporal \
+    ///The caller has to own the pages
+    pub unsafe fn zero_range(range: PhysRange) {
+        for page in range.split_into_pages() {
+            match page {
+                RangePage::Lv1(page) => zero_lv1(page),
+                RangePage::Lv2(page) => zero_lv2_nontemporal(page),
+                RangePage::Lv3(page) => zero_lv3_nontemporal(page),
+            }
+        }
     }
 }
 
-//Wrapper that ensures that a address is always aligned to the page size two level above the base page size
-#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
-#[repr(transparent)]
-pub struct VirtLv3PageAddress {
-    address: VirtAddress,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::simulated_memory::{init_platform_parameters, FOUR_LEVEL};
 
-impl VirtLv3PageAddress {
-    #[inline]
-    pub fn new(value: u64) -> Result<Self, MemoryAddressErrors> {
-        if value & (!*LV3_PAGE_MASK) != 0 {
-            return Err(MemoryAddressErrors::InvalidBits);
-        }
+    const FIVE_LEVEL: PlatformParameters = PlatformParameters {
+        virt_address_mask: 0x1FF_FFFF_FFFF_FFFF,
+        cannonical_bit: Some(56),
+        ..FOUR_LEVEL
+    };
 
-        Ok(Self {
-            address: VirtAddress::new(value)?,
-        })
-    }
+    //where limine places the HHDM
+    const FOUR_LEVEL_HHDM: u64 = 0xFFFF_8000_0000_0000;
+    const FIVE_LEVEL_HHDM: u64 = 0xFF00_0000_0000_0000;
 
-    ///masks of unsupported bits
-    #[inline]
-    pub fn new_maskoff(value: u64) -> Result<Self, MemoryAddressErrors> {
-        Ok(Self {
-            address: VirtAddress::new_maskoff(value & *LV3_PAGE_MASK)?,
-        })
+    fn check_virt(parameters: &PlatformParameters, value: u64) -> Result<u64, MemoryAddressErrors> {
+        parameters.check_virt(value).map(NonZeroU64::get)
     }
 
-    ///Caller has to ensure that >value< fullfills platform address space constrains
-    #[inline]
-    pub unsafe fn new_unchecked(value: u64) -> Self {
-        Self {
-            address: VirtAddress::new_unchecked(value),
-        }
-    }
+    #[test]
+    fn canonical_addresses_are_accepted() {
+        for (parameters, bits) in [(FOUR_LEVEL, 48), (FIVE_LEVEL, 57)] {
+            let lower_last = (1u64 << (bits - 1)) - 1;
+            let upper_first = !lower_last;
 
-    #[inline]
-    pub fn get_address(&self) -> VirtAddress {
-        self.address
+            assert_eq!(check_virt(&parameters, 0x1000).unwrap(), 0x1000);
+            assert_eq!(check_virt(&parameters, lower_last).unwrap(), lower_last);
+            assert_eq!(check_virt(&parameters, upper_first).unwrap(), upper_first);
+            assert_eq!(check_virt(&parameters, u64::MAX).unwrap(), u64::MAX);
+        }
     }
 
-    #[inline]
-    pub fn offset(&self, count: i64) -> Result<Self, MemoryAddressErrors> {
-        Self::new(
-            self.get_address()
-                .get_u64()
-                .checked_add_signed(*LV3_PAGE_SIZE as i64 * count)
-                .ok_or(MemoryAddressErrors::OutOfBounds)?,
-        )
-    }
+    #[test]
+    fn non_canonical_addresses_are_rejected() {
+        for (parameters, bits) in [(FOUR_LEVEL, 48), (FIVE_LEVEL, 57)] {
+            let lower_last = (1u64 << (bits - 1)) - 1;
+
+            assert!(matches!(
+                check_virt(&parameters, lower_last + 1),
+                Err(MemoryAddressErrors::NonCannonical)
+            ));
+            assert!(matches!(
+                check_virt(&parameters, !lower_last - 1),
+                Err(MemoryAddressErrors::NonCannonical)
+            ));
+            assert!(matches!(
+                check_virt(&parameters, 1 << 63),
+                Err(MemoryAddressErrors::NonCannonical)
+            ));
+            assert!(matches!(
+                check_virt(&parameters, 0),
+                Err(MemoryAddressErrors::NULLPTR)
+            ));
+        }
 
-    #[inline]
-    pub fn offset_maskoff(&self, count: i64) -> Result<Self, MemoryAddressErrors> {
-        Self::new_maskoff(
-            self.get_address()
-                .get_u64()
-                .checked_add_signed(*LV3_PAGE_SIZE as i64 * count)
-                .ok_or(MemoryAddressErrors::OutOfBounds)?,
-        )
+        //the hole of 4 level paging is usable address space with 5 levels
+        assert!(check_virt(&FOUR_LEVEL, 0x0000_8000_0000_0000).is_err());
+        assert!(check_virt(&FIVE_LEVEL, 0x0000_8000_0000_0000).is_ok());
+        assert!(check_virt(&FOUR_LEVEL, 0xFF00_0000_0000_0000).is_err());
+        assert!(check_virt(&FIVE_LEVEL, 0xFF00_0000_0000_0000).is_ok());
+
+        let without_cannonical_bit = PlatformParameters {
+            cannonical_bit: None,
+            ..FOUR_LEVEL
+        };
+        assert!(matches!(
+            check_virt(&without_cannonical_bit, FOUR_LEVEL_HHDM),
+            Err(MemoryAddressErrors::InvalidBits)
+        ));
+    }
+
+    #[test]
+    fn maskoff_sign_extends_the_cannonical_bit() {
+        let address = 0x1234_8000_0000_1000;
+
+        assert_eq!(
+            FOUR_LEVEL.maskoff_virt(address).unwrap().get(),
+            0xFFFF_8000_0000_1000
+        );
+        assert_eq!(
+            FIVE_LEVEL.maskoff_virt(address).unwrap().get(),
+            0x0034_8000_0000_1000
+        );
+        assert_eq!(
+            FIVE_LEVEL
+                .maskoff_virt(0x0100_0000_0000_1000)
+                .unwrap()
+                .get(),
+            0xFF00_0000_0000_1000
+        );
+
+        for parameters in [FOUR_LEVEL, FIVE_LEVEL] {
+            //a masked address always passes the check
+            let masked = parameters.maskoff_virt(address).unwrap().get();
+            assert_eq!(check_virt(&parameters, masked).unwrap(), masked);
+
+            assert!(matches!(
+                parameters.maskoff_virt(0xFFFE_0000_0000_0000 & !parameters.virt_address_mask),
+                Err(MemoryAddressErrors::NULLPTR)
+            ));
+        }
     }
 
-    ///Caller has to ensure that the new address fullfills platform address space constrains
-    #[inline]
-    pub unsafe fn offset_unchecked(&self, count: i64) -> Self {
-        Self::new_unchecked(
-            self.get_address()
-                .get_u64()
-                .wrapping_add_signed(*LV3_PAGE_SIZE as i64 * count),
-        )
+    #[test]
+    fn phys_addresses_are_limited_to_the_mask() {
+        for parameters in [FOUR_LEVEL, FIVE_LEVEL] {
+            assert_eq!(parameters.check_phys(0).unwrap(), 0);
+            assert_eq!(
+                parameters.check_phys(0xF_FFFF_FFFF_FFFF).unwrap(),
+                0xF_FFFF_FFFF_FFFF
+            );
+            assert!(matches!(
+                parameters.check_phys(1 << 52),
+                Err(MemoryAddressErrors::InvalidBits)
+            ));
+            assert_eq!(parameters.maskoff_phys(0x8010_0000_0000_1000), 0x1000);
+        }
     }
 
-    ///Converts the virtual Address into a physical Address via the HHDM
-    #[inline]
-    pub fn to_phys(&self) -> Result<PhysLv3PageAddress, MemoryAddressErrors> {
-        if self.get_address().get_u64() < (*HHDM_OFFSET).address.get_u64() {
-            return Err(MemoryAddressErrors::Invalid);
-        }
+    #[test]
+    fn offsets_do_not_overflow() {
+        assert_eq!(offset_address(0x1000, 8, 2).unwrap(), 0x1010);
+        assert_eq!(offset_address(0x1000, 0x1000, -1).unwrap(), 0);
+        assert!(matches!(
+            offset_address(u64::MAX - 7, 8, 1),
+            Err(MemoryAddressErrors::OutOfBounds)
+        ));
+        assert!(matches!(
+            offset_address(0x1000, 0x1000, -2),
+            Err(MemoryAddressErrors::OutOfBounds)
+        ));
+        assert!(matches!(
+            offset_address(0, 0x1000, i64::MAX),
+            Err(MemoryAddressErrors::OutOfBounds)
+        ));
+        assert!(matches!(
+            offset_address(0, 0x1000, i64::MIN),
+            Err(MemoryAddressErrors::OutOfBounds)
+        ));
+
+        //leaving the lower half is only an error if the address space ends there
+        let next = offset_address(0x7FFF_FFFF_F000, 0x1000, 1).unwrap();
+        assert!(matches!(
+            check_virt(&FOUR_LEVEL, next),
+            Err(MemoryAddressErrors::NonCannonical)
+        ));
+        assert_eq!(check_virt(&FIVE_LEVEL, next).unwrap(), 0x8000_0000_0000);
+    }
+
+    #[test]
+    fn hhdm_conversion_round_trips() {
+        for (parameters, hhdm_offset) in
+            [(FOUR_LEVEL, FOUR_LEVEL_HHDM), (FIVE_LEVEL, FIVE_LEVEL_HHDM)]
+        {
+            for phys in [0, 0x1000, 0x1_2345_6000, 0x3FFF_FFFF_F000] {
+                let virt = parameters.phys_to_virt(phys, hhdm_offset).unwrap().get();
 
-        PhysLv3PageAddress::new(self.get_address().get_u64() - (*HHDM_OFFSET).address.get_u64())
-    }
+                assert_eq!(virt, hhdm_offset + phys);
+                assert_eq!(parameters.virt_to_phys(virt, hhdm_offset).unwrap(), phys);
+            }
 
-    ///Converts the virtual Address into a physical Address via the HHDM \
-    ///masks of unsupported bits
-    #[inline]
-    pub fn to_phys_maskoff(&self) -> PhysLv3PageAddress {
-        if self.get_address().get_u64() < (*HHDM_OFFSET).address.get_u64() {
-            return PhysLv3PageAddress::new_maskoff(0);
+            //below the HHDM
+            assert!(matches!(
+                parameters.virt_to_phys(0x1000, hhdm_offset),
+                Err(MemoryAddressErrors::Invalid)
+            ));
         }
 
-        PhysLv3PageAddress::new_maskoff(
-            self.get_address().get_u64() - (*HHDM_OFFSET).address.get_u64(),
-        )
-    }
-
-    ///Converts the virtual Address into a physical Address via the HHDM \
-    ///Caller has to ensure that >value< fullfills platform address space constrains
-    #[inline]
-    pub unsafe fn to_phys_unchecked(&self) -> PhysLv3PageAddress {
-        PhysLv3PageAddress::new_unchecked(
-            self.get_address().get_u64() - (*HHDM_OFFSET).address.get_u64(),
-        )
-    }
+        //the 4 level HHDM only covers 128TiB before the address wraps around
+        assert!(matches!(
+            FOUR_LEVEL.phys_to_virt(1 << 47, FOUR_LEVEL_HHDM),
+            Err(MemoryAddressErrors::OutOfBounds)
+        ));
+        assert_eq!(
+            FIVE_LEVEL
+                .phys_to_virt(1 << 47, FIVE_LEVEL_HHDM)
+                .unwrap()
+                .get(),
+            0xFF00_8000_0000_0000
+        );
+
+        //the HHDM offset of the other paging mode is not cannonical
+        assert!(matches!(
+            FOUR_LEVEL.phys_to_virt(0x1000, FIVE_LEVEL_HHDM),
+            Err(MemoryAddressErrors::NonCannonical)
+        ));
+        assert!(matches!(
+            FOUR_LEVEL.virt_to_phys(u64::MAX, FOUR_LEVEL_HHDM - (1 << 60)),
+            Err(MemoryAddressErrors::InvalidBits)
+        ));
+    }
+
+    //the page address types read the global parameters, which are FOUR_LEVEL in every test
+
+    #[test]
+    fn page_addresses_are_aligned_to_their_level() {
+        init_platform_parameters();
+
+        assert!(PhysLv2PageAddress::new(0x20_0000).is_ok());
+        assert!(matches!(
+            PhysLv2PageAddress::new(0x20_1000),
+            Err(MemoryAddressErrors::InvalidBits)
+        ));
+        assert!(matches!(
+            VirtLv1PageAddress::new(0x8000_0000_0000),
+            Err(MemoryAddressErrors::NonCannonical)
+        ));
+    }
+
+    #[test]
+    fn align_down_and_up_round_to_the_page_of_the_level() {
+        init_platform_parameters();
+
+        let page = PhysLv1PageAddress::new(0x4020_3000).unwrap();
+        let get = |address: PhysAddress| address.get_u64();
+
+        assert_eq!(
+            get(page.align_down::<Lv1>().unwrap().get_address()),
+            0x4020_3000
+        );
+        assert_eq!(
+            get(page.align_down::<Lv2>().unwrap().get_address()),
+            0x4020_0000
+        );
+        assert_eq!(
+            get(page.align_down::<Lv3>().unwrap().get_address()),
+            0x4000_0000
+        );
+        assert_eq!(
+            get(page.align_up::<Lv1>().unwrap().get_address()),
+            0x4020_3000
+        );
+        assert_eq!(
+            get(page.align_up::<Lv2>().unwrap().get_address()),
+            0x4040_0000
+        );
+        assert_eq!(
+            get(page.align_up::<Lv3>().unwrap().get_address()),
+            0x8000_0000
+        );
+
+        //an aligned page stays where it is
+        let aligned = PhysLv1PageAddress::new(0x4000_0000).unwrap();
+        assert_eq!(
+            get(aligned.align_up::<Lv3>().unwrap().get_address()),
+            0x4000_0000
+        );
+        assert_eq!(
+            get(aligned.align_down::<Lv3>().unwrap().get_address()),
+            0x4000_0000
+        );
+
+        //the last page of the address space has no lv2 page above it
+        let last = VirtLv1PageAddress::new(0xFFFF_FFFF_FFFF_F000).unwrap();
+        assert!(matches!(
+            last.align_up::<Lv2>(),
+            Err(MemoryAddressErrors::OutOfBounds)
+        ));
+        assert_eq!(
+            last.align_down::<Lv2>().unwrap().get_address().get_u64(),
+            0xFFFF_FFFF_FFE0_0000
+        );
+
+        //the lower half ends below the next lv3 page
+        let top = VirtLv1PageAddress::new(0x7FFF_FFFF_F000).unwrap();
+        assert!(matches!(
+            top.align_up::<Lv3>(),
+            Err(MemoryAddressErrors::NonCannonical)
+        ));
+    }
+
+    #[test]
+    fn containing_lv2_is_the_lv2_page_around_the_page() {
+        init_platform_parameters();
+
+        for (address, lv2) in [
+            (0x0000_1000, 0x0000_0000),
+            (0x001F_F000, 0x0000_0000),
+            (0x0020_0000, 0x0020_0000),
+            (0x4035_7000, 0x4020_0000),
+        ] {
+            let page = PhysLv1PageAddress::new(address).unwrap();
+            assert_eq!(page.containing_lv2().unwrap().get_address().get_u64(), lv2);
+        }
 
-    ///Performs a volatile Read to virtual Memory \
-    ///The caller has to ensure that the target page is mapped with the needed permissions \
-    ///The caller has to properly handle invalid instances of T returned by this function
-    #[inline(always)]
-    pub unsafe fn read<T>(&self) -> T
-    where
-        [(); size_of::<T>()]:,
-    {
-        self.address.read::<T>()
+        let lv3 = VirtLv3PageAddress::new(0xFFFF_8000_4000_0000).unwrap();
+        assert_eq!(
+            lv3.containing_lv2().unwrap().get_address().get_u64(),
+            0xFFFF_8000_4000_0000
+        );
     }
 
-    ///Performs a volatile Write to virtual Memory \
-    ///The caller has to ensure that the target page is mapped with the needed permissions
-    #[inline(always)]
-    pub unsafe fn write<T>(&self, value: &T)
-    where
-        [(); size_of::<T>()]:,
-    {
-        self.address.write::<T>(value)
-    }
-}
+    #[test]
+    fn iter_lv1_pages_covers_the_page() {
+        init_platform_parameters();
 
-/*
-///General Purpose Optimized Memory Functions for larger Memory Operations
+        let lv1 = PhysLv1PageAddress::new(0x5000).unwrap();
+        assert_eq!(lv1.iter_lv1_pages().collect::<Vec<_>>(), [lv1]);
 
-pub mod utility {
-    use core::mem::size_of;
+        let lv2 = VirtLv2PageAddress::new(0xFFFF_8000_0020_0000).unwrap();
+        let pages: Vec<u64> = lv2
+            .iter_lv1_pages()
+            .map(|page| page.get_address().get_u64())
+            .collect();
 
-    use alloc::boxed::Box;
-    use x86_64::VirtAddr;
+        assert_eq!(pages.len(), 512);
+        assert!(pages
+            .iter()
+            .enumerate()
+            .all(|(index, page)| *page == 0xFFFF_8000_0020_0000 + index as u64 * 0x1000));
 
-    #[inline(always)]
-    pub fn memset_1(ptr: VirtAddr, value: bool, size: usize) {
-        unsafe { memset_core(ptr.as_u64() as *mut u8, value, size) }
+        let lv3 = PhysLv3PageAddress::new(0x4000_0000).unwrap();
+        let mut pages = lv3.iter_lv1_pages();
+        assert_eq!(pages.next().unwrap().get_address().get_u64(), 0x4000_0000);
+        assert_eq!(pages.last().unwrap().get_address().get_u64(), 0x7FFF_F000);
+        assert_eq!(lv3.iter_lv1_pages().count(), 512 * 512);
     }
 
-    #[inline(always)]
-    pub fn memset_2<T>(ptr: &mut Box<T>, value: bool) {
-        unsafe {
-            memset_core(
-                core::ptr::addr_of_mut!(*ptr) as *mut u8,
-                value,
-                size_of::<T>(),
-            )
-        }
+    fn phys(start: u64, end: u64) -> PhysRange {
+        PhysRange::new(start, end).unwrap()
     }
 
-    #[inline(always)]
-    unsafe fn memcpy_1(ptr_src: VirtAddr, ptr_dst: VirtAddr, size: usize) {
-        memcpy_core(
-            ptr_src.as_u64() as *mut u8,
-            ptr_dst.as_u64() as *mut u8,
-            size,
-        )
-    }
+    #[test]
+    fn overlapping_ranges_intersect() {
+        init_platform_parameters();
 
-    compile_error!("TODO");
+        let range = phys(0x1000, 0x3000);
+        let touching = phys(0x3000, 0x4000);
+        let contained = phys(0x1800, 0x2000);
+        let partial = phys(0x2000, 0x5000);
+        let disjoint = phys(0x5000, 0x6000);
+        let empty = phys(0x2000, 0x2000);
 
-    #[inline(always)]
-    unsafe fn memset_core(ptr: *mut u8, value: bool, size: usize) {
-        //arch::memory::memset()
-    }
+        assert!(range.overlaps(&contained) && contained.overlaps(&range));
+        assert_eq!(range.intersection(&contained), Some(contained));
+        assert_eq!(range.intersection(&partial), Some(phys(0x2000, 0x3000)));
+        assert_eq!(partial.intersection(&range), Some(phys(0x2000, 0x3000)));
+        assert_eq!(range.intersection(&range), Some(range));
 
-    #[inline(always)]
-    unsafe fn memcpy_core(ptr_src: *mut u8, ptr_dst: *mut u8, size: usize) {
-        //arch::memory::memcpy()
+        for other in [touching, disjoint, empty, PhysRange::empty()] {
+            assert!(!range.overlaps(&other) && !other.overlaps(&range));
+            assert_eq!(range.intersection(&other), None);
+        }
+
+        assert!(range.contains(&range));
+        assert!(range.contains(&contained) && !contained.contains(&range));
+        assert!(!range.contains(&partial) && !range.contains(&touching));
+        assert!(!range.contains(&disjoint));
+
+        //an empty range is contained everywhere, but contains nothing that is not empty
+        assert!(range.contains(&empty) && disjoint.contains(&empty));
+        assert!(empty.contains(&PhysRange::empty()));
+        assert!(!empty.contains(&range));
+
+        assert!(range.contains_address(0x1000) && range.contains_address(0x2FFF));
+        assert!(!range.contains_address(0x3000) && !range.contains_address(0xFFF));
+        assert!(!empty.contains_address(0x2000));
+    }
+
+    #[test]
+    fn ranges_can_end_at_the_top_of_the_address_space() {
+        init_platform_parameters();
+
+        let top = VirtRange::with_size(0xFFFF_FFFF_FFFF_E000, 0x2000).unwrap();
+        let last = VirtRange::with_size(0xFFFF_FFFF_FFFF_F000, 0x1000).unwrap();
+
+        assert_eq!(top.get_end(), 0);
+        assert_eq!(top.get_last(), Some(u64::MAX));
+        assert!(top.contains_address(u64::MAX) && top.contains(&last));
+        assert_eq!(top.intersection(&last), Some(last));
+        assert_eq!(
+            top.subtract(&last),
+            (
+                Some(VirtRange::with_size(0xFFFF_FFFF_FFFF_E000, 0x1000).unwrap()),
+                None
+            )
+        );
+
+        //the range may not cross the cannonical hole
+        assert!(matches!(
+            VirtRange::with_size(0x7FFF_FFFF_F000, 0x2000),
+            Err(MemoryAddressErrors::NonCannonical)
+        ));
+    }
+
+    #[test]
+    fn subtract_returns_the_parts_below_and_above() {
+        init_platform_parameters();
+
+        let range = phys(0x1000, 0x3000);
+
+        assert_eq!(
+            range.subtract(&phys(0x1800, 0x2000)),
+            (Some(phys(0x1000, 0x1800)), Some(phys(0x2000, 0x3000)))
+        );
+        assert_eq!(
+            range.subtract(&phys(0x2000, 0x5000)),
+            (Some(phys(0x1000, 0x2000)), None)
+        );
+        assert_eq!(
+            range.subtract(&phys(0x0, 0x2000)),
+            (None, Some(phys(0x2000, 0x3000)))
+        );
+
+        //a touching or disjoint range leaves the range on the side it is on
+        assert_eq!(range.subtract(&phys(0x3000, 0x4000)), (Some(range), None));
+        assert_eq!(range.subtract(&phys(0x0, 0x1000)), (None, Some(range)));
+        assert_eq!(range.subtract(&phys(0x5000, 0x6000)), (Some(range), None));
+
+        assert_eq!(range.subtract(&range), (None, None));
+        assert_eq!(range.subtract(&phys(0x0, 0x4000)), (None, None));
+
+        assert_eq!(range.subtract(&PhysRange::empty()), (Some(range), None));
+        assert_eq!(PhysRange::empty().subtract(&range), (None, None));
+    }
+
+    #[test]
+    fn join_covers_touching_and_overlapping_ranges() {
+        init_platform_parameters();
+
+        let range = phys(0x1000, 0x3000);
+
+        assert_eq!(
+            range.join(&phys(0x3000, 0x4000)),
+            Some(phys(0x1000, 0x4000))
+        );
+        assert_eq!(
+            phys(0x3000, 0x4000).join(&range),
+            Some(phys(0x1000, 0x4000))
+        );
+        assert_eq!(
+            range.join(&phys(0x2000, 0x5000)),
+            Some(phys(0x1000, 0x5000))
+        );
+        assert_eq!(range.join(&phys(0x1800, 0x2000)), Some(range));
+        assert_eq!(range.join(&phys(0x5000, 0x6000)), None);
+        assert_eq!(phys(0x5000, 0x6000).join(&range), None);
+
+        //an empty range joins with everything, no matter where it starts
+        assert_eq!(range.join(&phys(0x8000, 0x8000)), Some(range));
+        assert_eq!(PhysRange::empty().join(&range), Some(range));
+        assert_eq!(
+            PhysRange::empty().join(&PhysRange::empty()),
+            Some(PhysRange::empty())
+        );
+    }
+
+    #[test]
+    fn align_inward_and_outward_round_to_whole_pages() {
+        init_platform_parameters();
+
+        let range = phys(0x1800, 0x5800);
+
+        assert!(!range.is_aligned::<Lv1>());
+        assert_eq!(range.align_inward::<Lv1>(), phys(0x2000, 0x5000));
+        assert_eq!(range.align_outward::<Lv1>().unwrap(), phys(0x1000, 0x6000));
+        assert!(range.align_inward::<Lv1>().is_aligned::<Lv1>());
+        assert!(range.align_outward::<Lv1>().unwrap().is_aligned::<Lv1>());
+
+        //no lv2 page fits into the range, but one covers it
+        assert!(range.align_inward::<Lv2>().is_empty());
+        assert_eq!(range.align_outward::<Lv2>().unwrap(), phys(0x0, 0x20_0000));
+
+        let aligned = phys(0x20_0000, 0x60_0000);
+        assert!(aligned.is_aligned::<Lv2>() && !aligned.is_aligned::<Lv3>());
+        assert_eq!(aligned.align_inward::<Lv2>(), aligned);
+        assert_eq!(aligned.align_outward::<Lv2>().unwrap(), aligned);
+
+        //a range inside a single page
+        assert!(phys(0x1100, 0x1200).align_inward::<Lv1>().is_empty());
+        assert_eq!(
+            phys(0x1100, 0x1200).align_outward::<Lv1>().unwrap(),
+            phys(0x1000, 0x2000)
+        );
+
+        assert!(PhysRange::empty().align_inward::<Lv1>().is_empty());
+        assert_eq!(
+            PhysRange::empty().align_outward::<Lv1>().unwrap(),
+            PhysRange::empty()
+        );
+
+        //the last page of the address space can not be grown past the top
+        let top = VirtRange::with_size(0xFFFF_FFFF_FFFF_F800, 0x800).unwrap();
+        assert_eq!(
+            top.align_outward::<Lv1>().unwrap(),
+            VirtRange::with_size(0xFFFF_FFFF_FFFF_F000, 0x1000).unwrap()
+        );
+    }
+
+    #[test]
+    fn iter_pages_yields_the_pages_inside_the_range() {
+        init_platform_parameters();
+
+        let range = phys(0x1800, 0x5800);
+        let pages: Vec<u64> = range
+            .iter_pages::<Lv1>()
+            .map(|page| page.get_address().get_u64())
+            .collect();
+
+        assert_eq!(pages, [0x2000, 0x3000, 0x4000]);
+        assert_eq!(range.get_number_of_pages::<Lv1>(), 3);
+        assert_eq!(range.iter_pages::<Lv2>().count(), 0);
+
+        let lv2: Vec<u64> = phys(0x1F_F000, 0x60_1000)
+            .iter_pages::<Lv2>()
+            .map(|page| page.get_address().get_u64())
+            .collect();
+        assert_eq!(lv2, [0x20_0000, 0x40_0000]);
+
+        assert_eq!(PhysRange::empty().iter_pages::<Lv1>().count(), 0);
+    }
+
+    #[test]
+    fn split_into_pages_uses_the_largest_aligned_pages() {
+        init_platform_parameters();
+
+        let lv1 = |address| RangePage::Lv1(PhysLv1PageAddress::new(address).unwrap());
+        let lv2 = |address| RangePage::Lv2(PhysLv2PageAddress::new(address).unwrap());
+        let lv3 = |address| RangePage::Lv3(PhysLv3PageAddress::new(address).unwrap());
+
+        //the partial lv1 pages at both ends are left out
+        let pages: Vec<_> = phys(0x1F_E800, 0x60_1800).split_into_pages().collect();
+        assert_eq!(
+            pages,
+            [
+                lv1(0x1F_F000),
+                lv2(0x20_0000),
+                lv2(0x40_0000),
+                lv1(0x60_0000)
+            ]
+        );
+
+        let pages: Vec<_> = phys(0x3FE0_0000, 0x8020_0000).split_into_pages().collect();
+        assert_eq!(
+            pages,
+            [lv2(0x3FE0_0000), lv3(0x4000_0000), lv2(0x8000_0000)]
+        );
+
+        //an aligned lv2 range that is too short for a lv3 page
+        let pages: Vec<_> = phys(0x4000_0000, 0x4040_0000).split_into_pages().collect();
+        assert_eq!(pages, [lv2(0x4000_0000), lv2(0x4020_0000)]);
+
+        //the sizes of the pages add up to the whole pages of the range
+        let range = phys(0x1234, 0x4567_8000);
+        assert_eq!(
+            range
+                .split_into_pages()
+                .map(|page| page.get_size())
+                .sum::<u64>(),
+            range.align_inward::<Lv1>().get_size()
+        );
+
+        assert_eq!(phys(0x1100, 0x1200).split_into_pages().count(), 0);
+        assert_eq!(PhysRange::empty().split_into_pages().count(), 0);
+
+        //the last page of the address space ends the iteration
+        let top = VirtRange::with_size(0xFFFF_FFFF_FFC0_0000, 0x40_0000).unwrap();
+        let pages: Vec<u64> = top
+            .split_into_pages()
+            .map(|page| page.get_start())
+            .collect();
+        assert_eq!(pages, [0xFFFF_FFFF_FFC0_0000, 0xFFFF_FFFF_FFE0_0000]);
     }
 }
-    */
@@ -0,0 +1,296 @@
+//Memory mapped device registers
+//The HHDM maps everything WB, so device memory is mapped a second time into a window of the kernel half with CachingMode::MMIO
+//Register blocks are #[repr(C)] structs of ReadOnly/WriteOnly/ReadWrite fields that are placed over the mapping with MmioRegion::get_block
+
+use core::{
+    cell::UnsafeCell,
+    mem::{align_of, size_of},
+    ops::Range,
+    ptr::{read_volatile, write_volatile},
+};
+
+use alloc::vec::Vec;
+use bit_field::BitField;
+
+use super::{
+    arch,
+    interrupt::MASK_ALL,
+    memory::*,
+    paging::{self, CachingMode, PageAttributes, PagingErros},
+};
+use crate::{pmm, sync::spinlock::Spinlock};
+
+//Free parts of the window, sorted by address and never adjacent
+static WINDOW: Spinlock<Option<Vec<VirtRange>>> = Spinlock::new(None, MASK_ALL);
+
+#[derive(Debug)]
+pub enum MmioError {
+    InvalidRange,
+    InvalidCachingMode, //only CachingMode::MMIO and CachingMode::MmioPrefetch are valid for device memory
+    WindowFull,
+    NoKernelRoot,
+    OutOfMemory, //no pages for the page tables of the mapping
+    Paging(PagingErros),
+}
+
+///Value of a register, a register is always accessed with a single access of its size
+pub trait RegisterValue: Copy + BitField {}
+
+impl RegisterValue for u8 {}
+impl RegisterValue for u16 {}
+impl RegisterValue for u32 {}
+impl RegisterValue for u64 {}
+
+#[repr(transparent)]
+pub struct ReadOnly<T: RegisterValue>(UnsafeCell<T>);
+
+#[repr(transparent)]
+pub struct WriteOnly<T: RegisterValue>(UnsafeCell<T>);
+
+#[repr(transparent)]
+pub struct ReadWrite<T: RegisterValue>(UnsafeCell<T>);
+
+impl<T: RegisterValue> ReadOnly<T> {
+    #[inline(always)]
+    pub fn read(&self) -> T {
+        unsafe { read_volatile(self.0.get()) }
+    }
+
+    #[inline(always)]
+    pub fn get_bit(&self, bit: usize) -> bool {
+        self.read().get_bit(bit)
+    }
+
+    #[inline(always)]
+    pub fn get_bits(&self, range: Range<usize>) -> T {
+        self.read().get_bits(range)
+    }
+}
+
+impl<T: RegisterValue> WriteOnly<T> {
+    #[inline(always)]
+    pub fn write(&self, value: T) {
+        unsafe { write_volatile(self.0.get(), value) }
+    }
+}
+
+impl<T: RegisterValue> ReadWrite<T> {
+    #[inline(always)]
+    pub fn read(&self) -> T {
+        unsafe { read_volatile(self.0.get()) }
+    }
+
+    #[inline(always)]
+    pub fn write(&self, value: T) {
+        unsafe { write_volatile(self.0.get(), value) }
+    }
+
+    ///Read, change, write, the register is not locked in between
+    #[inline(always)]
+    pub fn modify(&self, f: impl FnOnce(T) -> T) {
+        self.write(f(self.read()));
+    }
+
+    #[inline(always)]
+    pub fn get_bit(&self, bit: usize) -> bool {
+        self.read().get_bit(bit)
+    }
+
+    #[inline(always)]
+    pub fn get_bits(&self, range: Range<usize>) -> T {
+        self.read().get_bits(range)
+    }
+
+    #[inline(always)]
+    pub fn set_bit(&self, bit: usize, value: bool) {
+        self.modify(|mut register| *register.set_bit(bit, value));
+    }
+
+    #[inline(always)]
+    pub fn set_bits(&self, range: Range<usize>, value: T) {
+        self.modify(|mut register| *register.set_bits(range, value));
+    }
+}
+
+///Mapping of device memory into the MMIO window, unmapped on drop
+pub struct MmioRegion {
+    phys: PhysRange, //as requested, not page aligned
+    virt: VirtRange, //all pages of the mapping
+}
+
+impl MmioRegion {
+    ///Maps the pages that contain >phys< into the MMIO window of the kernel root \
+    ///>caching_mode< has to be CachingMode::MMIO, or CachingMode::MmioPrefetch for memory without read side effects \
+    ///Safety: >phys< has to be device memory, the caller is responsible for all accesses to the device
+    pub unsafe fn map(phys: PhysRange, caching_mode: CachingMode) -> Result<MmioRegion, MmioError> {
+        if phys.is_empty() {
+            return Err(MmioError::InvalidRange);
+        }
+
+        if !matches!(caching_mode, CachingMode::MMIO | CachingMode::MmioPrefetch) {
+            return Err(MmioError::InvalidCachingMode);
+        }
+
+        let pages = phys
+            .align_outward::<Lv1>()
+            .map_err(|_| MmioError::InvalidRange)?;
+        let virt = alloc_window(pages.get_size())?;
+
+        let attributes = PageAttributes {
+            present: true,
+            readonly: false,
+            executable: false,
+            supervisor: true,
+            global: true,
+            protection_key: 0,
+            caching_mode,
+        };
+
+        let mut phys_pages: Vec<PhysLv1PageAddress> = pages.iter_pages::<Lv1>().collect();
+        let virt_start = VirtLv1PageAddress::new_unchecked(virt.get_start());
+
+        let result = paging::with_kernel_page_root(|root| {
+            //the kernel root is locked, so running out of memory must not panic
+            pmm::map_with_pt_pages(
+                paging::needed_pt_pages_lv1(root, virt_start, phys_pages.len() as u64),
+                |pt_pages| {
+                    paging::map_slice_lv1_page(
+                        root,
+                        pt_pages,
+                        &mut phys_pages,
+                        virt_start,
+                        attributes,
+                    )
+                },
+            )
+            .ok_or(MmioError::OutOfMemory)?
+            .map_err(MmioError::Paging)
+        });
+
+        match result {
+            Some(Ok(_)) => Ok(MmioRegion { phys, virt }),
+            Some(Err(error)) => {
+                free_window(virt);
+                Err(error)
+            }
+            None => {
+                free_window(virt);
+                Err(MmioError::NoKernelRoot)
+            }
+        }
+    }
+
+    ///Returns the physical range that was requested
+    #[inline]
+    pub fn get_phys_range(&self) -> PhysRange {
+        self.phys
+    }
+
+    ///Returns the virtual address of the start of the requested physical range
+    #[inline]
+    pub fn get_virt_address(&self) -> VirtAddress {
+        unsafe { VirtAddress::new_unchecked(self.virt.get_start() + self.get_page_offset()) }
+    }
+
+    ///Returns the register block of type >T< that starts >offset< bytes after the start of the requested range \
+    ///Panics if the block is not completely inside of the range or misaligned
+    pub fn get_block<T>(&self, offset: u64) -> &T {
+        let end = offset.checked_add(size_of::<T>() as u64);
+        if end.map_or(true, |end| end > self.phys.get_size()) {
+            panic!(
+                "MMIO ERROR: BLOCK AT {:#x} OUT OF BOUNDS OF {:?}",
+                offset, self.phys
+            );
+        }
+
+        let address = self.get_virt_address().get_u64() + offset;
+        if address % align_of::<T>() as u64 != 0 {
+            panic!("MMIO ERROR: BLOCK AT {:#x} MISALIGNED", offset);
+        }
+
+        unsafe { &*(address as *const T) }
+    }
+
+    #[inline]
+    fn get_page_offset(&self) -> u64 {
+        self.phys.get_start() % Lv1::get_size()
+    }
+}
+
+impl Drop for MmioRegion {
+    fn drop(&mut self) {
+        let virt_start = unsafe { VirtLv1PageAddress::new_unchecked(self.virt.get_start()) };
+
+        let freed_tables = paging::with_kernel_page_root(|root| unsafe {
+            paging::unmap_lv1_page(root, virt_start, self.virt.get_number_of_pages::<Lv1>())
+        });
+
+        match freed_tables {
+            Some(Ok(freed_tables)) => {
+                //all entries of an emptied table are cleared
+                for table in freed_tables {
+                    pmm::free_zeroed_lv1(table);
+                }
+            }
+            _ => panic!("MMIO ERROR: UNMAP OF {:?} FAILED", self.virt),
+        }
+
+        free_window(self.virt);
+    }
+}
+
+//first fit
+fn alloc_window(size: u64) -> Result<VirtRange, MmioError> {
+    let mut guard = unsafe { WINDOW.lock() };
+    let free = guard.get_or_insert_with(|| {
+        let window = VirtRange::with_size(
+            arch::memory::MMIO_WINDOW_START,
+            arch::memory::MMIO_WINDOW_SIZE,
+        )
+        .expect("MMIO ERROR: INVALID WINDOW");
+
+        alloc::vec![window]
+    });
+
+    let index = free
+        .iter()
+        .position(|range| range.get_size() >= size)
+        .ok_or(MmioError::WindowFull)?;
+
+    let range = free[index];
+    let allocated = unsafe { VirtRange::with_size_unchecked(range.get_start(), size) };
+
+    match range.subtract(&allocated) {
+        (_, Some(rest)) => free[index] = rest,
+        _ => {
+            free.remove(index);
+        }
+    }
+
+    Ok(allocated)
+}
+
+fn free_window(range: VirtRange) {
+    let mut guard = unsafe { WINDOW.lock() };
+    let Some(free) = guard.as_mut() else {
+        return;
+    };
+
+    let index = free.partition_point(|free_range| free_range.get_start() < range.get_start());
+    free.insert(index, range);
+
+    //merge with the neighbours
+    if index + 1 < free.len()
+        && let Some(joined) = free[index].join(&free[index + 1])
+    {
+        free[index] = joined;
+        free.remove(index + 1);
+    }
+
+    if index > 0
+        && let Some(joined) = free[index - 1].join(&free[index])
+    {
+        free[index - 1] = joined;
+        free.remove(index);
+    }
+}
@@ -1,11 +1,25 @@
 #[cfg_attr(target_arch = "x86_64", path = "x86_64/mod.rs")]
 mod arch;
 
+pub mod console;
 pub mod cpu;
 pub mod cpuid;
 pub mod interrupt;
-pub mod irqlvmutex;
 pub mod memory;
-///! Wrapper of varying thicknes around the arch module that implements/wraps needed stuff and ensures that no code outside of the hal mod needs to access the arch mod
-///! Intention is that a someone who implements a new arch can see what is missing
+pub mod mmio;
+//Wrapper of varying thicknes around the arch module that implements/wraps needed stuff and ensures that no code outside of the hal mod needs to access the arch mod
+//Intention is that a someone who implements a new arch can see what is missing
 pub mod paging;
+#[cfg(test)]
+pub(crate) mod simulated_memory;
+
+///Called early in OS Boot, before anything but the console is used \
+///No Heap and most other OS Services are not availible
+pub fn init_hal() {
+    arch::init_arch();
+}
+
+///Called when OS is finishing its boot, needs the heap and the kernel root
+pub fn init_hal_finalization() {
+    arch::init_arch_finalization();
+}
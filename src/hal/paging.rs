@@ -1,17 +1,27 @@
-use super::{arch, memory::*};
+use super::{arch, interrupt::MASK_ALL, memory::*};
+use crate::sync::spinlock::Spinlock;
 use alloc::vec::Vec;
 
 pub type PageRoot = arch::paging::ArchPageRoot;
+pub type ProtectionKeyRights = arch::protection_keys::ArchProtectionKeyRights;
 
+pub const PROTECTION_KEY_COUNT: u8 = arch::protection_keys::PROTECTION_KEY_COUNT;
+
+//The kernel half of every root is the kernel half of this root
+static KERNEL_PAGE_ROOT: Spinlock<Option<PageRoot>> = Spinlock::new(None, MASK_ALL);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct PageAttributes {
     pub present: bool, //Indicates to the MMU that it can use the Page
     pub readonly: bool,
     pub executable: bool,
     pub supervisor: bool,
     pub global: bool,
+    pub protection_key: u8, //0 is the default key, has to be smaller than PROTECTION_KEY_COUNT
     pub caching_mode: CachingMode,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CachingMode {
     Default,
     Framebuffer,
@@ -20,13 +30,34 @@ pub enum CachingMode {
     DMA,
 }
 
+///Access to the pages of a protection key, on top of the access granted by the PageAttributes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyAccess {
+    ReadWrite,
+    ReadOnly,
+    None,
+}
+
+#[derive(Debug)]
 pub enum PagingErros {
     PageAlreadyPresent,
     PageAlreadyNotPresent,
     NumberOfPagesOutOfBounds,
     InsufficientPagesForPageTable,
+    InvalidProtectionKey, //not smaller than PROTECTION_KEY_COUNT
+}
+
+///Found by check_page_root, >virt_address< is the first address covered by the entry
+#[derive(Debug)]
+pub enum PageTableIssue {
+    NonCanonical { virt_address: u64 },
+    PresentButInvalid { virt_address: u64, table_level: u8 }, //the MMU uses an entry the software does not know about
+    UserPageUnderSupervisorTable { virt_address: u64 },     //the page is not reachable from user mode
+    WritableAndExecutable { virt_address: u64 },
+    EmptyTable { virt_address: u64, table_level: u8 },       //should have been freed by unmap
 }
 
+#[allow(clippy::enum_variant_names)]
 pub enum Page {
     None,
     Lv1Page(Lv1Page),
@@ -34,6 +65,28 @@ pub enum Page {
     Lv3Page(Lv3Page),
 }
 
+///Accessed and dirty bits of a mapped page
+#[derive(Clone, Copy, Debug)]
+pub struct PageUsage {
+    pub virt_address: VirtAddress, //start of the page
+    pub page_size: u64,
+    pub accessed: bool,
+    pub dirty: bool,
+}
+
+///Page fault of the executing cpu, decoded by the arch and passed to vmm::handle_page_fault
+#[derive(Clone, Copy, Debug)]
+pub struct PageFault {
+    pub address: u64, //not a VirtAddress, accesses to the null page fault as well
+    pub instruction_pointer: u64,
+    pub present: bool, //the page was mapped and the access violated its attributes
+    pub write: bool,
+    pub user: bool, //the access came from user mode
+    pub instruction_fetch: bool,
+    pub protection_key: bool,  //the access was denied by the protection key of the page
+    pub malformed_table: bool, //an entry on the walk had a reserved bit set
+}
+
 pub struct Lv1Page {
     pub attributes: PageAttributes,
     pub phys_address: PhysLv1PageAddress,
@@ -52,19 +105,133 @@ pub struct Lv3Page {
     pub virt_address: VirtLv3PageAddress,
 }
 
+///Loads the root on the executing cpu, cached translations are kept if they are still valid
+#[inline(always)]
+pub unsafe fn activate_page_root(root: &PageRoot) {
+    arch::paging::activate_root(root)
+}
+
+///Has to be called for the previous root after switching to another one \
+///Until then the cpu keeps receiving the TLB shootdowns of the previous root
+#[inline(always)]
+pub fn deactivate_page_root(root: &PageRoot) {
+    arch::paging::deactivate_root(root)
+}
+
+///Sets the root that kernel mappings (for example the MMIO window) are created in \
+///Every root entry of the kernel half is created up front with pages from >phys_pages_for_pt<, \
+///so the entries that other roots copy never change, use needed_pt_pages_kernel_half to get the amount \
+///The used pages are removed from the vec, nothing is changed if an error is returned \
+///Safety: the root has to be built by the kernel, the walkers only understand entries with the valid bit, \
+///so the tables of the bootloader can not be used
+pub unsafe fn set_kernel_page_root(
+    mut root: PageRoot,
+    phys_pages_for_pt: &mut Vec<PhysLv1PageAddress>,
+) -> Result<(), PagingErros> {
+    let mut kernel_root = KERNEL_PAGE_ROOT.lock();
+
+    if kernel_root.is_some() {
+        panic!("PAGING ERROR: KERNEL ROOT ALREADY SET");
+    }
+
+    let used = arch::paging::fill_kernel_half(&mut root, phys_pages_for_pt)?;
+    phys_pages_for_pt.drain(..used);
+
+    *kernel_root = Some(root);
+    Ok(())
+}
+
+#[inline(always)]
+pub fn needed_pt_pages_kernel_half(root: &PageRoot) -> u64 {
+    arch::paging::needed_pt_pages_kernel_half(root)
+}
+
+///Calls >f< with the kernel root locked, None if no kernel root was set yet
+pub fn with_kernel_page_root<R>(f: impl FnOnce(&mut PageRoot) -> R) -> Option<R> {
+    unsafe { KERNEL_PAGE_ROOT.lock() }.as_mut().map(f)
+}
+
+///Hands out the protection keys of an address space, key 0 is the default key and is never handed out
+#[derive(Clone, Copy, Debug)]
+pub struct ProtectionKeyAllocator {
+    allocated: u32, //bit n: key n
+}
+
+impl ProtectionKeyAllocator {
+    pub const fn new() -> ProtectionKeyAllocator {
+        ProtectionKeyAllocator { allocated: 1 }
+    }
+
+    ///None if all keys are in use or the cpu does not support protection keys for user pages
+    pub fn alloc(&mut self) -> Option<u8> {
+        if !arch::protection_keys::is_pku_enabled() {
+            return None;
+        }
+
+        let key = (!self.allocated).trailing_zeros() as u8;
+        if key >= PROTECTION_KEY_COUNT {
+            return None;
+        }
+
+        self.allocated |= 1 << key;
+        Some(key)
+    }
+
+    ///The caller has to ensure that no page uses the key anymore
+    pub fn free(&mut self, key: u8) {
+        if key == 0 || key >= PROTECTION_KEY_COUNT || self.allocated & (1 << key) == 0 {
+            panic!("PAGING ERROR: FREE OF UNALLOCATED PROTECTION KEY {}", key);
+        }
+
+        self.allocated &= !(1 << key);
+    }
+}
+
+#[inline(always)]
+pub fn user_protection_keys_supported() -> bool {
+    arch::protection_keys::is_pku_enabled()
+}
+
+#[inline(always)]
+pub fn supervisor_protection_keys_supported() -> bool {
+    arch::protection_keys::is_pks_enabled()
+}
+
+///Rights of the user pages on the executing cpu, None if not supported \
+///User code can change them without the kernel, so they are part of the thread state and have to be saved on thread switches
+#[inline(always)]
+pub fn get_user_protection_key_rights() -> Option<ProtectionKeyRights> {
+    arch::protection_keys::get_user_rights()
+}
+
+///Changes the rights of the user pages on the executing cpu, does nothing if not supported
+#[inline(always)]
+pub unsafe fn set_user_protection_key_rights(rights: ProtectionKeyRights) {
+    arch::protection_keys::set_user_rights(rights)
+}
+
+///Changes the rights of the supervisor pages on the executing cpu, does nothing if not supported
+#[inline(always)]
+pub unsafe fn set_supervisor_protection_key_rights(rights: ProtectionKeyRights) {
+    arch::protection_keys::set_supervisor_rights(rights)
+}
+
 //these functions dont manage physical pages in any way
 //Thin Wrapper to ensure that all code outside the hal mod never needs to touch the arch mod
 //the slice variant is intended for when dynamic allocation is not availible (for example at boottime) or when the amount of pages is known at compiletime
 //preserves the physical page ordering from the input list (Only for the mapped Pages NOT for the pages needed for the Page Table)
+//pages for new tables are taken from the front of >phys_pages_for_pt<, use needed_pt_pages_* to get the amount
+//the slice variants return how many were taken, the vec variants remove them from the vec
+//nothing is mapped if an error is returned
 
 #[inline(always)]
 pub unsafe fn map_slice_lv1_page(
-    root: PageRoot,
+    root: &mut PageRoot,
     phys_pages_for_pt: &mut [PhysLv1PageAddress],
     phys_pages: &mut [PhysLv1PageAddress],
     virt_start_addr: VirtLv1PageAddress,
     attributes: PageAttributes,
-) -> Result<(), PagingErros> {
+) -> Result<usize, PagingErros> {
     arch::paging::map_slice_lv1_page(
         root,
         phys_pages_for_pt,
@@ -76,12 +243,12 @@ pub unsafe fn map_slice_lv1_page(
 
 #[inline(always)]
 pub unsafe fn map_slice_lv2_page(
-    root: PageRoot,
+    root: &mut PageRoot,
     phys_pages_for_pt: &mut [PhysLv1PageAddress],
     phys_pages: &mut [PhysLv2PageAddress],
     virt_start_addr: VirtLv2PageAddress,
     attributes: PageAttributes,
-) -> Result<(), PagingErros> {
+) -> Result<usize, PagingErros> {
     arch::paging::map_slice_lv2_page(
         root,
         phys_pages_for_pt,
@@ -93,12 +260,12 @@ pub unsafe fn map_slice_lv2_page(
 
 #[inline(always)]
 pub unsafe fn map_slice_lv3_page(
-    root: PageRoot,
+    root: &mut PageRoot,
     phys_pages_for_pt: &mut [PhysLv1PageAddress],
     phys_pages: &mut [PhysLv3PageAddress],
     virt_start_addr: VirtLv3PageAddress,
     attributes: PageAttributes,
-) -> Result<(), PagingErros> {
+) -> Result<usize, PagingErros> {
     arch::paging::map_slice_lv3_page(
         root,
         phys_pages_for_pt,
@@ -110,8 +277,8 @@ pub unsafe fn map_slice_lv3_page(
 
 #[inline(always)]
 pub unsafe fn map_vec_lv1_page(
-    root: PageRoot,
-    phys_pages_for_pt: Vec<PhysLv1PageAddress>,
+    root: &mut PageRoot,
+    phys_pages_for_pt: &mut Vec<PhysLv1PageAddress>,
     phys_pages: Vec<PhysLv1PageAddress>,
     virt_start_addr: VirtLv1PageAddress,
     attributes: PageAttributes,
@@ -127,8 +294,8 @@ pub unsafe fn map_vec_lv1_page(
 
 #[inline(always)]
 pub unsafe fn map_vec_lv2_page(
-    root: PageRoot,
-    phys_pages_for_pt: Vec<PhysLv1PageAddress>,
+    root: &mut PageRoot,
+    phys_pages_for_pt: &mut Vec<PhysLv1PageAddress>,
     phys_pages: Vec<PhysLv2PageAddress>,
     virt_start_addr: VirtLv2PageAddress,
     attributes: PageAttributes,
@@ -144,8 +311,8 @@ pub unsafe fn map_vec_lv2_page(
 
 #[inline(always)]
 pub unsafe fn map_vec_lv3_page(
-    root: PageRoot,
-    phys_pages_for_pt: Vec<PhysLv1PageAddress>,
+    root: &mut PageRoot,
+    phys_pages_for_pt: &mut Vec<PhysLv1PageAddress>,
     phys_pages: Vec<PhysLv3PageAddress>,
     virt_start_addr: VirtLv3PageAddress,
     attributes: PageAttributes,
@@ -159,76 +326,519 @@ pub unsafe fn map_vec_lv3_page(
     )
 }
 
-///Returns a Vec with pt pages that are not needed anymore
+///Returns a Vec with pt pages that are not needed anymore, the unmapped pages themself are not returned
 /// Performs necessary TLB Invalidations
 #[inline(always)]
 pub unsafe fn unmap_lv1_page(
-    root: PageRoot,
+    root: &mut PageRoot,
     start: VirtLv1PageAddress,
     number_of_pages: u64,
 ) -> Result<Vec<PhysLv1PageAddress>, PagingErros> {
     arch::paging::unmap_lv1_page(root, start, number_of_pages)
 }
 
-///Returns a Vec with pt pages that are not needed anymore
+///Returns a Vec with pt pages that are not needed anymore, the unmapped pages themself are not returned
 /// Performs necessary TLB Invalidations
 #[inline(always)]
 pub unsafe fn unmap_lv2_page(
-    root: PageRoot,
+    root: &mut PageRoot,
     start: VirtLv2PageAddress,
     number_of_pages: u64,
-) -> Result<Vec<PhysLv2PageAddress>, PagingErros> {
+) -> Result<Vec<PhysLv1PageAddress>, PagingErros> {
     arch::paging::unmap_lv2_page(root, start, number_of_pages)
 }
 
-///Returns a Vec with pt pages that are not needed anymore
+///Returns a Vec with pt pages that are not needed anymore, the unmapped pages themself are not returned
 /// Performs necessary TLB Invalidations
 #[inline(always)]
 pub unsafe fn unmap_lv3_page(
-    root: PageRoot,
+    root: &mut PageRoot,
     start: VirtLv3PageAddress,
     number_of_pages: u64,
-) -> Result<Vec<PhysLv3PageAddress>, PagingErros> {
+) -> Result<Vec<PhysLv1PageAddress>, PagingErros> {
     arch::paging::unmap_lv3_page(root, start, number_of_pages)
 }
 
 ///Updates the Attributes of the give Range
+///Huge pages that are only partially covered are split, the new tables are taken from the front of >phys_pages_for_pt< \
+///and removed from the vec, use needed_pt_pages_update to get the amount \
+///>merge_pages< merges the uniform lv1 tables of 2MiB regions that lie completely inside the range back into lv2 pages \
+///Returns a Vec with pt pages that are not needed anymore \
+///Nothing is changed if an error is returned, for example if a page of the range is not mapped
 /// Performs necessary TLB Invalidations
 #[inline(always)]
 pub unsafe fn update_page_attributes(
-    root: PageRoot,
-    virt_start_addr: VirtAddress,
-    virt_end_addr: VirtAddress,
+    root: &mut PageRoot,
+    phys_pages_for_pt: &mut Vec<PhysLv1PageAddress>,
+    range: VirtRange,
     attributes: PageAttributes,
-) {
-    arch::paging::update_page_attributes(root, virt_start_addr, virt_end_addr, attributes)
+    merge_pages: bool,
+) -> Result<Vec<PhysLv1PageAddress>, PagingErros> {
+    let (used, freed_tables) = arch::paging::update_page_attributes(
+        root,
+        phys_pages_for_pt,
+        range,
+        attributes,
+        merge_pages,
+    )?;
+
+    phys_pages_for_pt.drain(..used);
+    Ok(freed_tables)
+}
+
+#[inline(always)]
+pub fn needed_pt_pages_update(root: &PageRoot, range: VirtRange) -> u64 {
+    arch::paging::needed_pt_pages_update(root, range)
 }
 
 #[inline(always)]
-pub fn get_single_page(root: PageRoot, virt_start_addr: VirtAddress) -> Option<Page> {
+pub fn get_single_page(root: &PageRoot, virt_start_addr: VirtAddress) -> Option<Page> {
     arch::paging::get_single_page(root, virt_start_addr)
 }
 
 #[inline(always)]
-pub fn get_vec_page(
-    root: PageRoot,
-    virt_start_addr: VirtAddress,
-    virt_end_addr: VirtAddress,
-) -> Vec<Option<Page>> {
-    arch::paging::get_vec_page(root, virt_start_addr, virt_end_addr)
+pub fn get_vec_page(root: &PageRoot, range: VirtRange) -> Vec<Option<Page>> {
+    arch::paging::get_vec_page(root, range)
+}
+
+///Returns the accessed and dirty bits of all mapped pages that overlap the range, optionally clears them \
+///The cleared bits are flushed from the TLBs of all cpus, so the next access to a page sets them again \
+///Used for working set estimation, page reclaim and dirty tracking
+#[inline(always)]
+pub unsafe fn harvest_page_usage(
+    root: &mut PageRoot,
+    range: VirtRange,
+    clear_accessed: bool,
+    clear_dirty: bool,
+) -> Vec<PageUsage> {
+    arch::paging::harvest_page_usage(root, range, clear_accessed, clear_dirty)
+}
+
+///Writes a summary of the mapped regions of the root to the kernel log
+#[inline(always)]
+pub fn dump_page_root(root: &PageRoot) {
+    arch::paging::dump_root(root)
+}
+
+///Walks the whole root and returns every inconsistency found
+#[inline(always)]
+pub fn check_page_root(root: &PageRoot) -> Vec<PageTableIssue> {
+    arch::paging::check_root(root)
 }
 
 #[inline(always)]
-pub fn needed_pt_pages_lv1(root: PageRoot, start: VirtLv1PageAddress, number_of_pages: u64) -> u64 {
+pub fn needed_pt_pages_lv1(root: &PageRoot, start: VirtLv1PageAddress, number_of_pages: u64) -> u64 {
     arch::paging::needed_pt_pages_lv1(root, start, number_of_pages)
 }
 
 #[inline(always)]
-pub fn needed_pt_pages_lv2(root: PageRoot, start: VirtLv2PageAddress, number_of_pages: u64) -> u64 {
+pub fn needed_pt_pages_lv2(root: &PageRoot, start: VirtLv2PageAddress, number_of_pages: u64) -> u64 {
     arch::paging::needed_pt_pages_lv2(root, start, number_of_pages)
 }
 
 #[inline(always)]
-pub fn needed_pt_pages_lv3(root: PageRoot, start: VirtLv3PageAddress, number_of_pages: u64) -> u64 {
+pub fn needed_pt_pages_lv3(root: &PageRoot, start: VirtLv3PageAddress, number_of_pages: u64) -> u64 {
     arch::paging::needed_pt_pages_lv3(root, start, number_of_pages)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, vec::Vec};
+
+    use super::*;
+    use crate::hal::simulated_memory::SimulatedMemory;
+
+    const ARENA_PAGES: u64 = 4096; //16MiB, only the tables live in it
+    const ROUNDS: usize = 3000;
+
+    const LV1_SIZE: u64 = 0x1000;
+    const LV2_SIZE: u64 = 0x20_0000;
+
+    //the pages are spread over several root entries so that every table level is created and freed
+    const REGIONS: [u64; 3] = [0, 1 << 39, 5 << 39];
+    const REGION_PAGES: u64 = 4 * 512 * 512; //4GiB
+
+    const CACHING_MODES: [CachingMode; 5] = [
+        CachingMode::Default,
+        CachingMode::Framebuffer,
+        CachingMode::MMIO,
+        CachingMode::MmioPrefetch,
+        CachingMode::DMA,
+    ];
+
+    //xorshift64*, the sequence is fixed so that a failure can be reproduced
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+        }
+
+        fn below(&mut self, bound: u64) -> u64 {
+            self.next() % bound
+        }
+    }
+
+    //what the page table is expected to contain, keyed by the virtual address of the page
+    #[derive(Default)]
+    struct Model {
+        lv1: BTreeMap<u64, (u64, PageAttributes)>,
+        lv2: BTreeMap<u64, (u64, PageAttributes)>,
+    }
+
+    impl Model {
+        fn is_free(&self, start: u64, last: u64) -> bool {
+            self.lv1.range(start..=last).next().is_none()
+                && self
+                    .lv2
+                    .range(start - start % LV2_SIZE..=last)
+                    .next()
+                    .is_none()
+        }
+
+        fn lookup(&self, address: u64) -> Option<(u64, u64, PageAttributes)> {
+            let lv1 = address - address % LV1_SIZE;
+            let lv2 = address - address % LV2_SIZE;
+
+            match (self.lv1.get(&lv1), self.lv2.get(&lv2)) {
+                (Some((phys, attributes)), _) => Some((LV1_SIZE, *phys, *attributes)),
+                (_, Some((phys, attributes))) => Some((LV2_SIZE, *phys, *attributes)),
+                _ => None,
+            }
+        }
+    }
+
+    fn random_attributes(rng: &mut Rng) -> PageAttributes {
+        let readonly = rng.below(2) == 0;
+
+        PageAttributes {
+            present: rng.below(4) != 0,
+            readonly,
+            executable: readonly && rng.below(2) == 0, //writable and executable is reported by check_page_root
+            supervisor: rng.below(2) == 0,
+            global: false,
+            protection_key: rng.below(PROTECTION_KEY_COUNT as u64) as u8,
+            caching_mode: CACHING_MODES[rng.below(CACHING_MODES.len() as u64) as usize],
+        }
+    }
+
+    fn random_address(rng: &mut Rng, page_size: u64) -> u64 {
+        let region = REGIONS[rng.below(REGIONS.len() as u64) as usize];
+        let page_pages = page_size / LV1_SIZE;
+
+        //the null page is not a valid VirtAddress
+        region + (1 + rng.below(REGION_PAGES / page_pages - 1)) * page_size
+    }
+
+    fn map_lv1(
+        memory: &mut SimulatedMemory,
+        root: &mut PageRoot,
+        model: &mut Model,
+        rng: &mut Rng,
+    ) {
+        let start = random_address(rng, LV1_SIZE);
+        let count = 1 + rng.below(16);
+        let attributes = random_attributes(rng);
+        let phys: Vec<PhysLv1PageAddress> = (0..count)
+            .map(|_| PhysLv1PageAddress::new(rng.below(1 << 28) * LV1_SIZE).unwrap())
+            .collect();
+
+        let virt = VirtLv1PageAddress::new(start).unwrap();
+        let mut pt_pages = memory.alloc_pages(needed_pt_pages_lv1(root, virt, count));
+        let result =
+            unsafe { map_vec_lv1_page(root, &mut pt_pages, phys.clone(), virt, attributes) };
+
+        if model.is_free(start, start + count * LV1_SIZE - 1) {
+            result.unwrap();
+            assert!(
+                pt_pages.is_empty(),
+                "needed_pt_pages_lv1 counted too many tables"
+            );
+
+            for (page, phys) in phys.iter().enumerate() {
+                model.lv1.insert(
+                    start + page as u64 * LV1_SIZE,
+                    (phys.get_address().get_u64(), attributes),
+                );
+            }
+        } else {
+            assert!(matches!(result, Err(PagingErros::PageAlreadyPresent)));
+        }
+
+        for page in pt_pages {
+            memory.free_page(page);
+        }
+    }
+
+    fn map_lv2(
+        memory: &mut SimulatedMemory,
+        root: &mut PageRoot,
+        model: &mut Model,
+        rng: &mut Rng,
+    ) {
+        let start = random_address(rng, LV2_SIZE);
+        let count = 1 + rng.below(2);
+        let attributes = random_attributes(rng);
+        let phys: Vec<PhysLv2PageAddress> = (0..count)
+            .map(|_| PhysLv2PageAddress::new(rng.below(1 << 19) * LV2_SIZE).unwrap())
+            .collect();
+
+        let virt = VirtLv2PageAddress::new(start).unwrap();
+        let mut pt_pages = memory.alloc_pages(needed_pt_pages_lv2(root, virt, count));
+        let result =
+            unsafe { map_vec_lv2_page(root, &mut pt_pages, phys.clone(), virt, attributes) };
+
+        if model.is_free(start, start + count * LV2_SIZE - 1) {
+            result.unwrap();
+            assert!(
+                pt_pages.is_empty(),
+                "needed_pt_pages_lv2 counted too many tables"
+            );
+
+            for (page, phys) in phys.iter().enumerate() {
+                model.lv2.insert(
+                    start + page as u64 * LV2_SIZE,
+                    (phys.get_address().get_u64(), attributes),
+                );
+            }
+        } else {
+            assert!(matches!(result, Err(PagingErros::PageAlreadyPresent)));
+        }
+
+        for page in pt_pages {
+            memory.free_page(page);
+        }
+    }
+
+    //usually starts at a mapped page, sometimes the run reaches past the mapped pages and has to fail
+    fn unmap_lv1(
+        memory: &mut SimulatedMemory,
+        root: &mut PageRoot,
+        model: &mut Model,
+        rng: &mut Rng,
+    ) {
+        let Some(start) = model
+            .lv1
+            .keys()
+            .nth(rng.below(model.lv1.len().max(1) as u64) as usize)
+            .copied()
+        else {
+            return;
+        };
+        let count = 1 + rng.below(8);
+
+        let result =
+            unsafe { unmap_lv1_page(root, VirtLv1PageAddress::new(start).unwrap(), count) };
+
+        if (0..count).all(|page| model.lv1.contains_key(&(start + page * LV1_SIZE))) {
+            for table in result.unwrap() {
+                memory.free_page(table);
+            }
+
+            for page in 0..count {
+                model.lv1.remove(&(start + page * LV1_SIZE));
+            }
+        } else {
+            assert!(matches!(result, Err(PagingErros::PageAlreadyNotPresent)));
+        }
+    }
+
+    fn unmap_lv2(
+        memory: &mut SimulatedMemory,
+        root: &mut PageRoot,
+        model: &mut Model,
+        rng: &mut Rng,
+    ) {
+        let Some(start) = model
+            .lv2
+            .keys()
+            .nth(rng.below(model.lv2.len().max(1) as u64) as usize)
+            .copied()
+        else {
+            return;
+        };
+        let count = 1 + rng.below(2);
+
+        let result =
+            unsafe { unmap_lv2_page(root, VirtLv2PageAddress::new(start).unwrap(), count) };
+
+        if (0..count).all(|page| model.lv2.contains_key(&(start + page * LV2_SIZE))) {
+            for table in result.unwrap() {
+                memory.free_page(table);
+            }
+
+            for page in 0..count {
+                model.lv2.remove(&(start + page * LV2_SIZE));
+            }
+        } else {
+            assert!(matches!(result, Err(PagingErros::PageAlreadyNotPresent)));
+        }
+    }
+
+    fn check_translation(root: &PageRoot, model: &Model, address: u64) {
+        let page = get_single_page(root, VirtAddress::new(address).unwrap());
+
+        match (model.lookup(address), page) {
+            (None, None) => {}
+            (Some((LV1_SIZE, phys, attributes)), Some(Page::Lv1Page(page))) => {
+                assert_eq!(page.phys_address.get_address().get_u64(), phys);
+                assert_eq!(
+                    page.virt_address.get_address().get_u64(),
+                    address - address % LV1_SIZE
+                );
+                assert_eq!(page.attributes, attributes);
+            }
+            (Some((LV2_SIZE, phys, attributes)), Some(Page::Lv2Page(page))) => {
+                assert_eq!(page.phys_address.get_address().get_u64(), phys);
+                assert_eq!(
+                    page.virt_address.get_address().get_u64(),
+                    address - address % LV2_SIZE
+                );
+                assert_eq!(page.attributes, attributes);
+            }
+            (expected, _) => panic!(
+                "translation of {:#x} differs from the model, expected {:?}",
+                address, expected
+            ),
+        }
+    }
+
+    fn run(seed: u64, rounds: usize) {
+        let mut memory = SimulatedMemory::new(ARENA_PAGES);
+        let mut root = unsafe { PageRoot::new(memory.alloc_page()) };
+        let mut model = Model::default();
+        let mut rng = Rng(seed);
+
+        let free_pages = memory.get_free_pages();
+
+        for _ in 0..rounds {
+            match rng.below(8) {
+                0..=2 => map_lv1(&mut memory, &mut root, &mut model, &mut rng),
+                3 => map_lv2(&mut memory, &mut root, &mut model, &mut rng),
+                4..=6 => unmap_lv1(&mut memory, &mut root, &mut model, &mut rng),
+                _ => unmap_lv2(&mut memory, &mut root, &mut model, &mut rng),
+            }
+
+            //random addresses are nearly always unmapped, so the mapped pages are checked as well
+            for _ in 0..4 {
+                check_translation(
+                    &root,
+                    &model,
+                    random_address(&mut rng, LV1_SIZE) + rng.below(LV1_SIZE),
+                );
+            }
+            if let Some(address) = model
+                .lv1
+                .keys()
+                .nth(rng.below(model.lv1.len().max(1) as u64) as usize)
+            {
+                check_translation(&root, &model, address + rng.below(LV1_SIZE));
+            }
+            if let Some(address) = model
+                .lv2
+                .keys()
+                .nth(rng.below(model.lv2.len().max(1) as u64) as usize)
+            {
+                check_translation(&root, &model, address + rng.below(LV2_SIZE));
+            }
+        }
+
+        let issues = check_page_root(&root);
+        assert!(issues.is_empty(), "{:?}", issues);
+
+        //every table has to be returned once the last page below it is unmapped
+        for address in core::mem::take(&mut model.lv1).into_keys() {
+            let tables =
+                unsafe { unmap_lv1_page(&mut root, VirtLv1PageAddress::new(address).unwrap(), 1) }
+                    .unwrap();
+            tables.into_iter().for_each(|table| memory.free_page(table));
+        }
+        for address in core::mem::take(&mut model.lv2).into_keys() {
+            let tables =
+                unsafe { unmap_lv2_page(&mut root, VirtLv2PageAddress::new(address).unwrap(), 1) }
+                    .unwrap();
+            tables.into_iter().for_each(|table| memory.free_page(table));
+        }
+
+        assert_eq!(memory.get_free_pages(), free_pages);
+    }
+
+    #[test]
+    fn map_unmap_and_translate_match_a_model() {
+        for seed in [
+            0x9E37_79B9_7F4A_7C15,
+            0xD1B5_4A32_D192_ED03,
+            0x8CB9_2BA7_2F3D_8DD7,
+        ] {
+            run(seed, ROUNDS);
+        }
+    }
+
+    #[test]
+    fn failed_calls_change_nothing() {
+        let mut memory = SimulatedMemory::new(ARENA_PAGES);
+        let mut root = unsafe { PageRoot::new(memory.alloc_page()) };
+        let mut model = Model::default();
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+
+        let attributes = random_attributes(&mut rng);
+        let start = 0x40_0000;
+        let phys = PhysLv1PageAddress::new(0x1000_0000).unwrap();
+        let virt = VirtLv1PageAddress::new(start + LV1_SIZE).unwrap();
+
+        let mut pt_pages = memory.alloc_pages(needed_pt_pages_lv1(&root, virt, 1));
+        unsafe { map_vec_lv1_page(&mut root, &mut pt_pages, vec![phys], virt, attributes) }
+            .unwrap();
+        model
+            .lv1
+            .insert(start + LV1_SIZE, (phys.get_address().get_u64(), attributes));
+
+        let free_pages = memory.get_free_pages();
+
+        //the second page of the run is already mapped
+        let mut pt_pages = memory.alloc_pages(4);
+        let phys_run = vec![PhysLv1PageAddress::new(0x2000_0000).unwrap(); 3];
+        let result = unsafe {
+            map_vec_lv1_page(
+                &mut root,
+                &mut pt_pages,
+                phys_run,
+                VirtLv1PageAddress::new(start).unwrap(),
+                attributes,
+            )
+        };
+        assert!(matches!(result, Err(PagingErros::PageAlreadyPresent)));
+        assert_eq!(pt_pages.len(), 4);
+        pt_pages.into_iter().for_each(|page| memory.free_page(page));
+
+        //the first page of the run is not mapped
+        let result =
+            unsafe { unmap_lv1_page(&mut root, VirtLv1PageAddress::new(start).unwrap(), 2) };
+        assert!(matches!(result, Err(PagingErros::PageAlreadyNotPresent)));
+
+        //the tables exist already, but the key does not fit into the entry
+        let mut pt_pages = Vec::new();
+        let invalid_key = PageAttributes {
+            protection_key: PROTECTION_KEY_COUNT,
+            ..attributes
+        };
+        let result = unsafe {
+            map_vec_lv1_page(
+                &mut root,
+                &mut pt_pages,
+                vec![phys],
+                VirtLv1PageAddress::new(start).unwrap(),
+                invalid_key,
+            )
+        };
+        assert!(matches!(result, Err(PagingErros::InvalidProtectionKey)));
+
+        assert_eq!(memory.get_free_pages(), free_pages);
+        for address in (start..start + 4 * LV1_SIZE).step_by(LV1_SIZE as usize) {
+            check_translation(&root, &model, address);
+        }
+    }
+}
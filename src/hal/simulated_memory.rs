@@ -0,0 +1,117 @@
+//Simulated physical memory for the unit tests
+//A zeroed, page aligned host allocation stands in for the physical address space starting at 0,
+//the HHDM offset is redirected to it, so the paging code runs unchanged on the host
+//The redirection is global, so only one arena exists at a time, the others wait in SimulatedMemory::new
+
+use std::{
+    alloc::{alloc_zeroed, dealloc, Layout},
+    sync::{Mutex, MutexGuard, Once},
+    vec::Vec,
+};
+
+use super::memory::*;
+
+//4 level paging with the page sizes of x86_64, set once for the whole test binary
+pub(crate) const FOUR_LEVEL: PlatformParameters = PlatformParameters {
+    phys_address_mask: 0xF_FFFF_FFFF_FFFF,
+    virt_address_mask: 0xFFFF_FFFF_FFFF,
+    cannonical_bit: Some(47),
+    lv1_page_mask: 0xFFFF_FFFF_FFFF_F000,
+    lv2_page_mask: 0xFFFF_FFFF_FFE0_0000,
+    lv3_page_mask: 0xFFFF_FFFF_C000_0000,
+};
+
+static INIT: Once = Once::new();
+static ARENA_LOCK: Mutex<()> = Mutex::new(());
+
+const PAGE_SIZE: u64 = 0x1000;
+
+///Sets the FOUR_LEVEL parameters for the address types, every test that creates addresses calls this first
+pub(crate) fn init_platform_parameters() {
+    INIT.call_once(|| set_platform_parameters(&FOUR_LEVEL));
+}
+
+pub(crate) struct SimulatedMemory {
+    base: *mut u8,
+    layout: Layout,
+    free_pages: Vec<PhysLv1PageAddress>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl SimulatedMemory {
+    ///Creates an arena of >number_of_pages< zeroed pages and redirects physical memory to it
+    pub(crate) fn new(number_of_pages: u64) -> SimulatedMemory {
+        init_platform_parameters();
+
+        //a failed test must not block the others
+        let lock = ARENA_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let layout =
+            Layout::from_size_align((number_of_pages * PAGE_SIZE) as usize, PAGE_SIZE as usize)
+                .expect("SIMULATED MEMORY ERROR: INVALID SIZE");
+        let base = unsafe { alloc_zeroed(layout) };
+        if base.is_null() {
+            panic!("SIMULATED MEMORY ERROR: HOST ALLOCATION FAILED");
+        }
+
+        unsafe { set_hhdm_offset_override(base as u64) };
+
+        //page 0 is never handed out, a table at physical address 0 would hide bugs that treat 0 as missing
+        let free_pages = (1..number_of_pages)
+            .rev()
+            .map(|page| {
+                PhysLv1PageAddress::new(page * PAGE_SIZE)
+                    .expect("SIMULATED MEMORY ERROR: ARENA TOO LARGE")
+            })
+            .collect();
+
+        SimulatedMemory {
+            base,
+            layout,
+            free_pages,
+            _lock: lock,
+        }
+    }
+
+    ///Returns a zeroed page
+    pub(crate) fn alloc_page(&mut self) -> PhysLv1PageAddress {
+        let page = self
+            .free_pages
+            .pop()
+            .expect("SIMULATED MEMORY ERROR: ARENA FULL");
+        unsafe { utility::zero_lv1(page) };
+
+        page
+    }
+
+    pub(crate) fn alloc_pages(&mut self, count: u64) -> Vec<PhysLv1PageAddress> {
+        (0..count).map(|_| self.alloc_page()).collect()
+    }
+
+    pub(crate) fn free_page(&mut self, page: PhysLv1PageAddress) {
+        if page.get_address().get_u64() == 0
+            || page.get_address().get_u64() >= self.layout.size() as u64
+            || self.free_pages.contains(&page)
+        {
+            panic!("SIMULATED MEMORY ERROR: INVALID FREE OF {:?}", page);
+        }
+
+        self.free_pages.push(page);
+    }
+
+    #[inline]
+    pub(crate) fn get_free_pages(&self) -> usize {
+        self.free_pages.len()
+    }
+}
+
+impl Drop for SimulatedMemory {
+    fn drop(&mut self) {
+        unsafe {
+            clear_hhdm_offset_override();
+            dealloc(self.base, self.layout);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//Local APIC, only what is needed to send IPIs and to acknowledge interrupts
+//x2APIC is used if the cpu supports it, otherwise the xAPIC registers are mapped through an MmioRegion
+//IPIs are addressed by local APIC ID, cpu::get_apic_id translates a cpu index
+
+use core::{
+    mem::offset_of,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use lazy_static::lazy_static;
+use x86_64::{registers::model_specific::Msr, structures::idt::InterruptStackFrame};
+
+use crate::hal::{
+    memory::PhysRange,
+    mmio::{MmioRegion, ReadWrite, WriteOnly},
+    paging::CachingMode,
+};
+
+use super::cpuid;
+
+const IA32_APIC_BASE: u32 = 0x1B;
+const APIC_BASE_X2APIC_ENABLE: u64 = 1 << 10;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_ADDRESS_MASK: u64 = 0xF_FFFF_FFFF_F000;
+
+//the x2APIC MSR of a register is 0x800 + its offset in the xAPIC page / 16
+const X2APIC_MSR_BASE: u32 = 0x800;
+const X2APIC_MSR_EOI: u32 = X2APIC_MSR_BASE + 0xB0 / 16;
+const X2APIC_MSR_SPURIOUS: u32 = X2APIC_MSR_BASE + 0xF0 / 16;
+const X2APIC_MSR_ICR: u32 = X2APIC_MSR_BASE + 0x300 / 16; //a single 64bit MSR
+
+const XAPIC_PAGE_SIZE: u64 = 0x1000;
+
+const SPURIOUS_APIC_ENABLE: u32 = 1 << 8;
+const ICR_DELIVERY_PENDING_BIT: usize = 12; //xAPIC only
+const ICR_LEVEL_ASSERT: u32 = 1 << 14;
+const ICR_HIGH_DESTINATION_SHIFT: u32 = 24;
+
+///Registers of the xAPIC page that are used, every register is 32bit and 16 byte aligned
+#[repr(C)]
+struct XapicRegisters {
+    _reserved0: [u8; 0xB0],
+    eoi: WriteOnly<u32>,
+    _reserved1: [u8; 0x3C],
+    spurious: ReadWrite<u32>,
+    _reserved2: [u8; 0x20C],
+    icr_low: ReadWrite<u32>,
+    _reserved3: [u8; 0xC],
+    icr_high: ReadWrite<u32>,
+}
+
+const _: () = assert!(offset_of!(XapicRegisters, eoi) == 0xB0);
+const _: () = assert!(offset_of!(XapicRegisters, spurious) == 0xF0);
+const _: () = assert!(offset_of!(XapicRegisters, icr_low) == 0x300);
+const _: () = assert!(offset_of!(XapicRegisters, icr_high) == 0x310);
+
+pub(in crate::hal::arch) const SPURIOUS_VECTOR: u8 = 0xFF;
+
+static X2APIC_ENABLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    //all cpus use the APIC base of the cpu that maps it first
+    static ref XAPIC: MmioRegion = unsafe {
+        let base = Msr::new(IA32_APIC_BASE).read() & APIC_BASE_ADDRESS_MASK;
+        let range = PhysRange::with_size(base, XAPIC_PAGE_SIZE).expect("APIC ERROR: INVALID BASE");
+
+        MmioRegion::map(range, CachingMode::MMIO).expect("APIC ERROR: MAPPING THE xAPIC FAILED")
+    };
+}
+
+///Enables the local APIC of the executing cpu, has to be called on every cpu \
+///Without x2APIC the registers are mapped into the MMIO window, which needs the heap and the kernel root
+pub(in crate::hal::arch) fn init_apic() {
+    let mut apic_base = Msr::new(IA32_APIC_BASE);
+
+    unsafe {
+        let mut value = apic_base.read() | APIC_BASE_ENABLE;
+
+        if cpuid::x2apic_supported() {
+            value |= APIC_BASE_X2APIC_ENABLE;
+            X2APIC_ENABLED.store(true, Ordering::Release);
+        }
+
+        apic_base.write(value);
+    }
+
+    let spurious = SPURIOUS_APIC_ENABLE | SPURIOUS_VECTOR as u32;
+
+    if X2APIC_ENABLED.load(Ordering::Acquire) {
+        unsafe { Msr::new(X2APIC_MSR_SPURIOUS).write(spurious as u64) };
+    } else {
+        get_xapic().spurious.write(spurious);
+    }
+}
+
+///Sends a fixed interrupt with >vector< to the cpu with the local APIC ID >apic_id<
+pub(in crate::hal::arch) unsafe fn send_ipi(apic_id: u32, vector: u8) {
+    let command = ICR_LEVEL_ASSERT | vector as u32;
+
+    if X2APIC_ENABLED.load(Ordering::Acquire) {
+        Msr::new(X2APIC_MSR_ICR).write((apic_id as u64) << 32 | command as u64);
+        return;
+    }
+
+    let xapic = get_xapic();
+
+    //the xAPIC accepts a new command once the previous one was delivered
+    while xapic.icr_low.get_bit(ICR_DELIVERY_PENDING_BIT) {
+        core::hint::spin_loop();
+    }
+
+    xapic.icr_high.write(apic_id << ICR_HIGH_DESTINATION_SHIFT);
+    xapic.icr_low.write(command);
+}
+
+///Has to be called at the end of every interrupt handler of an APIC interrupt, except for the spurious vector
+#[inline]
+pub(in crate::hal::arch) unsafe fn end_of_interrupt() {
+    if X2APIC_ENABLED.load(Ordering::Acquire) {
+        Msr::new(X2APIC_MSR_EOI).write(0);
+    } else {
+        get_xapic().eoi.write(0);
+    }
+}
+
+//spurious interrupts are not acknowledged
+pub(in crate::hal::arch) extern "x86-interrupt" fn spurious_interrupt_handler(_stack_frame: InterruptStackFrame) {}
+
+#[inline]
+fn get_xapic() -> &'static XapicRegisters {
+    XAPIC.get_block(0)
+}
@@ -1 +1,70 @@
-pub fn get_hrng_value() -> u64 {}
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use x86_64::registers::model_specific::Msr;
+
+use super::cpuid;
+
+///Random value of the hardware random number generator
+pub fn get_hrng_value() -> u64 {
+    if !cpuid::rdrand_supported() {
+        panic!("CPU ERROR: RDRAND NOT SUPPORTED");
+    }
+
+    //RDRAND fails if the entropy is exhausted for a moment, the carry flag is cleared then
+    loop {
+        let value: u64;
+        let success: u8;
+        unsafe {
+            asm!("rdrand {}", "setc {}", out(reg) value, out(reg_byte) success, options(nomem, nostack))
+        };
+
+        if success != 0 {
+            return value;
+        }
+    }
+}
+
+//holds the cpu index, RDTSCP returns it in ecx without a VM exit or serialization like CPUID
+const IA32_TSC_AUX: u32 = 0xC000_0103;
+
+//cpu indices are handed out densely in the order in which the cpus are initialized
+pub(in crate::hal) const MAX_CPUS: usize = 256;
+
+static NEXT_CPU_ID: AtomicU32 = AtomicU32::new(0);
+static APIC_IDS: [AtomicU32; MAX_CPUS] = [const { AtomicU32::new(0) }; MAX_CPUS];
+
+///Assigns the executing cpu its index, has to be called on every cpu before anything else uses get_cpu_id
+pub(in crate::hal::arch) fn init_cpu_id() {
+    if !cpuid::rdtscp_supported() {
+        panic!("CPU ERROR: RDTSCP NOT SUPPORTED");
+    }
+
+    let cpu = NEXT_CPU_ID.fetch_add(1, Ordering::Relaxed);
+    if cpu as usize >= MAX_CPUS {
+        panic!("CPU ERROR: MORE THAN {} CPUS", MAX_CPUS);
+    }
+
+    APIC_IDS[cpu as usize].store(cpuid::apic_id(), Ordering::Release);
+
+    unsafe { Msr::new(IA32_TSC_AUX).write(cpu as u64) };
+}
+
+///Index of the executing cpu, smaller than MAX_CPUS
+#[inline]
+pub(in crate::hal) fn get_cpu_id() -> u32 {
+    let cpu: u32;
+    unsafe {
+        asm!("rdtscp", out("ecx") cpu, out("eax") _, out("edx") _, options(nomem, nostack, preserves_flags))
+    };
+
+    cpu
+}
+
+///Local APIC ID of the cpu with the index >cpu<, IPIs are addressed by it
+#[inline]
+pub(in crate::hal::arch) fn get_apic_id(cpu: u32) -> u32 {
+    APIC_IDS[cpu as usize].load(Ordering::Acquire)
+}
@@ -30,3 +30,73 @@ pub fn pcid_supported() -> bool {
         .expect("CPUID ERROR: LEAF 0x01 NOT SUPPORTED")
         .has_pcid()
 }
+
+pub fn invpcid_supported() -> bool {
+    (*CPUID_INSTANCE)
+        .get_extended_feature_info()
+        .is_some_and(|extended_feature_info| extended_feature_info.has_invpcid())
+}
+
+///Initial local APIC ID of the executing cpu
+pub fn initial_apic_id() -> u8 {
+    (*CPUID_INSTANCE)
+        .get_feature_info()
+        .expect("CPUID ERROR: LEAF 0x01 NOT SUPPORTED")
+        .initial_local_apic_id()
+}
+
+///Local APIC ID of the executing cpu, the full 32bit x2APIC ID if the cpu reports one
+pub fn apic_id() -> u32 {
+    (*CPUID_INSTANCE)
+        .get_extended_topology_info()
+        .and_then(|mut levels| levels.next())
+        .map_or(initial_apic_id() as u32, |level| level.x2apic_id())
+}
+
+pub fn rdrand_supported() -> bool {
+    (*CPUID_INSTANCE)
+        .get_feature_info()
+        .expect("CPUID ERROR: LEAF 0x01 NOT SUPPORTED")
+        .has_rdrand()
+}
+
+pub fn rdtscp_supported() -> bool {
+    (*CPUID_INSTANCE)
+        .get_extended_processor_and_feature_identifiers()
+        .is_some_and(|extended_feature_identifiers| extended_feature_identifiers.has_rdtscp())
+}
+
+pub fn x2apic_supported() -> bool {
+    (*CPUID_INSTANCE)
+        .get_feature_info()
+        .expect("CPUID ERROR: LEAF 0x01 NOT SUPPORTED")
+        .has_x2apic()
+}
+
+///Protection keys for user pages
+pub fn pku_supported() -> bool {
+    (*CPUID_INSTANCE)
+        .get_extended_feature_info()
+        .is_some_and(|extended_feature_info| extended_feature_info.has_pku())
+}
+
+///Protection keys for supervisor pages
+pub fn pks_supported() -> bool {
+    //CPUID.(EAX=07H,ECX=0H):ECX[31], not exposed by raw_cpuid
+    (*CPUID_INSTANCE).get_extended_feature_info().is_some()
+        && unsafe { core::arch::x86_64::__cpuid_count(0x07, 0).ecx } & (1 << 31) != 0
+}
+
+///Enhanced REP MOVSB/STOSB, the byte string instructions are the fastest way to fill and copy larger blocks
+pub fn erms_supported() -> bool {
+    (*CPUID_INSTANCE)
+        .get_extended_feature_info()
+        .is_some_and(|extended_feature_info| extended_feature_info.has_rep_movsb_stosb())
+}
+
+///Fast short REP MOVSB, REP MOVSB is fast for short copies as well
+pub fn fsrm_supported() -> bool {
+    //CPUID.(EAX=07H,ECX=0H):EDX[4], not exposed by raw_cpuid
+    (*CPUID_INSTANCE).get_extended_feature_info().is_some()
+        && unsafe { core::arch::x86_64::__cpuid_count(0x07, 0).edx } & (1 << 4) != 0
+}
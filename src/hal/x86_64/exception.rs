@@ -0,0 +1,28 @@
+//Exception handlers, they decode the state of the cpu and hand the exception to the kernel
+
+use core::arch::asm;
+
+use x86_64::structures::idt::{InterruptStackFrame, PageFaultErrorCode};
+
+use crate::{hal::paging::PageFault, vmm};
+
+//the access is retried when the handler returns, vmm::handle_page_fault does not return for faults it can not resolve
+pub(in crate::hal::arch) extern "x86-interrupt" fn page_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    //has to be read before anything else can fault
+    let address: u64;
+    unsafe { asm!("mov {}, cr2", out(reg) address, options(nomem, nostack, preserves_flags)) };
+
+    vmm::handle_page_fault(&PageFault {
+        address,
+        instruction_pointer: stack_frame.instruction_pointer.as_u64(),
+        present: error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION),
+        write: error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE),
+        user: error_code.contains(PageFaultErrorCode::USER_MODE),
+        instruction_fetch: error_code.contains(PageFaultErrorCode::INSTRUCTION_FETCH),
+        protection_key: error_code.contains(PageFaultErrorCode::PROTECTION_KEY),
+        malformed_table: error_code.contains(PageFaultErrorCode::MALFORMED_TABLE),
+    });
+}
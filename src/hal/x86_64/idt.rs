@@ -1 +1,19 @@
+use lazy_static::lazy_static;
+use x86_64::structures::idt::InterruptDescriptorTable;
 
+use super::{apic, exception, shootdown};
+
+lazy_static! {
+    static ref IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.page_fault.set_handler_fn(exception::page_fault_handler);
+        idt[shootdown::SHOOTDOWN_VECTOR].set_handler_fn(shootdown::shootdown_interrupt_handler);
+        idt[apic::SPURIOUS_VECTOR].set_handler_fn(apic::spurious_interrupt_handler);
+        idt
+    };
+}
+
+///Has to be called on every cpu
+pub(in crate::hal::arch) fn init_idt() {
+    IDT.load();
+}
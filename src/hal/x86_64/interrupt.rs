@@ -7,28 +7,26 @@ use crate::hal::interrupt::IrqLevel;
 #[derive(Clone, Copy)]
 pub struct ArchIrqLevel(u8);
 
-
 pub(in crate::hal) const MASK_ALL: IrqLevel = ArchIrqLevel(15);
 
-
+//CR8 holds the task priority, interrupts with a priority class at or below it are masked
 pub(in crate::hal) unsafe fn set_irq_level(value: IrqLevel) {
-    asm!(
-        " mov cr8, {}",
-        in(reg_byte) value.0,
-    )
+    asm!("mov cr8, {}", in(reg) value.0 as u64, options(nomem, nostack, preserves_flags));
 }
 
 pub(in crate::hal) unsafe fn bump_irq_level(value: IrqLevel) -> IrqLevel {
-    
-    let mut value_new: u8;
-    
-    asm!(
-        " mov {0}, cr8",
-        " movzbq cr8, {1}",
-        out(reg_byte) value_new,
-        in(reg_byte) value.0,
-    );
-
-    ArchIrqLevel(value_new)
-
-}
\ No newline at end of file
+    let current: u64;
+    asm!("mov {}, cr8", out(reg) current, options(nomem, nostack, preserves_flags));
+
+    if current < value.0 as u64 {
+        set_irq_level(value);
+    }
+
+    ArchIrqLevel(current as u8)
+}
+
+///Handles the IPIs that are pending on the executing cpu without waiting for their interrupt
+#[inline]
+pub(in crate::hal) fn service_pending_ipis() {
+    super::shootdown::service_shootdown();
+}
@@ -1,5 +1,6 @@
 use bit_field::BitField;
 use core::arch::asm;
+use lazy_static::lazy_static;
 
 use crate::hal::memory::VirtLv1PageAddress;
 
@@ -25,15 +26,9 @@ pub fn get_max_supported_phy_address_as_bit_mask() -> u64 {
 }
 
 pub fn get_max_supported_virt_address_as_bit_mask() -> u64 {
-    let mut data: u64;
-    asm!(
-        "mov {}, cr4",
-        out(reg) data,
-    );
-
     //57 Bits VAS are used,
     //5 Level Paging
-    if data.get_bit(12) {
+    if is_la57_enabled() {
         return 0x1FF_FFFF_FFFF_FFFFu64;
     }
 
@@ -41,6 +36,28 @@ pub fn get_max_supported_virt_address_as_bit_mask() -> u64 {
     0xFFFF_FFFF_FFFFu64
 }
 
+///CR4.LA57, set by the bootloader if the cpu supports 5 level paging \
+///Can only be changed while paging is disabled, so it is the same for every root on every cpu
+pub fn is_la57_enabled() -> bool {
+    let mut data: u64;
+    unsafe {
+        asm!(
+            "mov {}, cr4",
+            out(reg) data,
+        );
+    }
+
+    data.get_bit(12)
+}
+
+//PML4 entry 510 (inside the last PML5 entry with LA57), the bootloader uses neither it for the HHDM nor for the kernel image
+pub const MMIO_WINDOW_START: u64 = 0xFFFF_FF00_0000_0000;
+pub const MMIO_WINDOW_SIZE: u64 = 0x80_0000_0000; //512G
+
+//PML4 entry 509, the kernel segments of the vmm are placed here
+pub const SEGMENT_WINDOW_START: u64 = 0xFFFF_FE80_0000_0000;
+pub const SEGMENT_WINDOW_SIZE: u64 = 0x80_0000_0000; //512G
+
 pub fn get_cannonical_bit_number() -> Option<u8> {
     //5 Level Paging
     if get_max_supported_virt_address_as_bit_mask() == 0x1FF_FFFF_FFFF_FFFFu64 {
@@ -50,5 +67,105 @@ pub fn get_cannonical_bit_number() -> Option<u8> {
     Some(47)
 }
 
+lazy_static! {
+    static ref ERMS_SUPPORTED: bool = cpuid::erms_supported();
+    static ref FSRM_SUPPORTED: bool = cpuid::fsrm_supported();
+}
+
+//The string instructions count up as the direction flag is always clear in Rust code
+
+///Fills >size< bytes at >destination< with >value<
+pub unsafe fn memset(destination: *mut u8, value: u8, size: usize) {
+    if *ERMS_SUPPORTED {
+        asm!(
+            "rep stosb",
+            inout("rcx") size => _,
+            inout("rdi") destination => _,
+            in("al") value,
+            options(nostack, preserves_flags),
+        );
+        return;
+    }
+
+    let tail: *mut u8;
+    asm!(
+        "rep stosq",
+        inout("rcx") size / 8 => _,
+        inout("rdi") destination => tail,
+        in("rax") u64::from_ne_bytes([value; 8]),
+        options(nostack, preserves_flags),
+    );
+    asm!(
+        "rep stosb",
+        inout("rcx") size % 8 => _,
+        inout("rdi") tail => _,
+        in("al") value,
+        options(nostack, preserves_flags),
+    );
+}
+
+///Copies >size< bytes from >source< to >destination<, the two must not overlap
+pub unsafe fn memcpy(destination: *mut u8, source: *const u8, size: usize) {
+    //FSRM covers the short copies ERMS is slow at
+    if *ERMS_SUPPORTED || *FSRM_SUPPORTED {
+        asm!(
+            "rep movsb",
+            inout("rcx") size => _,
+            inout("rdi") destination => _,
+            inout("rsi") source => _,
+            options(nostack, preserves_flags),
+        );
+        return;
+    }
+
+    asm!(
+        "rep movsq",
+        "mov rcx, {tail}",
+        "rep movsb",
+        tail = in(reg) size % 8,
+        inout("rcx") size / 8 => _,
+        inout("rdi") destination => _,
+        inout("rsi") source => _,
+        options(nostack, preserves_flags),
+    );
+}
+
+///Zeroes >size< bytes at >destination<, >destination< and >size< have to be 8 byte aligned
+pub unsafe fn zero_pages(destination: *mut u8, size: usize) {
+    asm!(
+        "rep stosq",
+        inout("rcx") size / 8 => _,
+        inout("rdi") destination => _,
+        in("rax") 0u64,
+        options(nostack, preserves_flags),
+    );
+}
+
+///Zeroes >size< bytes at >destination< without pulling the lines into the cache \
+///>destination< and >size< have to be 32 byte aligned and >size< must not be 0
+pub unsafe fn zero_pages_nontemporal(destination: *mut u8, size: usize) {
+    asm!(
+        "2:",
+        "movnti [rdi], rax",
+        "movnti [rdi + 8], rax",
+        "movnti [rdi + 16], rax",
+        "movnti [rdi + 24], rax",
+        "add rdi, 32",
+        "sub rcx, 32",
+        "jnz 2b",
+        //the stores are weakly ordered, later stores must not pass them
+        "sfence",
+        inout("rcx") size => _,
+        inout("rdi") destination => _,
+        in("rax") 0u64,
+        options(nostack),
+    );
+}
+
 //used as the HHDM Base if KASLR is not used
-pub fn get_lowest_higher_half_address() -> VirtLv1PageAddress {}
+pub fn get_lowest_higher_half_address() -> VirtLv1PageAddress {
+    //the sign extension of the highest implemented bit
+    unsafe {
+        VirtLv1PageAddress::new_unchecked(!(get_max_supported_virt_address_as_bit_mask() >> 1))
+    }
+}
@@ -1,21 +1,32 @@
 use crate::hal::arch::paging::init_paging;
 
+mod apic;
 pub(in crate::hal) mod cpu;
 mod cpuid;
+mod exception;
 mod gdt;
 mod idt;
+pub(in crate::hal) mod interrupt;
 pub(in crate::hal) mod memory;
 pub(in crate::hal) mod paging;
+mod pcid;
+pub(in crate::hal) mod protection_keys;
+pub(in crate::hal) mod serial;
+mod shootdown;
 
 ///Called early in OS Boot
 ///No Heap and most other OS Services are not availible
 pub fn init_arch() {
-    compile_error!("TODO");
+    //every lock reads the cpu index, so it is assigned first
+    cpu::init_cpu_id();
     init_paging();
+    idt::init_idt();
 }
 
 ///Called when OS is finishing its boot
 ///Nearly all OS-Services are availible
 pub fn init_arch_finalization() {
-    compile_error!("TODO");
+    //the xAPIC registers are mapped into the MMIO window
+    apic::init_apic();
+    shootdown::init_shootdown();
 }
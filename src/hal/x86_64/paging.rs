@@ -1,8 +1,25 @@
 use crate::hal::paging::PageAttributes;
 use alloc::vec::Vec;
+use bit_field::BitField;
+use core::arch::asm;
+use x86_64::{instructions::tlb, registers::model_specific::Msr};
 
 use crate::hal::{memory::*, paging::*};
 
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use lazy_static::lazy_static;
+
+use super::{
+    cpu::get_cpu_id,
+    cpuid,
+    pcid,
+    protection_keys,
+    shootdown::{self, CpuSet, ShootdownBatch},
+};
+mod debug;
+
+pub(in crate::hal) use debug::{check_root, dump_root};
+
 pub const PML5ENTRYINDEXMASK: u64 = 0x1FF_0000_0000_0000;
 pub const PML4ENTRYINDEXMASK: u64 = 0xFF80_0000_0000;
 pub const PML3ENTRYINDEXMASK: u64 = 0x7F_C000_0000;
@@ -27,145 +44,407 @@ pub const DEFAULTPHYSADDRESSMASK: u64 = 0xF_FFFF_FFFF_F000;
 pub const LV2PHYSADDRESSMASK: u64 = 0xF_FFFF_FFE0_0000; //Used for the PML2 on 2MB Pages
 pub const LV3PHYSADDRESSMASK: u64 = 0xF_FFFF_C000_0000; //Used for the PML3 on 1GB Pages
 
+//PAT layout, entry n is selected by (PAT << 2) | (PCD << 1) | PWT
+//0: WB, 1: WT, 2: UC-, 3: UC, 4: WC, 5: WP, 6: UC-, 7: UC
+const IA32_PAT: u32 = 0x277;
+const PAT_UC: u64 = 0x00;
+const PAT_WC: u64 = 0x01;
+const PAT_WT: u64 = 0x04;
+const PAT_WP: u64 = 0x05;
+const PAT_WB: u64 = 0x06;
+const PAT_UC_MINUS: u64 = 0x07;
+const PAT_LAYOUT: [u64; 8] = [PAT_WB, PAT_WT, PAT_UC_MINUS, PAT_UC, PAT_WC, PAT_WP, PAT_UC_MINUS, PAT_UC];
+
+//accessed and dirty, at the same position in the entries of all levels
+const USAGE_BITS_MASK: u64 = 1 << ACCESSED_BIT | 1 << DIRTY_BIT;
+
+const CR3_NO_FLUSH: u64 = 1 << 63;
+//without CR4.PCIDE bits 3 and 4 of CR3 are PWT and PCD, the rest of the low 12 bits is the PCID once it is set
+const CR3_PCID_MASK: u64 = 0xFFF & !(1 << 3 | 1 << 4);
+const CR4_PCIDE: u64 = 1 << 17;
+
+static PCID_ENABLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref INVPCID_SUPPORTED: bool = cpuid::invpcid_supported();
+}
+
+///Has to be called on every cpu before it uses the kernel page tables
 pub(super) fn init_paging() {
-    compile_error!("TODO: SET PAT MSR");
+    init_pat();
+    init_pcid();
+    protection_keys::init_protection_keys();
+}
+
+fn init_pcid() {
+    if !cpuid::pcid_supported() {
+        return;
+    }
+
+    unsafe {
+        //CR4.PCIDE can only be set while the current PCID is 0
+        let cr3: u64;
+        asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags));
+        asm!("mov cr3, {}", in(reg) cr3 & !CR3_PCID_MASK, options(nostack, preserves_flags));
+
+        let cr4: u64;
+        asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags));
+        asm!("mov cr4, {}", in(reg) cr4 | CR4_PCIDE, options(nostack, preserves_flags));
+    }
+
+    PCID_ENABLED.store(true, Ordering::Release);
+}
+
+fn init_pat() {
+    let value = PAT_LAYOUT
+        .iter()
+        .enumerate()
+        .fold(0u64, |value, (entry, memory_type)| value | (memory_type << (entry * 8)));
+
+    unsafe {
+        //cached lines and TLB entries may still use the old memory types
+        asm!("wbinvd", options(nostack, preserves_flags));
+        Msr::new(IA32_PAT).write(value);
+        asm!("wbinvd", options(nostack, preserves_flags));
+    }
+
+    tlb::flush_all();
 }
 
 pub(in crate::hal) unsafe fn map_slice_lv1_page(
-    root: PageRoot,
+    root: &mut PageRoot,
     phys_pages_for_pt: &mut [PhysLv1PageAddress],
     phys_pages: &mut [PhysLv1PageAddress],
     virt_start_addr: VirtLv1PageAddress,
     attributes: PageAttributes,
-) -> Result<(), PagingErros> {
-    compile_error!("TODO")
+) -> Result<usize, PagingErros> {
+    check_protection_key(attributes)?;
+
+    map_pages(
+        root,
+        phys_pages_for_pt,
+        phys_pages.len() as u64,
+        virt_start_addr.get_address().get_u64(),
+        1,
+        |table, index, page| pml1::write_page(table, index, attributes, phys_pages[page]),
+    )
 }
 
 pub(in crate::hal) unsafe fn map_slice_lv2_page(
-    root: PageRoot,
+    root: &mut PageRoot,
     phys_pages_for_pt: &mut [PhysLv1PageAddress],
     phys_pages: &mut [PhysLv2PageAddress],
     virt_start_addr: VirtLv2PageAddress,
     attributes: PageAttributes,
-) -> Result<(), PagingErros> {
-    compile_error!("TODO")
+) -> Result<usize, PagingErros> {
+    check_protection_key(attributes)?;
+
+    map_pages(
+        root,
+        phys_pages_for_pt,
+        phys_pages.len() as u64,
+        virt_start_addr.get_address().get_u64(),
+        2,
+        |table, index, page| pml2::write_page_2mb(table, index, attributes, phys_pages[page]),
+    )
 }
 
 pub(in crate::hal) unsafe fn map_slice_lv3_page(
-    root: PageRoot,
+    root: &mut PageRoot,
     phys_pages_for_pt: &mut [PhysLv1PageAddress],
     phys_page: &mut [PhysLv3PageAddress],
     virt_start_addr: VirtLv3PageAddress,
     attributes: PageAttributes,
-) -> Result<(), PagingErros> {
-    compile_error!("TODO")
+) -> Result<usize, PagingErros> {
+    check_protection_key(attributes)?;
+
+    map_pages(
+        root,
+        phys_pages_for_pt,
+        phys_page.len() as u64,
+        virt_start_addr.get_address().get_u64(),
+        3,
+        |table, index, page| pml3::write_page_1g(table, index, attributes, phys_page[page]),
+    )
 }
 
 pub(in crate::hal) unsafe fn map_vec_lv1_page(
-    root: PageRoot,
-    phys_pages_for_pt: Vec<PhysLv1PageAddress>,
-    phys_pages: Vec<PhysLv1PageAddress>,
+    root: &mut PageRoot,
+    phys_pages_for_pt: &mut Vec<PhysLv1PageAddress>,
+    mut phys_pages: Vec<PhysLv1PageAddress>,
     virt_start_addr: VirtLv1PageAddress,
     attributes: PageAttributes,
 ) -> Result<(), PagingErros> {
-    compile_error!("TODO")
+    let used = map_slice_lv1_page(root, phys_pages_for_pt, &mut phys_pages, virt_start_addr, attributes)?;
+    phys_pages_for_pt.drain(..used);
+    Ok(())
 }
 
 pub(in crate::hal) unsafe fn map_vec_lv2_page(
-    root: PageRoot,
-    phys_pages_for_pt: Vec<PhysLv1PageAddress>,
-    phys_pages: Vec<PhysLv2PageAddress>,
+    root: &mut PageRoot,
+    phys_pages_for_pt: &mut Vec<PhysLv1PageAddress>,
+    mut phys_pages: Vec<PhysLv2PageAddress>,
     virt_start_addr: VirtLv2PageAddress,
     attributes: PageAttributes,
 ) -> Result<(), PagingErros> {
-    compile_error!("TODO")
+    let used = map_slice_lv2_page(root, phys_pages_for_pt, &mut phys_pages, virt_start_addr, attributes)?;
+    phys_pages_for_pt.drain(..used);
+    Ok(())
 }
 
 pub(in crate::hal) unsafe fn map_vec_lv3_page(
-    root: PageRoot,
-    phys_pages_for_pt: Vec<PhysLv1PageAddress>,
-    phys_pages: Vec<PhysLv3PageAddress>,
+    root: &mut PageRoot,
+    phys_pages_for_pt: &mut Vec<PhysLv1PageAddress>,
+    mut phys_pages: Vec<PhysLv3PageAddress>,
     virt_start_addr: VirtLv3PageAddress,
     attributes: PageAttributes,
 ) -> Result<(), PagingErros> {
-    compile_error!("TODO")
+    let used = map_slice_lv3_page(root, phys_pages_for_pt, &mut phys_pages, virt_start_addr, attributes)?;
+    phys_pages_for_pt.drain(..used);
+    Ok(())
 }
 
 /// Performs necessary TLB Invalidations
 pub(in crate::hal) unsafe fn unmap_lv1_page(
-    root: PageRoot,
+    root: &mut PageRoot,
     start: VirtLv1PageAddress,
     number_of_pages: u64,
 ) -> Result<Vec<PhysLv1PageAddress>, PagingErros> {
-    compile_error!("TODO")
+    unmap_pages(root, start.get_address().get_u64(), number_of_pages, 1)
 }
 
 /// Performs necessary TLB Invalidations
 pub(in crate::hal) unsafe fn unmap_lv2_page(
-    root: PageRoot,
+    root: &mut PageRoot,
     start: VirtLv2PageAddress,
     number_of_pages: u64,
-) -> Result<Vec<PhysLv2PageAddress>, PagingErros> {
-    compile_error!("TODO")
+) -> Result<Vec<PhysLv1PageAddress>, PagingErros> {
+    unmap_pages(root, start.get_address().get_u64(), number_of_pages, 2)
 }
 
 /// Performs necessary TLB Invalidations
 pub(in crate::hal) unsafe fn unmap_lv3_page(
-    root: PageRoot,
+    root: &mut PageRoot,
     start: VirtLv3PageAddress,
     number_of_pages: u64,
-) -> Result<Vec<PhysLv3PageAddress>, PagingErros> {
-    compile_error!("TODO")
+) -> Result<Vec<PhysLv1PageAddress>, PagingErros> {
+    unmap_pages(root, start.get_address().get_u64(), number_of_pages, 3)
 }
 
-///Updates the Attributes of the give Range
+///Updates the Attributes of the give Range \
+///Returns the number of pages used from >phys_pages_for_pt< and the tables that are no longer part of the page table
 /// Performs necessary TLB Invalidations
 pub(in crate::hal) unsafe fn update_page_attributes(
-    root: PageRoot,
-    virt_start_addr: VirtAddress,
-    virt_end_addr: VirtAddress,
+    root: &mut PageRoot,
+    phys_pages_for_pt: &[PhysLv1PageAddress],
+    range: VirtRange,
     attributes: PageAttributes,
-) {
-    compile_error!("TODO")
+    merge_pages: bool,
+) -> Result<(usize, Vec<PhysLv1PageAddress>), PagingErros> {
+    check_protection_key(attributes)?;
+
+    let mut freed_tables: Vec<PhysLv1PageAddress> = Vec::new();
+
+    let Some(last) = range.get_last() else {
+        return Ok((0, freed_tables));
+    };
+
+    let page_size = get_entry_size(1);
+    let start = range.get_start() - range.get_start() % page_size;
+
+    //the whole range has to be mapped, nothing is changed otherwise
+    let mut address = start;
+    loop {
+        let Some((_, level)) = find_leaf(root, address) else {
+            return Err(PagingErros::PageAlreadyNotPresent);
+        };
+
+        match next_page(address, get_entry_size(level)) {
+            Some(next) if next <= last => address = next,
+            _ => break,
+        }
+    }
+
+    //only the pages at the ends of the range can be partially covered
+    let split_addresses = [Some(start), next_page(last, page_size)];
+
+    if count_split_tables(root, &split_addresses) > phys_pages_for_pt.len() as u64 {
+        return Err(PagingErros::InsufficientPagesForPageTable);
+    }
+
+    let mut pt_pages = PtPages {
+        pages: phys_pages_for_pt,
+        used: 0,
+    };
+    let mut batch = ShootdownBatch::new();
+
+    //can not fail anymore, the pages were counted
+    for address in split_addresses.into_iter().flatten() {
+        split_pages_at(root, &mut batch, &mut pt_pages, address)?;
+    }
+
+    let mut address = start;
+    loop {
+        let (table, level) = find_leaf(root, address).ok_or(PagingErros::PageAlreadyNotPresent)?;
+        let index = get_table_index(address, level);
+
+        if let Some((_, phys_address)) = read_leaf(table, level, index) {
+            write_leaf(table, level, index, attributes, phys_address);
+            invalidate_page(root, &mut batch, address);
+        }
+
+        match next_page(address, get_entry_size(level)) {
+            Some(next) if next <= last => address = next,
+            _ => break,
+        }
+    }
+
+    if merge_pages && *LV2_PAGE_SUPPORTED {
+        let table_size = get_entry_size(2);
+
+        //only regions that lie completely inside of the range, the pages around the range keep their level
+        let mut region = start.checked_next_multiple_of(table_size);
+
+        while let Some(address) = region
+            && let Some(region_last) = address.checked_add(table_size - 1)
+            && region_last <= last
+        {
+            if let Some(table) = merge_table(root, &mut batch, address) {
+                freed_tables.push(table);
+            }
+
+            region = region_last.checked_add(1);
+        }
+    }
+
+    //other cpus could walk the merged tables until the shootdown is done
+    finish_invalidation(root, batch);
+    Ok((pt_pages.used, freed_tables))
+}
+
+pub(in crate::hal) fn needed_pt_pages_update(root: &PageRoot, range: VirtRange) -> u64 {
+    let Some(last) = range.get_last() else {
+        return 0;
+    };
+
+    let page_size = get_entry_size(1);
+    count_split_tables(
+        root,
+        &[Some(range.get_start() - range.get_start() % page_size), next_page(last, page_size)],
+    )
 }
 
 pub(in crate::hal) fn get_single_page(
-    root: PageRoot,
+    root: &PageRoot,
     virt_start_addr: VirtAddress,
 ) -> Option<Page> {
-    compile_error!("TODO")
+    lookup_page(root, virt_start_addr.get_u64())
 }
 
-pub(in crate::hal) fn get_vec_page(
-    root: PageRoot,
-    virt_start_addr: VirtAddress,
-    virt_end_addr: VirtAddress,
-) -> Vec<Option<Page>> {
-    compile_error!("TODO")
+///Consecutive unmapped addresses are reported as a single None
+pub(in crate::hal) fn get_vec_page(root: &PageRoot, range: VirtRange) -> Vec<Option<Page>> {
+    let mut pages: Vec<Option<Page>> = Vec::new();
+    let mut address = range.get_start();
+
+    while range.contains_address(address) {
+        let page = lookup_page(root, address);
+
+        let page_size = match &page {
+            Some(Page::Lv3Page(_)) => get_entry_size(3),
+            Some(Page::Lv2Page(_)) => get_entry_size(2),
+            Some(_) => get_entry_size(1),
+            None => get_unmapped_size(root, address),
+        };
+
+        if page.is_some() || !matches!(pages.last(), Some(None)) {
+            pages.push(page);
+        }
+
+        match (address - address % page_size).checked_add(page_size) {
+            Some(next) => address = next,
+            None => break,
+        }
+    }
+
+    pages
+}
+
+///Reports the accessed and dirty bits of every mapped page in the range, unmapped addresses are skipped \
+///The cleared bits are invalidated in the TLBs, so the next access sets them again
+pub(in crate::hal) unsafe fn harvest_page_usage(
+    root: &mut PageRoot,
+    range: VirtRange,
+    clear_accessed: bool,
+    clear_dirty: bool,
+) -> Vec<PageUsage> {
+    let mut usage: Vec<PageUsage> = Vec::new();
+    let mut batch = ShootdownBatch::new();
+    let mut clear_mask: u64 = 0;
+    clear_mask.set_bit(ACCESSED_BIT, clear_accessed);
+    clear_mask.set_bit(DIRTY_BIT, clear_dirty);
+
+    let mut address = range.get_start();
+
+    while range.contains_address(address) {
+        let Some((table, level)) = find_leaf(root, address) else {
+            match next_page(address, get_unmapped_size(root, address)) {
+                Some(next) => address = next,
+                None => break,
+            }
+            continue;
+        };
+
+        let page_size = get_entry_size(level);
+        let page_address = address - address % page_size;
+        let index = get_table_index(address, level);
+
+        if let Some((accessed, dirty)) = read_leaf_flags(table, level, index) {
+            usage.push(PageUsage {
+                virt_address: VirtAddress::new_unchecked(page_address),
+                page_size,
+                accessed,
+                dirty,
+            });
+
+            //untouched pages have no TLB entry that could hide a later access
+            if (accessed && clear_accessed) || (dirty && clear_dirty) {
+                clear_entry_bits(table, index, clear_mask);
+                invalidate_page(root, &mut batch, page_address);
+            }
+        }
+
+        match next_page(address, page_size) {
+            Some(next) => address = next,
+            None => break,
+        }
+    }
+
+    finish_invalidation(root, batch);
+    usage
 }
 
 pub(in crate::hal) fn needed_pt_pages_lv1(
-    root: PageRoot,
+    root: &PageRoot,
     start: VirtLv1PageAddress,
     number_of_pages: u64,
 ) -> u64 {
-    compile_error!("TODO")
+    needed_pt_pages(root, start.get_address().get_u64(), number_of_pages, 1)
 }
 
 pub(in crate::hal) fn needed_pt_pages_lv2(
-    root: PageRoot,
+    root: &PageRoot,
     start: VirtLv2PageAddress,
     number_of_pages: u64,
 ) -> u64 {
-    compile_error!("TODO")
+    needed_pt_pages(root, start.get_address().get_u64(), number_of_pages, 2)
 }
 
 pub(in crate::hal) fn needed_pt_pages_lv3(
-    root: PageRoot,
+    root: &PageRoot,
     start: VirtLv3PageAddress,
     number_of_pages: u64,
 ) -> u64 {
-    compile_error!("TODO")
+    needed_pt_pages(root, start.get_address().get_u64(), number_of_pages, 3)
 }
 
 ///Return (PML5, PML4, PML3, PML2, PML1)
@@ -188,17 +467,780 @@ fn decode_virtual_address(
     )
 }
 
-fn check_virt_space_is_free(start: VirtLv1PageAddress, number_of_pages: u64) -> bool {
-    compile_error!("TODO");
+fn check_virt_space_is_free(root: &PageRoot, start: VirtLv1PageAddress, number_of_pages: u64) -> bool {
+    let Ok(range) = get_page_range(start.get_address().get_u64(), number_of_pages, get_entry_size(1)) else {
+        return false;
+    };
+
+    match range.get_last() {
+        Some(last) => is_range_free(root.address, root.get_root_level(), 1, range.get_start(), last),
+        None => true,
+    }
+}
+
+//Table walkers
+//Table levels: 5 = PML5 (root with LA57), 4 = PML4 (root otherwise), 3 = PML3, 2 = PML2, 1 = PML1
+//The entries of the level n table map lvn pages
+
+const ENTRIES_PER_TABLE: u16 = 512;
+
+//Result of looking up the table that follows an entry
+enum TableLookup {
+    Table(PhysLv1PageAddress),
+    Missing,
+    HugePage, //the entry maps a page instead of pointing to a table
+}
+
+//Hands out the pages for new tables from the front of the list
+struct PtPages<'a> {
+    pages: &'a [PhysLv1PageAddress],
+    used: usize,
+}
+
+impl PtPages<'_> {
+    fn take(&mut self) -> Result<PhysLv1PageAddress, PagingErros> {
+        let page = *self
+            .pages
+            .get(self.used)
+            .ok_or(PagingErros::InsufficientPagesForPageTable)?;
+
+        self.used += 1;
+        Ok(page)
+    }
+}
+
+///Bytes mapped by a single entry of a level >table_level< table
+#[inline]
+fn get_entry_size(table_level: u8) -> u64 {
+    1 << (12 + 9 * (table_level as u64 - 1))
+}
+
+#[inline]
+fn get_table_index(address: u64, table_level: u8) -> u16 {
+    ((address >> (12 + 9 * (table_level as u64 - 1))) & 0x1FF) as u16
+}
+
+///Returns the last address of the range, errors if the range leaves its half of the address space
+//both ends have to be cannonical and on the same side of the hole
+fn get_page_range(start: u64, number_of_pages: u64, page_size: u64) -> Result<VirtRange, PagingErros> {
+    number_of_pages
+        .checked_mul(page_size)
+        .and_then(|size| VirtRange::with_size(start, size).ok())
+        .ok_or(PagingErros::NumberOfPagesOutOfBounds)
+}
+
+#[inline]
+fn read_entry(table: PhysLv1PageAddress, index: u16) -> u64 {
+    unsafe {
+        table
+            .get_address()
+            .offset_unchecked::<u64>(index.into())
+            .read_unchecked::<u64>()
+    }
+}
+
+#[inline]
+fn is_entry_valid(table: PhysLv1PageAddress, index: u16) -> bool {
+    read_entry(table, index).get_bit(VALID_BIT)
+}
+
+fn is_table_empty(table: PhysLv1PageAddress) -> bool {
+    (0..ENTRIES_PER_TABLE).all(|index| !is_entry_valid(table, index))
+}
+
+fn read_table_entry(table: PhysLv1PageAddress, table_level: u8, index: u16) -> TableLookup {
+    match table_level {
+        4 | 5 => match pml4_5::read_page(table, index) {
+            Some(next) => TableLookup::Table(next),
+            None => TableLookup::Missing,
+        },
+        3 => match pml3::read_page(table, index) {
+            Some(pml3::Pml2Or1G::Pml2(next)) => TableLookup::Table(next),
+            Some(pml3::Pml2Or1G::G1(..)) => TableLookup::HugePage,
+            None => TableLookup::Missing,
+        },
+        2 => match pml2::read_page(table, index) {
+            Some(pml2::Pml1Or2M::Pml1(next)) => TableLookup::Table(next),
+            Some(pml2::Pml1Or2M::MB2(..)) => TableLookup::HugePage,
+            None => TableLookup::Missing,
+        },
+        _ => unreachable!(),
+    }
+}
+
+unsafe fn write_table_entry(
+    table: PhysLv1PageAddress,
+    table_level: u8,
+    index: u16,
+    next: PhysLv1PageAddress,
+) {
+    match table_level {
+        4 | 5 => pml4_5::write_page_pml3_4(table, index, next),
+        3 => pml3::write_page_pml2(table, index, next),
+        2 => pml2::write_page_pml1(table, index, next),
+        _ => unreachable!(),
+    }
+}
+
+unsafe fn clear_entry(table: PhysLv1PageAddress, table_level: u8, index: u16) {
+    match table_level {
+        4 | 5 => pml4_5::unmap_page(table, index),
+        3 => pml3::unmap_page(table, index),
+        2 => pml2::unmap_page(table, index),
+        1 => pml1::unmap_page(table, index),
+        _ => unreachable!(),
+    }
+}
+
+//true if the entry maps a page of the table's level
+fn is_leaf_entry(table: PhysLv1PageAddress, table_level: u8, index: u16) -> bool {
+    match table_level {
+        3 => matches!(pml3::read_page(table, index), Some(pml3::Pml2Or1G::G1(..))),
+        2 => matches!(pml2::read_page(table, index), Some(pml2::Pml1Or2M::MB2(..))),
+        1 => pml1::read_page(table, index).is_some(),
+        _ => false,
+    }
+}
+
+///Walks from the root down to the level >table_level< table that is responsible for >address<
+fn find_table(root: &ArchPageRoot, address: u64, table_level: u8) -> TableLookup {
+    let mut table = root.address;
+
+    for level in (table_level + 1..=root.get_root_level()).rev() {
+        match read_table_entry(table, level, get_table_index(address, level)) {
+            TableLookup::Table(next) => table = next,
+            other => return other,
+        }
+    }
+
+    TableLookup::Table(table)
+}
+
+///Like find_table but creates missing tables with pages from >pt_pages<
+unsafe fn create_table(
+    root: &ArchPageRoot,
+    address: u64,
+    table_level: u8,
+    pt_pages: &mut PtPages,
+) -> Result<PhysLv1PageAddress, PagingErros> {
+    let mut table = root.address;
+
+    for level in (table_level + 1..=root.get_root_level()).rev() {
+        let index = get_table_index(address, level);
+
+        table = match read_table_entry(table, level, index) {
+            TableLookup::Table(next) => next,
+            TableLookup::HugePage => return Err(PagingErros::PageAlreadyPresent),
+            TableLookup::Missing => {
+                let next = pt_pages.take()?;
+
+                utility::zero_lv1(next);
+                write_table_entry(table, level, index, next);
+
+                next
+            }
+        };
+    }
+
+    Ok(table)
+}
+
+//true if no entry of level >leaf_level< or above is valid inside [address, last]
+fn is_range_free(table: PhysLv1PageAddress, table_level: u8, leaf_level: u8, address: u64, last: u64) -> bool {
+    let entry_size = get_entry_size(table_level);
+    let mut address = address;
+
+    loop {
+        let index = get_table_index(address, table_level);
+        let entry_last = (address | (entry_size - 1)).min(last);
+
+        if table_level == leaf_level {
+            if is_entry_valid(table, index) {
+                return false;
+            }
+        } else {
+            match read_table_entry(table, table_level, index) {
+                TableLookup::Missing => {}
+                TableLookup::HugePage => return false,
+                TableLookup::Table(next) => {
+                    if !is_range_free(next, table_level - 1, leaf_level, address, entry_last) {
+                        return false;
+                    }
+                }
+            }
+        }
+
+        if entry_last == last {
+            return true;
+        }
+
+        address = entry_last + 1;
+    }
+}
+
+//counts the tables below >table< (None: the table itself is missing) that are needed to map [address, last]
+fn count_missing_tables(
+    table: Option<PhysLv1PageAddress>,
+    table_level: u8,
+    leaf_level: u8,
+    address: u64,
+    last: u64,
+) -> u64 {
+    if table_level == leaf_level {
+        return 0;
+    }
+
+    let entry_size = get_entry_size(table_level);
+    let mut address = address;
+    let mut count: u64 = 0;
+
+    loop {
+        let entry_last = (address | (entry_size - 1)).min(last);
+
+        let next = match table.map(|table| read_table_entry(table, table_level, get_table_index(address, table_level))) {
+            Some(TableLookup::Table(next)) => Some(next),
+            //mapping fails anyway
+            Some(TableLookup::HugePage) => return count,
+            Some(TableLookup::Missing) | None => None,
+        };
+
+        if next.is_none() {
+            count += 1;
+        }
+
+        count += count_missing_tables(next, table_level - 1, leaf_level, address, entry_last);
+
+        if entry_last == last {
+            return count;
+        }
+
+        address = entry_last + 1;
+    }
+}
+
+///Number of tables needed to create every missing root entry of the kernel half
+pub(in crate::hal) fn needed_pt_pages_kernel_half(root: &ArchPageRoot) -> u64 {
+    (ENTRIES_PER_TABLE / 2..ENTRIES_PER_TABLE)
+        .filter(|index| !is_entry_valid(root.address, *index))
+        .count() as u64
+}
+
+///Creates every missing root entry of the kernel half, afterwards they never change and can be shared by copying them \
+///Returns the number of pages used from >phys_pages_for_pt<, nothing is changed on errors
+pub(in crate::hal) unsafe fn fill_kernel_half(
+    root: &mut ArchPageRoot,
+    phys_pages_for_pt: &[PhysLv1PageAddress],
+) -> Result<usize, PagingErros> {
+    if needed_pt_pages_kernel_half(root) > phys_pages_for_pt.len() as u64 {
+        return Err(PagingErros::InsufficientPagesForPageTable);
+    }
+
+    let mut pt_pages = PtPages {
+        pages: phys_pages_for_pt,
+        used: 0,
+    };
+
+    for index in ENTRIES_PER_TABLE / 2..ENTRIES_PER_TABLE {
+        if is_entry_valid(root.address, index) {
+            continue;
+        }
+
+        let table = pt_pages.take()?;
+
+        utility::zero_lv1(table);
+        write_table_entry(root.address, root.get_root_level(), index, table);
+    }
+
+    Ok(pt_pages.used)
+}
+
+fn needed_pt_pages(root: &ArchPageRoot, start: u64, number_of_pages: u64, leaf_level: u8) -> u64 {
+    let Some(last) = get_page_range(start, number_of_pages, get_entry_size(leaf_level))
+        .ok()
+        .and_then(|range| range.get_last())
+    else {
+        return 0;
+    };
+
+    count_missing_tables(Some(root.address), root.get_root_level(), leaf_level, start, last)
+}
+
+//the key is written into a 4 bit field of the entries
+fn check_protection_key(attributes: PageAttributes) -> Result<(), PagingErros> {
+    if attributes.protection_key >= protection_keys::PROTECTION_KEY_COUNT {
+        return Err(PagingErros::InvalidProtectionKey);
+    }
+
+    Ok(())
+}
+
+///Checks the whole range first so that nothing is changed on errors \
+///Returns the number of pages used from >phys_pages_for_pt<
+unsafe fn map_pages(
+    root: &mut ArchPageRoot,
+    phys_pages_for_pt: &[PhysLv1PageAddress],
+    number_of_pages: u64,
+    start: u64,
+    leaf_level: u8,
+    mut write_leaf: impl FnMut(PhysLv1PageAddress, u16, usize),
+) -> Result<usize, PagingErros> {
+    let page_size = get_entry_size(leaf_level);
+    let Some(last) = get_page_range(start, number_of_pages, page_size)?.get_last() else {
+        return Ok(0);
+    };
+
+    if !is_range_free(root.address, root.get_root_level(), leaf_level, start, last) {
+        return Err(PagingErros::PageAlreadyPresent);
+    }
+
+    if needed_pt_pages(root, start, number_of_pages, leaf_level) > phys_pages_for_pt.len() as u64 {
+        return Err(PagingErros::InsufficientPagesForPageTable);
+    }
+
+    let mut pt_pages = PtPages {
+        pages: phys_pages_for_pt,
+        used: 0,
+    };
+    let mut table: Option<PhysLv1PageAddress> = None;
+
+    for page in 0..number_of_pages {
+        let address = start + page * page_size;
+        let index = get_table_index(address, leaf_level);
+
+        //the table only changes when the index wraps around
+        let leaf_table = match table {
+            Some(leaf_table) if index != 0 => leaf_table,
+            _ => create_table(root, address, leaf_level, &mut pt_pages)?,
+        };
+
+        write_leaf(leaf_table, index, page as usize);
+        table = Some(leaf_table);
+    }
+
+    Ok(pt_pages.used)
+}
+
+///Checks the whole range first so that nothing is changed on errors \
+///Returns the tables that became empty, they are no longer part of the page table
+unsafe fn unmap_pages(
+    root: &mut ArchPageRoot,
+    start: u64,
+    number_of_pages: u64,
+    leaf_level: u8,
+) -> Result<Vec<PhysLv1PageAddress>, PagingErros> {
+    let mut freed_tables: Vec<PhysLv1PageAddress> = Vec::new();
+    let mut batch = ShootdownBatch::new();
+
+    let page_size = get_entry_size(leaf_level);
+    let Some(last) = get_page_range(start, number_of_pages, page_size)?.get_last() else {
+        return Ok(freed_tables);
+    };
+
+    for page in 0..number_of_pages {
+        let address = start + page * page_size;
+
+        match find_table(root, address, leaf_level) {
+            TableLookup::Table(table) if is_leaf_entry(table, leaf_level, get_table_index(address, leaf_level)) => {}
+            _ => return Err(PagingErros::PageAlreadyNotPresent),
+        }
+    }
+
+    for page in 0..number_of_pages {
+        let address = start + page * page_size;
+
+        if let TableLookup::Table(table) = find_table(root, address, leaf_level) {
+            clear_entry(table, leaf_level, get_table_index(address, leaf_level));
+            invalidate_page(root, &mut batch, address);
+        }
+    }
+
+    //bottom up so that the parents see their freed children
+    for table_level in leaf_level..root.get_root_level() {
+        let table_size = get_entry_size(table_level + 1);
+        let mut address = start - start % table_size;
+
+        loop {
+            //the root entries of the kernel half are copied into every root, so their tables are never freed
+            let shared = table_level + 1 == root.get_root_level() && (address as i64).is_negative();
+
+            if !shared
+                && let TableLookup::Table(table) = find_table(root, address, table_level)
+                && is_table_empty(table)
+                && let TableLookup::Table(parent) = find_table(root, address, table_level + 1)
+            {
+                clear_entry(parent, table_level + 1, get_table_index(address, table_level + 1));
+                invalidate_page(root, &mut batch, address);
+                freed_tables.push(table);
+            }
+
+            match address.checked_add(table_size) {
+                Some(next) if next <= last => address = next,
+                _ => break,
+            }
+        }
+    }
+
+    finish_invalidation(root, batch);
+    Ok(freed_tables)
+}
+
+fn lookup_page(root: &ArchPageRoot, address: u64) -> Option<Page> {
+    let mut table = root.address;
+
+    for level in (2..=root.get_root_level()).rev() {
+        let index = get_table_index(address, level);
+
+        match level {
+            3 => match pml3::read_page(table, index)? {
+                pml3::Pml2Or1G::Pml2(next) => table = next,
+                pml3::Pml2Or1G::G1(attributes, phys_address) => {
+                    return Some(Page::Lv3Page(Lv3Page {
+                        attributes,
+                        phys_address,
+                        virt_address: VirtLv3PageAddress::new_maskoff(address).ok()?,
+                    }))
+                }
+            },
+            2 => match pml2::read_page(table, index)? {
+                pml2::Pml1Or2M::Pml1(next) => table = next,
+                pml2::Pml1Or2M::MB2(attributes, phys_address) => {
+                    return Some(Page::Lv2Page(Lv2Page {
+                        attributes,
+                        phys_address,
+                        virt_address: VirtLv2PageAddress::new_maskoff(address).ok()?,
+                    }))
+                }
+            },
+            _ => table = pml4_5::read_page(table, index)?,
+        }
+    }
+
+    let (attributes, phys_address) = pml1::read_page(table, get_table_index(address, 1))?;
+
+    Some(Page::Lv1Page(Lv1Page {
+        attributes,
+        phys_address,
+        virt_address: VirtLv1PageAddress::new_maskoff(address).ok()?,
+    }))
+}
+
+///Returns the table that holds the entry mapping >address< and the level of that table
+fn find_leaf(root: &ArchPageRoot, address: u64) -> Option<(PhysLv1PageAddress, u8)> {
+    let mut table = root.address;
+
+    for level in (2..=root.get_root_level()).rev() {
+        match read_table_entry(table, level, get_table_index(address, level)) {
+            TableLookup::Table(next) => table = next,
+            TableLookup::HugePage => return Some((table, level)),
+            TableLookup::Missing => return None,
+        }
+    }
+
+    is_entry_valid(table, get_table_index(address, 1)).then_some((table, 1))
+}
+
+//(attributes, physical address) of a page mapping entry
+fn read_leaf(table: PhysLv1PageAddress, table_level: u8, index: u16) -> Option<(PageAttributes, u64)> {
+    match table_level {
+        3 => match pml3::read_page(table, index)? {
+            pml3::Pml2Or1G::G1(attributes, phys_address) => Some((attributes, phys_address.get_address().get_u64())),
+            _ => None,
+        },
+        2 => match pml2::read_page(table, index)? {
+            pml2::Pml1Or2M::MB2(attributes, phys_address) => Some((attributes, phys_address.get_address().get_u64())),
+            _ => None,
+        },
+        1 => pml1::read_page(table, index)
+            .map(|(attributes, phys_address)| (attributes, phys_address.get_address().get_u64())),
+        _ => None,
+    }
+}
+
+//(accessed, dirty) of a page mapping entry
+fn read_leaf_flags(table: PhysLv1PageAddress, table_level: u8, index: u16) -> Option<(bool, bool)> {
+    match table_level {
+        3 => pml3::read_page_flags(table, index),
+        2 => pml2::read_page_flags(table, index),
+        1 => pml1::read_page_flags(table, index),
+        _ => None,
+    }
+}
+
+//atomic as well, the entry may already be in use
+unsafe fn set_entry_bits(table: PhysLv1PageAddress, index: u16, bits: u64) {
+    let entry = table.get_address().offset_unchecked::<u64>(index.into()).to_virt_unchecked().get_u64() as *mut u64;
+    AtomicU64::from_ptr(entry).fetch_or(bits, Ordering::AcqRel);
+}
+
+//the cpu sets the accessed and dirty bits concurrently, so they have to be cleared atomically
+unsafe fn clear_entry_bits(table: PhysLv1PageAddress, index: u16, mask: u64) {
+    let entry = table.get_address().offset_unchecked::<u64>(index.into()).to_virt_unchecked().get_u64() as *mut u64;
+    AtomicU64::from_ptr(entry).fetch_and(!mask, Ordering::AcqRel);
+}
+
+unsafe fn write_leaf(
+    table: PhysLv1PageAddress,
+    table_level: u8,
+    index: u16,
+    attributes: PageAttributes,
+    phys_address: u64,
+) {
+    match table_level {
+        3 => pml3::write_page_1g(table, index, attributes, PhysLv3PageAddress::new_maskoff(phys_address)),
+        2 => pml2::write_page_2mb(table, index, attributes, PhysLv2PageAddress::new_maskoff(phys_address)),
+        1 => pml1::write_page(table, index, attributes, PhysLv1PageAddress::new_maskoff(phys_address)),
+        _ => unreachable!(),
+    }
+}
+
+//first address of the next page, None at the end of the address space
+#[inline]
+fn next_page(address: u64, page_size: u64) -> Option<u64> {
+    (address - address % page_size).checked_add(page_size)
+}
+
+//number of tables split_pages_at needs for all >addresses<, huge pages that contain more than one of them are split once
+fn count_split_tables(root: &ArchPageRoot, addresses: &[Option<u64>]) -> u64 {
+    //(level, first address) of the huge pages that are split
+    let mut split_pages: Vec<(u8, u64)> = Vec::new();
+
+    for address in addresses.iter().flatten() {
+        let Some((_, leaf_level)) = find_leaf(root, *address) else {
+            continue;
+        };
+
+        for level in (2..=leaf_level).rev() {
+            let page_size = get_entry_size(level);
+            if address % page_size == 0 {
+                break;
+            }
+
+            let split_page = (level, address - address % page_size);
+            if !split_pages.contains(&split_page) {
+                split_pages.push(split_page);
+            }
+        }
+    }
+
+    split_pages.len() as u64
+}
+
+///Splits the huge pages that map >address< until >address< is the start of a page \
+///The new tables are taken from >pt_pages<, the sub pages keep the accessed and dirty bits of the huge page
+unsafe fn split_pages_at(
+    root: &ArchPageRoot,
+    batch: &mut ShootdownBatch,
+    pt_pages: &mut PtPages,
+    address: u64,
+) -> Result<(), PagingErros> {
+    while let Some((table, level)) = find_leaf(root, address)
+        && level > 1
+        && address % get_entry_size(level) != 0
+    {
+        let index = get_table_index(address, level);
+        let (attributes, phys_address) = read_leaf(table, level, index).ok_or(PagingErros::PageAlreadyNotPresent)?;
+        let usage_bits = read_entry(table, index) & USAGE_BITS_MASK;
+        let page_address = address - address % get_entry_size(level);
+
+        let new_table = pt_pages.take()?;
+        let sub_page_size = get_entry_size(level - 1);
+
+        for sub_index in 0..ENTRIES_PER_TABLE {
+            write_leaf(
+                new_table,
+                level - 1,
+                sub_index,
+                attributes,
+                phys_address + sub_index as u64 * sub_page_size,
+            );
+            set_entry_bits(new_table, sub_index, usage_bits);
+        }
+
+        //a single write replaces the huge page, so the mapping is never absent
+        write_table_entry(table, level, index, new_table);
+        invalidate_page(root, batch, page_address);
+    }
+
+    Ok(())
+}
+
+///Replaces the lv1 table of the 2MiB region at >address< with a lv2 page if all its pages are contiguous and uniform \
+///The lv2 page is accessed or dirty if any of the lv1 pages is \
+///Returns the replaced table, it has to be returned to the PMM after the invalidation is finished
+unsafe fn merge_table(root: &ArchPageRoot, batch: &mut ShootdownBatch, address: u64) -> Option<PhysLv1PageAddress> {
+    let TableLookup::Table(pml2_table) = find_table(root, address, 2) else {
+        return None;
+    };
+
+    let pml2_index = get_table_index(address, 2);
+    let TableLookup::Table(pml1_table) = read_table_entry(pml2_table, 2, pml2_index) else {
+        return None;
+    };
+
+    let (attributes, phys_address) = read_leaf(pml1_table, 1, 0)?;
+
+    if phys_address % get_entry_size(2) != 0 {
+        return None;
+    }
+
+    let uniform = (1..ENTRIES_PER_TABLE).all(|index| {
+        read_leaf(pml1_table, 1, index) == Some((attributes, phys_address + index as u64 * get_entry_size(1)))
+    });
+
+    if !uniform {
+        return None;
+    }
+
+    let usage_bits = (0..ENTRIES_PER_TABLE).fold(0, |bits, index| bits | read_entry(pml1_table, index) & USAGE_BITS_MASK);
+
+    write_leaf(pml2_table, 2, pml2_index, attributes, phys_address);
+    set_entry_bits(pml2_table, pml2_index, usage_bits);
+    invalidate_page(root, batch, address - address % get_entry_size(2));
+
+    Some(pml1_table)
+}
+
+//size of the unmapped area around >address< as seen by the walk
+fn get_unmapped_size(root: &ArchPageRoot, address: u64) -> u64 {
+    let mut table = root.address;
+
+    for level in (2..=root.get_root_level()).rev() {
+        match read_table_entry(table, level, get_table_index(address, level)) {
+            TableLookup::Table(next) => table = next,
+            _ => return get_entry_size(level),
+        }
+    }
+
+    get_entry_size(1)
+}
+
+///Invalidates the translation of >address< on the executing cpu and adds it to the batch for the other cpus
+unsafe fn invalidate_page(root: &ArchPageRoot, batch: &mut ShootdownBatch, address: u64) {
+    if is_physical_memory_simulated() {
+        return;
+    }
+
+    batch.add(address);
+
+    //the upper half is shared by all roots and may be mapped with global pages
+    if is_root_active(root) || (address as i64).is_negative() {
+        asm!("invlpg [{}]", in(reg) address, options(nostack, preserves_flags));
+        return;
+    }
+
+    if PCID_ENABLED.load(Ordering::Acquire)
+        && *INVPCID_SUPPORTED
+        && let Some(pcid) = pcid::find_pcid(get_cpu_id(), root.id)
+    {
+        pcid::invpcid_address(pcid, address);
+    }
+}
+
+///Has to be called after the invalidate_page calls of a change \
+///Sends the batch to the other cpus that have the root loaded and waits for them, \
+///the remaining cpus drop their cached translations of the root the next time they load it
+fn finish_invalidation(root: &ArchPageRoot, mut batch: ShootdownBatch) {
+    if is_physical_memory_simulated() {
+        return;
+    }
+
+    //invlpg only reaches the current PCID, but the upper half is cached under every PCID
+    if batch.is_upper_half() && PCID_ENABLED.load(Ordering::Acquire) {
+        batch.set_flush_all();
+        unsafe { shootdown::flush_global() };
+    }
+
+    //SeqCst pairs with activate_root, either the cpu is in active_cpus or it loads the new generation
+    let tlb_generation = root.tlb_generation.fetch_add(1, Ordering::SeqCst) + 1;
+
+    //invalidate_page already handled the executing cpu
+    if is_root_active(root) || (PCID_ENABLED.load(Ordering::Acquire) && *INVPCID_SUPPORTED) {
+        pcid::set_seen_generation(get_cpu_id(), root.id, tlb_generation);
+    }
+
+    shootdown::shootdown(
+        root.address.get_address().get_u64(),
+        root.id,
+        tlb_generation,
+        &root.active_cpus,
+        &batch,
+    );
+}
+
+fn is_root_active(root: &ArchPageRoot) -> bool {
+    let cr3: u64;
+    unsafe { asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags)) };
+
+    cr3 & DEFAULTPHYSADDRESSMASK == root.address.get_address().get_u64()
+}
+
+///Loads the root on the executing cpu \
+///Keeps the cached translations of the root if they are still up to date
+pub(in crate::hal) unsafe fn activate_root(root: &ArchPageRoot) {
+    let cpu = get_cpu_id();
+    let mut cr3 = root.address.get_address().get_u64();
+
+    //has to happen before the generation is read so that no shootdown is missed
+    root.active_cpus.insert(cpu);
+
+    if PCID_ENABLED.load(Ordering::Acquire) {
+        let (pcid, up_to_date) = pcid::assign_pcid(cpu, root.id, root.tlb_generation.load(Ordering::SeqCst));
+
+        cr3 |= pcid as u64;
+        if up_to_date {
+            cr3 |= CR3_NO_FLUSH;
+        }
+    }
+
+    asm!("mov cr3, {}", in(reg) cr3, options(nostack, preserves_flags));
+}
+
+///Stops the shootdowns of the root to the executing cpu \
+///Has to be called after the next root was activated, the cpu must not use the root anymore
+pub(in crate::hal) fn deactivate_root(root: &ArchPageRoot) {
+    root.active_cpus.remove(get_cpu_id());
 }
 
 fn decode_caching_bits(pwt: bool, pcd: bool, pat: bool) -> CachingMode {
-    compile_error!("TODO");
+    match PAT_LAYOUT[(pat as usize) << 2 | (pcd as usize) << 1 | pwt as usize] {
+        PAT_WB => CachingMode::Default,
+        PAT_WC => CachingMode::Framebuffer,
+        PAT_UC => CachingMode::MMIO,
+        //WP is never encoded, it caches reads like WT
+        PAT_WT | PAT_WP => CachingMode::MmioPrefetch,
+        _ => CachingMode::DMA,
+    }
 }
 
 ///(PWT, PCD, PAT)
 fn encode_caching_bits(caching_mode: CachingMode) -> (bool, bool, bool) {
-    compile_error!("TODO");
+    let entry: usize = match caching_mode {
+        CachingMode::Default => 0,      //WB
+        CachingMode::MmioPrefetch => 1, //WT
+        CachingMode::DMA => 2,          //UC-
+        CachingMode::MMIO => 3,         //UC
+        CachingMode::Framebuffer => 4,  //WC
+    };
+
+    (entry & 1 != 0, entry & 2 != 0, entry & 4 != 0)
+}
+
+///Sets the PWT, PCD and PAT bits of a page mapping entry \
+///The PAT bit is bit 7 in lv1 entries and bit 12 in lv2 and lv3 entries
+fn set_caching_bits(page_entry: &mut u64, caching_mode: CachingMode, pat_bit: usize) {
+    let (pwt, pcd, pat) = encode_caching_bits(caching_mode);
+
+    page_entry.set_bit(PAGE_LEVEL_WRITETHROUGH_BIT, pwt);
+    page_entry.set_bit(PAGE_LEVEL_CACHE_DISABLE_BIT, pcd);
+    page_entry.set_bit(pat_bit, pat);
+}
+
+fn get_caching_mode(page_entry: u64, pat_bit: usize) -> CachingMode {
+    decode_caching_bits(
+        page_entry.get_bit(PAGE_LEVEL_WRITETHROUGH_BIT),
+        page_entry.get_bit(PAGE_LEVEL_CACHE_DISABLE_BIT),
+        page_entry.get_bit(pat_bit),
+    )
 }
 
 pub struct PMLEntryIndex {
@@ -212,7 +1254,7 @@ impl PMLEntryIndex {
             return None;
         }
 
-        Some(Self { index: index })
+        Some(Self { index })
 
     }
 
@@ -221,7 +1263,7 @@ impl PMLEntryIndex {
     }
 
     pub unsafe fn new_unchecked(index: u64) -> PMLEntryIndex {
-        Self { index: index }
+        Self { index }
     }
 
     pub fn get_index(&self) -> u64 {
@@ -234,6 +1276,37 @@ impl PMLEntryIndex {
 //Should be guarded by a Mutex
 pub struct ArchPageRoot {
     address: PhysLv1PageAddress,
+    root_level: u8, //5: PML5, 4: PML4
+    id: u64,        //never reused, identifies the root in the per cpu PCID tables
+    tlb_generation: AtomicU64,
+    active_cpus: CpuSet, //cpus that have the root loaded, they receive its shootdowns
+}
+
+//0 marks unused PCID slots
+static NEXT_ROOT_ID: AtomicU64 = AtomicU64::new(1);
+
+impl ArchPageRoot {
+    ///The root is a PML5 if the platform has 57 virtual address bits (5 level paging) and a PML4 otherwise \
+    ///Safety: >address< has to be a page table root that is not managed by another ArchPageRoot
+    pub unsafe fn new(address: PhysLv1PageAddress) -> ArchPageRoot {
+        ArchPageRoot {
+            address,
+            root_level: if get_virt_address_bits() > 48 { 5 } else { 4 },
+            id: NEXT_ROOT_ID.fetch_add(1, Ordering::Relaxed),
+            tlb_generation: AtomicU64::new(0),
+            active_cpus: CpuSet::new(),
+        }
+    }
+
+    #[inline]
+    pub fn get_address(&self) -> PhysLv1PageAddress {
+        self.address
+    }
+
+    #[inline]
+    pub fn get_root_level(&self) -> u8 {
+        self.root_level
+    }
 }
 
 
@@ -254,9 +1327,9 @@ mod pml4_5 {
         };
 
         if page_entry.get_bit(VALID_BIT) {
-            return Some(PhysLv1PageAddress::new_maskoff(page_entry));
+            Some(PhysLv1PageAddress::new_maskoff(page_entry))
         } else {
-            return None;
+            None
         }
 
     }
@@ -270,10 +1343,10 @@ mod pml4_5 {
 
         if page_entry.get_bit(VALID_BIT) {
             
-            return Some(page_entry.get_bit(ACCESSED_BIT));
+            Some(page_entry.get_bit(ACCESSED_BIT))
 
         } else {
-            return None;
+            None
         }
 
     }
@@ -291,7 +1364,7 @@ mod pml4_5 {
         page_entry.set_bit(USER_SUPERVISOR_BIT, true);
         page_entry.set_bit(VALID_BIT, true);
 
-        page_entry = page_entry | pml3_4_base_address.get_address().get_u64();
+        page_entry |= pml3_4_base_address.get_address().get_u64();
 
         page_level_base_address.get_address().offset_unchecked::<u64>(index.into()).write_unchecked::<u64>(&page_entry);
 
@@ -310,13 +1383,13 @@ mod pml4_5 {
 mod pml3 {
 
     use super::{
-        decode_caching_bits, encode_caching_bits, PhysLv1PageAddress, PhysLv3PageAddress, ACCESSED_BIT, DIRTY_BIT, GLOBAL_BIT, NO_EXECUTE_BIT, PAGE_LEVEL_CACHE_DISABLE_BIT, PAGE_LEVEL_WRITETHROUGH_BIT, PRESENT_BIT, READ_WRITE_BIT, USER_SUPERVISOR_BIT, VALID_BIT
+        get_caching_mode, set_caching_bits, PhysLv1PageAddress, PhysLv3PageAddress, ACCESSED_BIT, DIRTY_BIT, GLOBAL_BIT, MEMORY_PROTECTION_KEY_END_BIT, MEMORY_PROTECTION_KEY_START_BIT, NO_EXECUTE_BIT, PRESENT_BIT, READ_WRITE_BIT, USER_SUPERVISOR_BIT, VALID_BIT
     };
     use crate::hal::paging::PageAttributes;
     use bit_field::BitField;
 
     const LARGE_PAGE_BIT: usize = 7;
-    const PAGE_ATTRIBUTE_TABLE_BIT: usize = 12;
+    pub(super) const PAGE_ATTRIBUTE_TABLE_BIT: usize = 12;
 
     pub enum Pml2Or1G {
         Pml2(PhysLv1PageAddress),
@@ -335,34 +1408,31 @@ mod pml3 {
             
             if page_entry.get_bit(LARGE_PAGE_BIT) {
                 //1G Page
-                return Some(Pml2Or1G::G1(
+                Some(Pml2Or1G::G1(
                     PageAttributes {
                         present: page_entry.get_bit(PRESENT_BIT),
                         readonly: !page_entry.get_bit(READ_WRITE_BIT),
                         executable: !page_entry.get_bit(NO_EXECUTE_BIT),
                         supervisor: !page_entry.get_bit(USER_SUPERVISOR_BIT),
                         global: page_entry.get_bit(GLOBAL_BIT),
-                        caching_mode: decode_caching_bits(
-                            page_entry.get_bit(PAGE_LEVEL_WRITETHROUGH_BIT),
-                            page_entry.get_bit(PAGE_LEVEL_CACHE_DISABLE_BIT),
-                            page_entry.get_bit(PAGE_ATTRIBUTE_TABLE_BIT),
-                        ),
+                        protection_key: page_entry.get_bits(MEMORY_PROTECTION_KEY_START_BIT..=MEMORY_PROTECTION_KEY_END_BIT) as u8,
+                        caching_mode: get_caching_mode(page_entry, PAGE_ATTRIBUTE_TABLE_BIT),
                     },
                     PhysLv3PageAddress::new_maskoff(page_entry),
-                ));
+                ))
 
             } else {
                 //Points to PML2
-                return Some(
+                Some(
                     Pml2Or1G::Pml2(
                         PhysLv1PageAddress::new_maskoff(page_entry)
                     )
-                );
+                )
 
             }
 
         } else {
-            return None;
+            None
         }
 
     }
@@ -376,12 +1446,12 @@ mod pml3 {
         };
 
         if page_entry.get_bit(VALID_BIT) {
-            return Some((
+            Some((
                 page_entry.get_bit(ACCESSED_BIT),
                 page_entry.get_bit(DIRTY_BIT) & page_entry.get_bit(LARGE_PAGE_BIT),
-            ));
+            ))
         } else {
-            return None;
+            None
         }
     }
 
@@ -398,7 +1468,7 @@ mod pml3 {
         page_entry.set_bit(USER_SUPERVISOR_BIT, true);
         page_entry.set_bit(VALID_BIT, true);
 
-        page_entry = page_entry | pml2_base_address.get_address().get_u64();
+        page_entry |= pml2_base_address.get_address().get_u64();
 
         page_level_base_address.get_address().offset_unchecked::<u64>(index.into()).write_unchecked::<u64>(&page_entry);
 
@@ -418,15 +1488,12 @@ mod pml3 {
         page_entry.set_bit(NO_EXECUTE_BIT, !attributes.executable);
         page_entry.set_bit(USER_SUPERVISOR_BIT, !attributes.supervisor);
         page_entry.set_bit(GLOBAL_BIT, attributes.global);
+        page_entry.set_bits(MEMORY_PROTECTION_KEY_START_BIT..=MEMORY_PROTECTION_KEY_END_BIT, attributes.protection_key as u64);
         page_entry.set_bit(LARGE_PAGE_BIT, true);
 
-        let caching: (bool, bool, bool) = encode_caching_bits(attributes.caching_mode);
-
-        page_entry.set_bit(PAGE_LEVEL_WRITETHROUGH_BIT, caching.0);
-        page_entry.set_bit(PAGE_LEVEL_CACHE_DISABLE_BIT, caching.1);
-        page_entry.set_bit(PAGE_ATTRIBUTE_TABLE_BIT, caching.2);
+        set_caching_bits(&mut page_entry, attributes.caching_mode, PAGE_ATTRIBUTE_TABLE_BIT);
 
-        page_entry = page_entry | phys_address.get_address().get_u64();
+        page_entry |= phys_address.get_address().get_u64();
 
         page_level_base_address.get_address().offset_unchecked::<u64>(index.into()).write_unchecked::<u64>(&page_entry);
 
@@ -444,13 +1511,13 @@ mod pml3 {
 mod pml2 {
 
     use super::{
-        decode_caching_bits, encode_caching_bits, PhysLv1PageAddress, PhysLv2PageAddress, ACCESSED_BIT, DIRTY_BIT, GLOBAL_BIT, NO_EXECUTE_BIT, PAGE_LEVEL_CACHE_DISABLE_BIT, PAGE_LEVEL_WRITETHROUGH_BIT, PRESENT_BIT, READ_WRITE_BIT, USER_SUPERVISOR_BIT, VALID_BIT
+        get_caching_mode, set_caching_bits, PhysLv1PageAddress, PhysLv2PageAddress, ACCESSED_BIT, DIRTY_BIT, GLOBAL_BIT, MEMORY_PROTECTION_KEY_END_BIT, MEMORY_PROTECTION_KEY_START_BIT, NO_EXECUTE_BIT, PRESENT_BIT, READ_WRITE_BIT, USER_SUPERVISOR_BIT, VALID_BIT
     };
     use crate::hal::paging::PageAttributes;
     use bit_field::BitField;
 
     const LARGE_PAGE_BIT: usize = 7;
-    const PAGE_ATTRIBUTE_TABLE_BIT: usize = 12;
+    pub(super) const PAGE_ATTRIBUTE_TABLE_BIT: usize = 12;
 
     pub enum Pml1Or2M {
         Pml1(PhysLv1PageAddress),
@@ -469,34 +1536,31 @@ mod pml2 {
             
             if page_entry.get_bit(LARGE_PAGE_BIT) {
                 //2MB Page
-                return Some(Pml1Or2M::MB2(
+                Some(Pml1Or2M::MB2(
                     PageAttributes {
                         present: page_entry.get_bit(PRESENT_BIT),
                         readonly: !page_entry.get_bit(READ_WRITE_BIT),
                         executable: !page_entry.get_bit(NO_EXECUTE_BIT),
                         supervisor: !page_entry.get_bit(USER_SUPERVISOR_BIT),
                         global: page_entry.get_bit(GLOBAL_BIT),
-                        caching_mode: decode_caching_bits(
-                            page_entry.get_bit(PAGE_LEVEL_WRITETHROUGH_BIT),
-                            page_entry.get_bit(PAGE_LEVEL_CACHE_DISABLE_BIT),
-                            page_entry.get_bit(PAGE_ATTRIBUTE_TABLE_BIT),
-                        ),
+                        protection_key: page_entry.get_bits(MEMORY_PROTECTION_KEY_START_BIT..=MEMORY_PROTECTION_KEY_END_BIT) as u8,
+                        caching_mode: get_caching_mode(page_entry, PAGE_ATTRIBUTE_TABLE_BIT),
                     },
                     PhysLv2PageAddress::new_maskoff(page_entry),
-                ));
+                ))
 
             } else {
                 //Points to PML1
-                return Some(
+                Some(
                     Pml1Or2M::Pml1(
                         PhysLv1PageAddress::new_maskoff(page_entry)
                     )
-                );
+                )
 
             }
 
         } else {
-            return None;
+            None
         }
 
     }
@@ -510,12 +1574,12 @@ mod pml2 {
         };
 
         if page_entry.get_bit(VALID_BIT) {
-            return Some((
+            Some((
                 page_entry.get_bit(ACCESSED_BIT),
                 page_entry.get_bit(DIRTY_BIT) & page_entry.get_bit(LARGE_PAGE_BIT),
-            ));
+            ))
         } else {
-            return None;
+            None
         }
     }
 
@@ -532,7 +1596,7 @@ mod pml2 {
         page_entry.set_bit(USER_SUPERVISOR_BIT, true);
         page_entry.set_bit(VALID_BIT, true);
 
-        page_entry = page_entry | pml1_base_address.get_address().get_u64();
+        page_entry |= pml1_base_address.get_address().get_u64();
 
         page_level_base_address.get_address().offset_unchecked::<u64>(index.into()).write_unchecked::<u64>(&page_entry);
 
@@ -552,15 +1616,12 @@ mod pml2 {
         page_entry.set_bit(NO_EXECUTE_BIT, !attributes.executable);
         page_entry.set_bit(USER_SUPERVISOR_BIT, !attributes.supervisor);
         page_entry.set_bit(GLOBAL_BIT, attributes.global);
+        page_entry.set_bits(MEMORY_PROTECTION_KEY_START_BIT..=MEMORY_PROTECTION_KEY_END_BIT, attributes.protection_key as u64);
         page_entry.set_bit(LARGE_PAGE_BIT, true);
 
-        let caching: (bool, bool, bool) = encode_caching_bits(attributes.caching_mode);
+        set_caching_bits(&mut page_entry, attributes.caching_mode, PAGE_ATTRIBUTE_TABLE_BIT);
 
-        page_entry.set_bit(PAGE_LEVEL_WRITETHROUGH_BIT, caching.0);
-        page_entry.set_bit(PAGE_LEVEL_CACHE_DISABLE_BIT, caching.1);
-        page_entry.set_bit(PAGE_ATTRIBUTE_TABLE_BIT, caching.2);
-
-        page_entry = page_entry | phys_address.get_address().get_u64();
+        page_entry |= phys_address.get_address().get_u64();
 
         page_level_base_address.get_address().offset_unchecked::<u64>(index.into()).write_unchecked::<u64>(&page_entry);
 
@@ -577,12 +1638,12 @@ mod pml2 {
 mod pml1 {
 
     use super::{
-        decode_caching_bits, encode_caching_bits, PhysLv1PageAddress, ACCESSED_BIT, DIRTY_BIT, GLOBAL_BIT, NO_EXECUTE_BIT, PAGE_LEVEL_CACHE_DISABLE_BIT, PAGE_LEVEL_WRITETHROUGH_BIT, PRESENT_BIT, READ_WRITE_BIT, USER_SUPERVISOR_BIT, VALID_BIT
+        get_caching_mode, set_caching_bits, PhysLv1PageAddress, ACCESSED_BIT, DIRTY_BIT, GLOBAL_BIT, MEMORY_PROTECTION_KEY_END_BIT, MEMORY_PROTECTION_KEY_START_BIT, NO_EXECUTE_BIT, PRESENT_BIT, READ_WRITE_BIT, USER_SUPERVISOR_BIT, VALID_BIT
     };
     use crate::hal::paging::PageAttributes;
     use bit_field::BitField;
 
-    const PAGE_ATTRIBUTE_TABLE_BIT: usize = 7;
+    pub(super) const PAGE_ATTRIBUTE_TABLE_BIT: usize = 7;
 
     pub(in crate::hal::arch::paging) fn read_page(
         page_level_base_address: PhysLv1PageAddress, index: u16
@@ -593,23 +1654,20 @@ mod pml1 {
         };
 
         if page_entry.get_bit(VALID_BIT) {
-            return Some((
+            Some((
                 PageAttributes {
                     present: page_entry.get_bit(PRESENT_BIT),
                     readonly: !page_entry.get_bit(READ_WRITE_BIT),
                     executable: !page_entry.get_bit(NO_EXECUTE_BIT),
                     supervisor: !page_entry.get_bit(USER_SUPERVISOR_BIT),
                     global: page_entry.get_bit(GLOBAL_BIT),
-                    caching_mode: decode_caching_bits(
-                        page_entry.get_bit(PAGE_LEVEL_WRITETHROUGH_BIT),
-                        page_entry.get_bit(PAGE_LEVEL_CACHE_DISABLE_BIT),
-                        page_entry.get_bit(PAGE_ATTRIBUTE_TABLE_BIT),
-                    ),
+                    protection_key: page_entry.get_bits(MEMORY_PROTECTION_KEY_START_BIT..=MEMORY_PROTECTION_KEY_END_BIT) as u8,
+                    caching_mode: get_caching_mode(page_entry, PAGE_ATTRIBUTE_TABLE_BIT),
                 },
                 PhysLv1PageAddress::new_maskoff(page_entry),
-            ));
+            ))
         } else {
-            return None;
+            None
         }
     }
 
@@ -621,12 +1679,12 @@ mod pml1 {
         };
 
         if page_entry.get_bit(VALID_BIT) {
-            return Some((
+            Some((
                 page_entry.get_bit(ACCESSED_BIT),
                 page_entry.get_bit(DIRTY_BIT),
-            ));
+            ))
         } else {
-            return None;
+            None
         }
     }
 
@@ -643,14 +1701,11 @@ mod pml1 {
         page_entry.set_bit(NO_EXECUTE_BIT, !attributes.executable);
         page_entry.set_bit(USER_SUPERVISOR_BIT, !attributes.supervisor);
         page_entry.set_bit(GLOBAL_BIT, attributes.global);
+        page_entry.set_bits(MEMORY_PROTECTION_KEY_START_BIT..=MEMORY_PROTECTION_KEY_END_BIT, attributes.protection_key as u64);
 
-        let caching: (bool, bool, bool) = encode_caching_bits(attributes.caching_mode);
+        set_caching_bits(&mut page_entry, attributes.caching_mode, PAGE_ATTRIBUTE_TABLE_BIT);
 
-        page_entry.set_bit(PAGE_LEVEL_WRITETHROUGH_BIT, caching.0);
-        page_entry.set_bit(PAGE_LEVEL_CACHE_DISABLE_BIT, caching.1);
-        page_entry.set_bit(PAGE_ATTRIBUTE_TABLE_BIT, caching.2);
-
-        page_entry = page_entry | phys_address.get_address().get_u64();
+        page_entry |= phys_address.get_address().get_u64();
 
         page_level_base_address.get_address().offset_unchecked::<u64>(index.into()).write_unchecked::<u64>(&page_entry);
 
@@ -662,3 +1717,64 @@ mod pml1 {
         page_level_base_address.get_address().offset_unchecked::<u64>(index.into()).write_unchecked::<u64>(&0);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CACHING_MODES: [CachingMode; 5] = [
+        CachingMode::Default,
+        CachingMode::Framebuffer,
+        CachingMode::MMIO,
+        CachingMode::MmioPrefetch,
+        CachingMode::DMA,
+    ];
+
+    //(PAT bit, physical address mask) of the entries that map lv1, lv2 and lv3 pages
+    const PAGE_ENTRY_LAYOUTS: [(usize, u64); 3] = [
+        (pml1::PAGE_ATTRIBUTE_TABLE_BIT, DEFAULTPHYSADDRESSMASK),
+        (pml2::PAGE_ATTRIBUTE_TABLE_BIT, LV2PHYSADDRESSMASK),
+        (pml3::PAGE_ATTRIBUTE_TABLE_BIT, LV3PHYSADDRESSMASK),
+    ];
+
+    #[test]
+    fn caching_modes_round_trip() {
+        for caching_mode in CACHING_MODES {
+            let (pwt, pcd, pat) = encode_caching_bits(caching_mode);
+            assert_eq!(decode_caching_bits(pwt, pcd, pat), caching_mode);
+
+            for (pat_bit, _) in PAGE_ENTRY_LAYOUTS {
+                let mut page_entry: u64 = 0;
+                set_caching_bits(&mut page_entry, caching_mode, pat_bit);
+
+                assert_eq!(
+                    get_caching_mode(page_entry, pat_bit),
+                    caching_mode,
+                    "PAT bit {}",
+                    pat_bit
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn caching_modes_have_their_own_pat_entry() {
+        for (index, caching_mode) in CACHING_MODES.iter().enumerate() {
+            for other in &CACHING_MODES[index + 1..] {
+                assert_ne!(encode_caching_bits(*caching_mode), encode_caching_bits(*other));
+            }
+        }
+    }
+
+    #[test]
+    fn caching_bits_do_not_overlap_the_address() {
+        for caching_mode in CACHING_MODES {
+            for (pat_bit, address_mask) in PAGE_ENTRY_LAYOUTS {
+                let mut page_entry: u64 = 0;
+                set_caching_bits(&mut page_entry, caching_mode, pat_bit);
+
+                assert_eq!(page_entry & address_mask, 0, "PAT bit {}", pat_bit);
+            }
+        }
+    }
+}
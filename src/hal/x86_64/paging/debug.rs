@@ -0,0 +1,239 @@
+//Debug views of a page table
+//Both walk every entry of the root via the pml* helpers and never change the table
+
+use alloc::vec::Vec;
+use bit_field::BitField;
+
+use crate::{
+    hal::{
+        memory::VirtAddress,
+        paging::{PageAttributes, PageTableIssue},
+    },
+    kprintln,
+};
+
+use super::{
+    get_entry_size, is_table_empty, pml1, pml2, pml3, pml4_5, read_entry, ArchPageRoot,
+    ENTRIES_PER_TABLE, PRESENT_BIT, USER_SUPERVISOR_BIT, VALID_BIT,
+};
+
+enum EntryKind {
+    Table(super::PhysLv1PageAddress),
+    Leaf(PageAttributes, u64), //attributes, physical address
+    Invalid,                   //not 0 but VALID_BIT is not set
+}
+
+struct WalkedEntry {
+    address: u64,    //first virtual address covered by the entry
+    table_level: u8, //level of the table that holds the entry
+    raw: u64,
+    kind: EntryKind,
+    user_path: bool, //all tables above the entry allow user access
+}
+
+//a run of leaves with the same attributes that are contiguous in virtual and physical memory
+struct Region {
+    start: u64,
+    end: u64,
+    phys_start: u64,
+    attributes: PageAttributes,
+}
+
+///Writes the mapped regions of the root to the kernel log, contiguous pages with equal attributes are merged
+pub(in crate::hal) fn dump_root(root: &ArchPageRoot) {
+    kprintln!(
+        "PAGING: ROOT {:#x} (LEVEL {})",
+        root.get_address().get_address().get_u64(),
+        root.get_root_level()
+    );
+
+    let mut region: Option<Region> = None;
+    let mut number_of_regions: u64 = 0;
+
+    walk(root, &mut |entry| {
+        let EntryKind::Leaf(attributes, phys_address) = entry.kind else {
+            return;
+        };
+
+        let size = get_entry_size(entry.table_level);
+
+        if let Some(current) = &mut region
+            && current.end == entry.address
+            && current.phys_start + (current.end - current.start) == phys_address
+            && current.attributes == attributes
+        {
+            current.end += size;
+            return;
+        }
+
+        if let Some(finished) = region.replace(Region {
+            start: entry.address,
+            end: entry.address.wrapping_add(size),
+            phys_start: phys_address,
+            attributes,
+        }) {
+            dump_region(&finished);
+            number_of_regions += 1;
+        }
+    });
+
+    if let Some(finished) = region {
+        dump_region(&finished);
+        number_of_regions += 1;
+    }
+
+    kprintln!("PAGING: {} REGIONS", number_of_regions);
+}
+
+fn dump_region(region: &Region) {
+    let attributes = &region.attributes;
+
+    kprintln!(
+        "  [{:#018x} - {:#018x}) -> {:#014x} {:>10} KiB {}{}{}{}{} key {:>2} {:?}",
+        region.start,
+        region.end,
+        region.phys_start,
+        region.end.wrapping_sub(region.start) / 1024,
+        if attributes.present { "P" } else { "-" },
+        if attributes.readonly { "R" } else { "W" },
+        if attributes.executable { "X" } else { "-" },
+        if attributes.supervisor { "S" } else { "U" },
+        if attributes.global { "G" } else { "-" },
+        attributes.protection_key,
+        attributes.caching_mode
+    );
+}
+
+///Returns every inconsistency of the root, an empty Vec if there is none
+pub(in crate::hal) fn check_root(root: &ArchPageRoot) -> Vec<PageTableIssue> {
+    let mut issues: Vec<PageTableIssue> = Vec::new();
+
+    walk(root, &mut |entry| {
+        let virt_address = entry.address;
+
+        //the null page is the only canonical address VirtAddress rejects
+        if virt_address != 0 && VirtAddress::new(virt_address).is_err() {
+            issues.push(PageTableIssue::NonCanonical { virt_address });
+        }
+
+        match entry.kind {
+            EntryKind::Invalid if entry.raw.get_bit(PRESENT_BIT) => {
+                issues.push(PageTableIssue::PresentButInvalid {
+                    virt_address,
+                    table_level: entry.table_level,
+                });
+            }
+            EntryKind::Invalid => {}
+            EntryKind::Table(table) => {
+                //the tables below the kernel half of the root are created up front and never freed
+                let shared = entry.table_level == root.get_root_level()
+                    && (virt_address as i64).is_negative();
+
+                if !shared && is_table_empty(table) {
+                    issues.push(PageTableIssue::EmptyTable {
+                        virt_address,
+                        table_level: entry.table_level - 1,
+                    });
+                }
+            }
+            EntryKind::Leaf(attributes, _) => {
+                if !attributes.supervisor && !entry.user_path {
+                    issues.push(PageTableIssue::UserPageUnderSupervisorTable { virt_address });
+                }
+
+                if !attributes.readonly && attributes.executable {
+                    issues.push(PageTableIssue::WritableAndExecutable { virt_address });
+                }
+            }
+        }
+    });
+
+    issues
+}
+
+fn walk(root: &ArchPageRoot, visit: &mut dyn FnMut(&WalkedEntry)) {
+    walk_table(
+        root.get_root_level(),
+        root.get_address(),
+        root.get_root_level(),
+        0,
+        true,
+        visit,
+    );
+}
+
+fn walk_table(
+    root_level: u8,
+    table: super::PhysLv1PageAddress,
+    table_level: u8,
+    base: u64,
+    user_path: bool,
+    visit: &mut dyn FnMut(&WalkedEntry),
+) {
+    let entry_size = get_entry_size(table_level);
+
+    for index in 0..ENTRIES_PER_TABLE {
+        let raw = read_entry(table, index);
+        if raw == 0 {
+            continue;
+        }
+
+        let mut address = base + index as u64 * entry_size;
+
+        //the upper half of the root is sign extended
+        if table_level == root_level && index >= ENTRIES_PER_TABLE / 2 {
+            address |= !((entry_size * ENTRIES_PER_TABLE as u64) - 1);
+        }
+
+        let kind = if !raw.get_bit(VALID_BIT) {
+            EntryKind::Invalid
+        } else {
+            match table_level {
+                4 | 5 => {
+                    pml4_5::read_page(table, index).map_or(EntryKind::Invalid, EntryKind::Table)
+                }
+                3 => match pml3::read_page(table, index) {
+                    Some(pml3::Pml2Or1G::Pml2(next)) => EntryKind::Table(next),
+                    Some(pml3::Pml2Or1G::G1(attributes, phys_address)) => {
+                        EntryKind::Leaf(attributes, phys_address.get_address().get_u64())
+                    }
+                    None => EntryKind::Invalid,
+                },
+                2 => match pml2::read_page(table, index) {
+                    Some(pml2::Pml1Or2M::Pml1(next)) => EntryKind::Table(next),
+                    Some(pml2::Pml1Or2M::MB2(attributes, phys_address)) => {
+                        EntryKind::Leaf(attributes, phys_address.get_address().get_u64())
+                    }
+                    None => EntryKind::Invalid,
+                },
+                _ => pml1::read_page(table, index).map_or(
+                    EntryKind::Invalid,
+                    |(attributes, phys_address)| {
+                        EntryKind::Leaf(attributes, phys_address.get_address().get_u64())
+                    },
+                ),
+            }
+        };
+
+        let entry = WalkedEntry {
+            address,
+            table_level,
+            raw,
+            kind,
+            user_path,
+        };
+
+        visit(&entry);
+
+        if let EntryKind::Table(next) = entry.kind {
+            walk_table(
+                root_level,
+                next,
+                table_level - 1,
+                address,
+                user_path && raw.get_bit(USER_SUPERVISOR_BIT),
+                visit,
+            );
+        }
+    }
+}
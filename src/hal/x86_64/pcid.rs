@@ -0,0 +1,114 @@
+//Process Context Identifiers
+//Every cpu hands out its own PCIDs to the roots that run on it, a root can have different PCIDs on different cpus
+//PCID 0 is never handed out, it is used while PCIDs are disabled and for the boot page tables
+//Every root has a TLB generation that is bumped on every change of its mappings,
+//a cpu only keeps the cached translations of a PCID if it has seen the current generation
+
+use core::arch::asm;
+
+use crate::{
+    hal::{cpu::MAX_CPUS, interrupt::MASK_ALL},
+    sync::spinlock::Spinlock,
+};
+
+const PCIDS_PER_CPU: usize = 32;
+
+const INVPCID_INDIVIDUAL_ADDRESS: u64 = 0;
+const INVPCID_SINGLE_CONTEXT: u64 = 1;
+
+#[derive(Clone, Copy)]
+struct PcidSlot {
+    root_id: u64, //0: unused
+    tlb_generation: u64,
+}
+
+struct PcidTable {
+    slots: [PcidSlot; PCIDS_PER_CPU],
+    next: usize, //round robin eviction
+}
+
+static PCID_TABLES: [Spinlock<PcidTable>; MAX_CPUS] = [const {
+    Spinlock::new(
+        PcidTable {
+            slots: [PcidSlot {
+                root_id: 0,
+                tlb_generation: 0,
+            }; PCIDS_PER_CPU],
+            next: 1,
+        },
+        MASK_ALL,
+    )
+}; MAX_CPUS];
+
+///Returns (PCID, can keep the cached translations) for the root on >cpu< \
+///Evicts the oldest root of the cpu if the root has no PCID there
+pub(in crate::hal::arch) fn assign_pcid(
+    cpu: u32,
+    root_id: u64,
+    tlb_generation: u64,
+) -> (u16, bool) {
+    let mut table = unsafe { PCID_TABLES[cpu as usize].lock() };
+
+    if let Some(pcid) = (1..PCIDS_PER_CPU).find(|pcid| table.slots[*pcid].root_id == root_id) {
+        let up_to_date = table.slots[pcid].tlb_generation == tlb_generation;
+        table.slots[pcid].tlb_generation = tlb_generation;
+        return (pcid as u16, up_to_date);
+    }
+
+    let pcid = table.next;
+    table.next = if pcid + 1 == PCIDS_PER_CPU {
+        1
+    } else {
+        pcid + 1
+    };
+    table.slots[pcid] = PcidSlot {
+        root_id,
+        tlb_generation,
+    };
+
+    (pcid as u16, false)
+}
+
+///PCID of the root on >cpu<, None if the cpu has no translations of the root cached
+pub(in crate::hal::arch) fn find_pcid(cpu: u32, root_id: u64) -> Option<u16> {
+    let table = unsafe { PCID_TABLES[cpu as usize].lock() };
+    (1..PCIDS_PER_CPU)
+        .find(|pcid| table.slots[*pcid].root_id == root_id)
+        .map(|pcid| pcid as u16)
+}
+
+///Marks the cached translations of the root on >cpu< as up to date
+pub(in crate::hal::arch) fn set_seen_generation(cpu: u32, root_id: u64, tlb_generation: u64) {
+    let mut table = unsafe { PCID_TABLES[cpu as usize].lock() };
+
+    if let Some(slot) = table.slots[1..]
+        .iter_mut()
+        .find(|slot| slot.root_id == root_id)
+    {
+        slot.tlb_generation = tlb_generation;
+    }
+}
+
+///Invalidates the translation of >address< tagged with >pcid<, works for inactive PCIDs
+#[inline]
+pub(in crate::hal::arch) unsafe fn invpcid_address(pcid: u16, address: u64) {
+    invpcid(INVPCID_INDIVIDUAL_ADDRESS, pcid, address);
+}
+
+///Invalidates all non global translations tagged with >pcid<
+#[inline]
+pub(in crate::hal::arch) unsafe fn invpcid_context(pcid: u16) {
+    invpcid(INVPCID_SINGLE_CONTEXT, pcid, 0);
+}
+
+#[inline]
+unsafe fn invpcid(invalidation_type: u64, pcid: u16, address: u64) {
+    let descriptor: [u64; 2] = [pcid as u64, address];
+
+    asm!(
+        "invpcid {}, [{}]",
+        in(reg) invalidation_type,
+        in(reg) &descriptor,
+        options(nostack, preserves_flags),
+    );
+}
@@ -0,0 +1,153 @@
+//Memory Protection Keys
+//Every leaf entry carries a 4 bit key, the access rights of a key are taken from PKRU for user pages and from IA32_PKRS for supervisor pages
+//PKRU can be written from user mode with WRPKRU, so it is part of the thread state and has to be saved/restored on thread switches
+//PKRS is an MSR and can only be changed by the kernel
+
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use bit_field::BitField;
+use x86_64::registers::model_specific::Msr;
+
+use crate::hal::paging::KeyAccess;
+
+use super::cpuid;
+
+pub(in crate::hal) const PROTECTION_KEY_COUNT: u8 = 16;
+
+const IA32_PKRS: u32 = 0x6E1;
+const CR4_PKE: u64 = 1 << 22;
+const CR4_PKS: u64 = 1 << 24;
+
+//2 bits per key
+const ACCESS_DISABLE_BIT: usize = 0;
+const WRITE_DISABLE_BIT: usize = 1;
+
+static PKU_ENABLED: AtomicBool = AtomicBool::new(false);
+static PKS_ENABLED: AtomicBool = AtomicBool::new(false);
+
+///Access rights of all keys, the layout of PKRU and IA32_PKRS
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArchProtectionKeyRights(u32);
+
+impl ArchProtectionKeyRights {
+    ///Key 0 is the default key of all pages and can be read and written, all other keys can not be accessed
+    pub const fn new() -> ArchProtectionKeyRights {
+        ArchProtectionKeyRights(0x5555_5554)
+    }
+
+    ///Panics if the key is out of range
+    pub fn set_access(&mut self, key: u8, access: KeyAccess) {
+        if key >= PROTECTION_KEY_COUNT {
+            panic!("PAGING ERROR: PROTECTION KEY {} OUT OF RANGE", key);
+        }
+
+        let offset = key as usize * 2;
+        self.0.set_bit(offset + ACCESS_DISABLE_BIT, access == KeyAccess::None);
+        self.0.set_bit(offset + WRITE_DISABLE_BIT, access == KeyAccess::ReadOnly);
+    }
+
+    ///Panics if the key is out of range
+    pub fn get_access(&self, key: u8) -> KeyAccess {
+        if key >= PROTECTION_KEY_COUNT {
+            panic!("PAGING ERROR: PROTECTION KEY {} OUT OF RANGE", key);
+        }
+
+        let offset = key as usize * 2;
+        if self.0.get_bit(offset + ACCESS_DISABLE_BIT) {
+            KeyAccess::None
+        } else if self.0.get_bit(offset + WRITE_DISABLE_BIT) {
+            KeyAccess::ReadOnly
+        } else {
+            KeyAccess::ReadWrite
+        }
+    }
+}
+
+///Enables protection keys for user (PKU) and supervisor (PKS) pages if the cpu supports them \
+///Has to be called on every cpu
+pub(in crate::hal::arch) fn init_protection_keys() {
+    let mut cr4_bits: u64 = 0;
+
+    if cpuid::pku_supported() {
+        cr4_bits |= CR4_PKE;
+    }
+
+    if cpuid::pks_supported() {
+        cr4_bits |= CR4_PKS;
+    }
+
+    if cr4_bits == 0 {
+        return;
+    }
+
+    unsafe {
+        let cr4: u64;
+        asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags));
+        asm!("mov cr4, {}", in(reg) cr4 | cr4_bits, options(nostack, preserves_flags));
+    }
+
+    PKU_ENABLED.store(cr4_bits & CR4_PKE != 0, Ordering::Release);
+    PKS_ENABLED.store(cr4_bits & CR4_PKS != 0, Ordering::Release);
+
+    //the kernel does not restrict its own pages until it asks for it
+    if cr4_bits & CR4_PKS != 0 {
+        unsafe { Msr::new(IA32_PKRS).write(0) };
+    }
+}
+
+#[inline]
+pub(in crate::hal) fn is_pku_enabled() -> bool {
+    PKU_ENABLED.load(Ordering::Acquire)
+}
+
+#[inline]
+pub(in crate::hal) fn is_pks_enabled() -> bool {
+    PKS_ENABLED.load(Ordering::Acquire)
+}
+
+///Reads PKRU of the executing cpu, None if PKU is not enabled
+pub(in crate::hal) fn get_user_rights() -> Option<ArchProtectionKeyRights> {
+    if !is_pku_enabled() {
+        return None;
+    }
+
+    let pkru: u32;
+    unsafe {
+        asm!(
+            "rdpkru",
+            in("ecx") 0,
+            out("eax") pkru,
+            out("edx") _,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+
+    Some(ArchProtectionKeyRights(pkru))
+}
+
+///Writes PKRU of the executing cpu, does nothing if PKU is not enabled
+pub(in crate::hal) unsafe fn set_user_rights(rights: ArchProtectionKeyRights) {
+    if !is_pku_enabled() {
+        return;
+    }
+
+    asm!(
+        "wrpkru",
+        in("eax") rights.0,
+        in("ecx") 0,
+        in("edx") 0,
+        options(nostack, preserves_flags),
+    );
+}
+
+///Writes IA32_PKRS of the executing cpu, does nothing if PKS is not enabled
+pub(in crate::hal) unsafe fn set_supervisor_rights(rights: ArchProtectionKeyRights) {
+    if !is_pks_enabled() {
+        return;
+    }
+
+    Msr::new(IA32_PKRS).write(rights.0 as u64);
+}
@@ -0,0 +1,56 @@
+//16550 UART on COM1, used as the early kernel console
+//Polling only, no interrupts so it works before the IDT and APIC are set up
+
+use x86_64::instructions::port::Port;
+
+const COM1: u16 = 0x3F8;
+
+const DATA: u16 = 0; //DLAB=0: transmit/receive buffer, DLAB=1: divisor low byte
+const INTERRUPT_ENABLE: u16 = 1; //DLAB=1: divisor high byte
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+
+const LINE_CONTROL_DLAB: u8 = 1 << 7;
+const LINE_CONTROL_8N1: u8 = 0b11;
+const LINE_STATUS_TRANSMIT_EMPTY: u8 = 1 << 5;
+
+const BAUD_DIVISOR: u16 = 1; //115200 baud
+
+#[inline]
+fn port(register: u16) -> Port<u8> {
+    Port::new(COM1 + register)
+}
+
+pub(in crate::hal) fn init_serial() {
+    unsafe {
+        port(INTERRUPT_ENABLE).write(0);
+        port(LINE_CONTROL).write(LINE_CONTROL_DLAB);
+        port(DATA).write(BAUD_DIVISOR as u8);
+        port(INTERRUPT_ENABLE).write((BAUD_DIVISOR >> 8) as u8);
+        port(LINE_CONTROL).write(LINE_CONTROL_8N1);
+        port(FIFO_CONTROL).write(0xC7); //enable and clear the FIFOs, 14 byte threshold
+        port(MODEM_CONTROL).write(0x03); //DTR + RTS
+    }
+}
+
+pub(in crate::hal) fn write_serial(bytes: &[u8]) {
+    for byte in bytes {
+        //terminals expect CRLF
+        if *byte == b'\n' {
+            write_byte(b'\r');
+        }
+        write_byte(*byte);
+    }
+}
+
+#[inline]
+fn write_byte(byte: u8) {
+    unsafe {
+        while port(LINE_STATUS).read() & LINE_STATUS_TRANSMIT_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+        port(DATA).write(byte);
+    }
+}
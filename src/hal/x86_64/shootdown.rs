@@ -0,0 +1,263 @@
+//TLB shootdowns
+//A change of a root has to reach every cpu that has the root loaded, a change of the upper half every online cpu
+//The initiator invalidates its own TLB, sends its batch of addresses to the other cpus via IPI and waits for their acks
+//Only one shootdown is in flight at a time. Cpus that wait for their turn, for acks or for a spinlock service the batch
+//sent to them by polling, so the wait does not deadlock when the IrqLevel of a target masks the shootdown vector
+//Cpus that have the root cached but not loaded are not interrupted, they flush it when they load it again (see pcid.rs)
+
+use core::{
+    arch::asm,
+    hint,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+};
+
+use x86_64::structures::idt::InterruptStackFrame;
+
+use crate::{
+    hal::{cpu::MAX_CPUS, interrupt::MASK_ALL},
+    sync::spinlock::Spinlock,
+};
+
+use super::{
+    apic,
+    cpu::{get_apic_id, get_cpu_id},
+    paging::DEFAULTPHYSADDRESSMASK,
+    pcid,
+};
+
+pub(in crate::hal::arch) const SHOOTDOWN_VECTOR: u8 = 0xF0;
+
+//more addresses are sent as a flush of the whole root
+const BATCH_SIZE: usize = 32;
+
+const CPU_SET_WORDS: usize = MAX_CPUS.div_ceil(64);
+
+const CR4_PGE: u64 = 1 << 7;
+
+///Bitmap of cpu indices
+pub(in crate::hal::arch) struct CpuSet {
+    words: [AtomicU64; CPU_SET_WORDS],
+}
+
+impl CpuSet {
+    pub(in crate::hal::arch) const fn new() -> CpuSet {
+        CpuSet {
+            words: [const { AtomicU64::new(0) }; CPU_SET_WORDS],
+        }
+    }
+
+    #[inline]
+    pub(in crate::hal::arch) fn insert(&self, cpu: u32) {
+        self.words[cpu as usize / 64].fetch_or(1 << (cpu % 64), Ordering::SeqCst);
+    }
+
+    #[inline]
+    pub(in crate::hal::arch) fn remove(&self, cpu: u32) {
+        self.words[cpu as usize / 64].fetch_and(!(1 << (cpu % 64)), Ordering::SeqCst);
+    }
+
+    ///Iterates over a snapshot of the set
+    fn iter(&self) -> impl Iterator<Item = u32> + Clone {
+        let words: [u64; CPU_SET_WORDS] =
+            core::array::from_fn(|index| self.words[index].load(Ordering::SeqCst));
+
+        (0..MAX_CPUS as u32).filter(move |cpu| words[*cpu as usize / 64] & (1 << (cpu % 64)) != 0)
+    }
+}
+
+///Addresses whose translations have to be invalidated on the other cpus
+#[derive(Clone, Copy)]
+pub(in crate::hal::arch) struct ShootdownBatch {
+    addresses: [u64; BATCH_SIZE],
+    count: usize,
+    flush_all: bool,  //more addresses than fit into the batch
+    upper_half: bool, //the upper half is shared by all roots
+}
+
+impl ShootdownBatch {
+    pub(in crate::hal::arch) const fn new() -> ShootdownBatch {
+        ShootdownBatch {
+            addresses: [0; BATCH_SIZE],
+            count: 0,
+            flush_all: false,
+            upper_half: false,
+        }
+    }
+
+    pub(in crate::hal::arch) fn add(&mut self, address: u64) {
+        self.upper_half |= (address as i64).is_negative();
+
+        if self.count == BATCH_SIZE {
+            self.flush_all = true;
+            return;
+        }
+
+        self.addresses[self.count] = address;
+        self.count += 1;
+    }
+
+    ///Sends the batch as a flush of everything it could touch
+    #[inline]
+    pub(in crate::hal::arch) fn set_flush_all(&mut self) {
+        self.flush_all = true;
+    }
+
+    #[inline]
+    pub(in crate::hal::arch) fn is_upper_half(&self) -> bool {
+        self.upper_half
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ShootdownRequest {
+    root_address: u64,
+    root_id: u64,
+    tlb_generation: u64,
+    batch: ShootdownBatch,
+}
+
+static ONLINE_CPUS: CpuSet = CpuSet::new();
+
+static SHOOTDOWN_IN_PROGRESS: AtomicBool = AtomicBool::new(false);
+static REQUEST: Spinlock<ShootdownRequest> = Spinlock::new(
+    ShootdownRequest {
+        root_address: 0,
+        root_id: 0,
+        tlb_generation: 0,
+        batch: ShootdownBatch::new(),
+    },
+    MASK_ALL,
+);
+static PENDING: [AtomicBool; MAX_CPUS] = [const { AtomicBool::new(false) }; MAX_CPUS];
+static PENDING_ACKS: AtomicU32 = AtomicU32::new(0);
+
+///Has to be called on every cpu once its IDT and local APIC are set up \
+///Changes of the upper half are only sent to online cpus
+pub(in crate::hal::arch) fn init_shootdown() {
+    ONLINE_CPUS.insert(get_cpu_id());
+}
+
+///Sends the batch to the other cpus that have the root loaded and waits until they invalidated it \
+///The executing cpu has to have invalidated its own TLB and bumped the TLB generation of the root already
+pub(in crate::hal::arch) fn shootdown(
+    root_address: u64,
+    root_id: u64,
+    tlb_generation: u64,
+    active_cpus: &CpuSet,
+    batch: &ShootdownBatch,
+) {
+    if batch.is_empty() {
+        return;
+    }
+
+    let cpu = get_cpu_id();
+    let targets = if batch.upper_half {
+        &ONLINE_CPUS
+    } else {
+        active_cpus
+    };
+
+    //most roots are only loaded on the executing cpu
+    if targets.iter().all(|target| target == cpu) {
+        return;
+    }
+
+    while SHOOTDOWN_IN_PROGRESS
+        .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        service_shootdown();
+        hint::spin_loop();
+    }
+
+    *unsafe { REQUEST.lock() } = ShootdownRequest {
+        root_address,
+        root_id,
+        tlb_generation,
+        batch: *batch,
+    };
+
+    //the snapshot is taken after the generation bump, cpus that load the root later see the new generation
+    let targets = targets.iter().filter(|target| *target != cpu);
+
+    PENDING_ACKS.store(targets.clone().count() as u32, Ordering::Release);
+
+    for target in targets {
+        PENDING[target as usize].store(true, Ordering::Release);
+        unsafe { apic::send_ipi(get_apic_id(target), SHOOTDOWN_VECTOR) };
+    }
+
+    while PENDING_ACKS.load(Ordering::Acquire) != 0 {
+        hint::spin_loop();
+    }
+
+    SHOOTDOWN_IN_PROGRESS.store(false, Ordering::Release);
+}
+
+///Invalidates the batch sent to the executing cpu if there is one \
+///Safe to call at any IrqLevel, used by waits that could block the delivery of the shootdown vector
+pub(in crate::hal::arch) fn service_shootdown() {
+    //cheap check first, this is called from every spinlock wait
+    if !SHOOTDOWN_IN_PROGRESS.load(Ordering::Acquire) {
+        return;
+    }
+
+    let cpu = get_cpu_id();
+    if !PENDING[cpu as usize].swap(false, Ordering::AcqRel) {
+        return;
+    }
+
+    let request = *unsafe { REQUEST.lock() };
+    unsafe { invalidate_request(cpu, &request) };
+
+    PENDING_ACKS.fetch_sub(1, Ordering::Release);
+}
+
+pub(in crate::hal::arch) extern "x86-interrupt" fn shootdown_interrupt_handler(
+    _stack_frame: InterruptStackFrame,
+) {
+    service_shootdown();
+    unsafe { apic::end_of_interrupt() };
+}
+
+unsafe fn invalidate_request(cpu: u32, request: &ShootdownRequest) {
+    let cr3: u64;
+    asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags));
+
+    let root_active = cr3 & DEFAULTPHYSADDRESSMASK == request.root_address;
+
+    //an inactive root is flushed by its TLB generation the next time it is loaded
+    if !root_active && !request.batch.upper_half {
+        return;
+    }
+
+    if request.batch.flush_all {
+        if request.batch.upper_half {
+            flush_global();
+        } else {
+            //reloading CR3 without the no flush bit drops the non global translations of the current PCID
+            asm!("mov cr3, {}", in(reg) cr3, options(nostack, preserves_flags));
+        }
+    } else {
+        for address in &request.batch.addresses[..request.batch.count] {
+            asm!("invlpg [{}]", in(reg) *address, options(nostack, preserves_flags));
+        }
+    }
+
+    if root_active {
+        pcid::set_seen_generation(cpu, request.root_id, request.tlb_generation);
+    }
+}
+
+///Drops all translations of the executing cpu including the global ones of every PCID
+pub(in crate::hal::arch) unsafe fn flush_global() {
+    let cr4: u64;
+    asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags));
+    asm!("mov cr4, {}", in(reg) cr4 ^ CR4_PGE, options(nostack, preserves_flags));
+    asm!("mov cr4, {}", in(reg) cr4, options(nostack, preserves_flags));
+}
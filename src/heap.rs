@@ -1 +1,38 @@
+//Kernel heap
+//Talc in a static arena inside of the kernel image, so the heap works before the PMM and the kernel root are set up
+//TODO grow the heap with pages of the PMM once the vmm maps kernel segments
 
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ptr::{addr_of, null_mut, NonNull},
+};
+
+use talc::{ClaimOnOom, Span, Talc};
+
+use crate::{hal::interrupt::MASK_ALL, sync::spinlock::Spinlock};
+
+const EARLY_HEAP_SIZE: usize = 0x10_0000; //1M
+
+static mut EARLY_HEAP: [u8; EARLY_HEAP_SIZE] = [0; EARLY_HEAP_SIZE];
+
+#[global_allocator]
+static HEAP: Heap = Heap(Spinlock::new(
+    //the arena is claimed on the first allocation, nothing else accesses it
+    Talc::new(unsafe { ClaimOnOom::new(Span::from_array(addr_of!(EARLY_HEAP).cast_mut())) }),
+    MASK_ALL,
+));
+
+struct Heap(Spinlock<Talc<ClaimOnOom>>);
+
+unsafe impl GlobalAlloc for Heap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.0
+            .lock()
+            .malloc(layout)
+            .map_or(null_mut(), |pointer| pointer.as_ptr())
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        self.0.lock().free(NonNull::new_unchecked(pointer), layout);
+    }
+}
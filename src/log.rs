@@ -0,0 +1,37 @@
+//Kernel log, formatted text goes to the hal console
+//Use kprint!/kprintln! instead of the functions in here
+
+use core::fmt::{self, Write};
+
+use crate::{
+    hal::{console::write_console, interrupt::MASK_ALL},
+    sync::spinlock::Spinlock,
+};
+
+struct ConsoleWriter;
+
+impl Write for ConsoleWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_console(s.as_bytes());
+        Ok(())
+    }
+}
+
+//keeps lines of different cores from interleaving
+static LOG_LOCK: Spinlock<ConsoleWriter> = Spinlock::new(ConsoleWriter, MASK_ALL);
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    let _ = unsafe { LOG_LOCK.lock() }.write_fmt(args);
+}
+
+#[macro_export]
+macro_rules! kprint {
+    ($($arg:tt)*) => ($crate::log::_print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! kprintln {
+    () => ($crate::kprint!("\n"));
+    ($($arg:tt)*) => ($crate::log::_print(format_args!("{}\n", format_args!($($arg)*))));
+}
@@ -1,6 +1,9 @@
-#![no_std] // don't link the Rust standard library
-#![no_main] // disable all Rust-level entry points
+#![cfg_attr(not(test), no_std)] // don't link the Rust standard library
+#![cfg_attr(not(test), no_main)] // disable all Rust-level entry points
 #![allow(dead_code)]
+#![allow(incomplete_features)] //generic_const_exprs
+#![allow(clippy::doc_lazy_continuation)] //parameters are quoted as >name<, which markdown reads as a block quote
+#![allow(clippy::upper_case_acronyms)]
 #![feature(abi_x86_interrupt)]
 #![feature(allocator_api)]
 #![feature(btreemap_alloc)]
@@ -12,26 +15,39 @@ extern crate x86_64;
 
 mod bal;
 mod hal;
+#[cfg(not(test))] //the unit tests use the allocator of the standard library
 mod heap;
+mod log;
+#[cfg(not(test))]
 mod panic_handler;
 mod pmm;
+mod sync;
 mod vmm;
 
 //TODO check cpuid for required features and panic if they are missing (PCID......)
 
+//the unit tests run on the host with cargo test-host and use the entry point of the test harness
+#[cfg(not(test))]
 #[no_mangle] // don't mangle the name of this function
 pub extern "C" fn _start() -> ! {
     // this function is the entry point, since the linker looks for a function
     // named `_start` by default
 
+    hal::console::init_console();
+    hal::init_hal();
+
     //Check for required Hardware Features
     //Setup Initial Numa Aware PMM
+    pmm::init_bootstrap();
     //Setup Allocators
     //Setup all PMMS
     //Claim Paging Tables
+    hal::init_hal_finalization();
     //Create Core-local Structs
     //Create Process and Thread Structs
     //Jump other cores to
 
-    loop {}
+    loop {
+        x86_64::instructions::hlt();
+    }
 }
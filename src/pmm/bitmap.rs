@@ -0,0 +1,43 @@
+//Bitmap helpers shared by the pmm allocators
+//set bit = allocated
+
+use super::align_up;
+
+#[inline]
+pub(super) fn get(bitmap: &[u64], index: u64) -> bool {
+    bitmap[(index / 64) as usize] & (1 << (index % 64)) != 0
+}
+
+#[inline]
+pub(super) fn set(bitmap: &mut [u64], index: u64, value: bool) {
+    if value {
+        bitmap[(index / 64) as usize] |= 1 << (index % 64);
+    } else {
+        bitmap[(index / 64) as usize] &= !(1 << (index % 64));
+    }
+}
+
+///Searches >count< clear bits starting at >start_index< \
+///>first_page_number< is the physical page number of bit 0, the returned run starts at a physical page number that is a multiple of >alignment_pages<
+pub(super) fn find_clear_run(
+    bitmap: &[u64],
+    number_of_bits: u64,
+    first_page_number: u64,
+    start_index: u64,
+    count: u64,
+    alignment_pages: u64,
+) -> Option<u64> {
+    let align = |index: u64| align_up(first_page_number + index, alignment_pages) - first_page_number;
+
+    let mut index = align(start_index);
+
+    while index + count <= number_of_bits {
+        //searching backwards allows skipping the whole window up to the last used bit
+        match (index..index + count).rev().find(|i| get(bitmap, *i)) {
+            None => return Some(index),
+            Some(used) => index = align(used + 1),
+        }
+    }
+
+    None
+}
@@ -0,0 +1,236 @@
+use crate::{
+    bal::memory_map::MemoryMapEntryType,
+    hal::memory::{Lv1, PhysAddress, PhysLv1PageAddress, PhysRange, LV1_PAGE_SIZE},
+};
+
+use super::{bitmap, physical_map};
+
+///Size of the memory region that is managed before the NUMA topology is known
+pub const BOOTSTRAP_SIZE: u64 = 10 * 1024 * 1024;
+
+///Memory below 1MiB is kept free for AP trampolines and legacy devices
+const BOOTSTRAP_EXCLUDED: PhysRange = unsafe { PhysRange::with_size_unchecked(0, 0x10_0000) };
+
+//Sized for the smallest lv1 page size (4K), bigger lv1 pages just leave the tail unused
+const BOOTSTRAP_MAX_PAGES: usize = (BOOTSTRAP_SIZE / 4096) as usize;
+const BOOTSTRAP_BITMAP_WORDS: usize = BOOTSTRAP_MAX_PAGES / 64;
+
+///Bitmap allocator over a single contiguous region \
+///Needs no memory for its own datastructures so it can be used before anything else is set up \
+///Refcounts are kept for the first page of every allocation, the other pages of an allocation have a refcount of 0
+pub(super) struct BootstrapAllocator {
+    base: PhysLv1PageAddress,
+    number_of_pages: u64,
+    bitmap: [u64; BOOTSTRAP_BITMAP_WORDS],
+    refcounts: [u8; BOOTSTRAP_MAX_PAGES],
+    next_hint: u64,
+}
+
+impl BootstrapAllocator {
+    ///Carves the bootstrap region out of the first usable memory map entry that is large enough
+    pub(super) fn new() -> BootstrapAllocator {
+        for entry in physical_map::iter() {
+            if entry.entry_type != MemoryMapEntryType::Usable {
+                continue;
+            }
+
+            let Ok(range) = entry.get_range() else {
+                continue;
+            };
+
+            let (_, above_excluded) = range.subtract(&BOOTSTRAP_EXCLUDED);
+            let Some(range) = above_excluded.map(|range| range.align_inward::<Lv1>()) else {
+                continue;
+            };
+
+            if range.get_size() < BOOTSTRAP_SIZE {
+                continue;
+            }
+
+            return BootstrapAllocator {
+                base: PhysLv1PageAddress::new_maskoff(range.get_start()),
+                number_of_pages: BOOTSTRAP_SIZE / *LV1_PAGE_SIZE,
+                bitmap: [0; BOOTSTRAP_BITMAP_WORDS],
+                refcounts: [0; BOOTSTRAP_MAX_PAGES],
+                next_hint: 0,
+            };
+        }
+
+        panic!("PMM ERROR: NO USABLE MEMORY REGION FOR THE BOOTSTRAP ALLOCATOR");
+    }
+
+    #[inline]
+    pub(super) fn get_base(&self) -> PhysLv1PageAddress {
+        self.base
+    }
+
+    #[inline]
+    pub(super) fn get_range(&self) -> PhysRange {
+        unsafe {
+            PhysRange::with_size_unchecked(
+                self.base.get_address().get_u64(),
+                self.number_of_pages * *LV1_PAGE_SIZE,
+            )
+        }
+    }
+
+    #[inline]
+    pub(super) fn get_number_of_pages(&self) -> u64 {
+        self.number_of_pages
+    }
+
+    #[inline]
+    pub(super) fn contains(&self, address: PhysAddress) -> bool {
+        self.get_range().contains_address(address.get_u64())
+    }
+
+    #[inline]
+    pub(super) fn is_allocated(&self, index: u64) -> bool {
+        bitmap::get(&self.bitmap, index)
+    }
+
+    #[inline]
+    pub(super) fn get_refcount(&self, index: u64) -> u8 {
+        self.refcounts[index as usize]
+    }
+
+    ///Iterates over all allocations as (first page, number of pages, refcount) \
+    ///An allocation reaches from a page with a refcount up to the next one or the next free page
+    pub(super) fn iter_allocations(
+        &self,
+    ) -> impl Iterator<Item = (PhysLv1PageAddress, u64, u8)> + '_ {
+        let mut index: u64 = 0;
+
+        core::iter::from_fn(move || {
+            while index < self.number_of_pages && self.get_refcount(index) == 0 {
+                index += 1;
+            }
+
+            if index == self.number_of_pages {
+                return None;
+            }
+
+            let first = index;
+            index += 1;
+
+            while index < self.number_of_pages
+                && self.is_allocated(index)
+                && self.get_refcount(index) == 0
+            {
+                index += 1;
+            }
+
+            Some((
+                unsafe { self.base.offset_unchecked(first as i64) },
+                index - first,
+                self.get_refcount(first),
+            ))
+        })
+    }
+
+    ///Allocates >number_of_pages< contiguous lv1 pages whose physical start address is aligned to >alignment< bytes \
+    ///All pages are below the physical address >limit<
+    pub(super) fn alloc(
+        &mut self,
+        number_of_pages: u64,
+        alignment: u64,
+        limit: u64,
+    ) -> Option<PhysLv1PageAddress> {
+        //the hint is only useful for single pages, aligned requests are rare during boot
+        let start_index = if alignment <= *LV1_PAGE_SIZE {
+            self.next_hint
+        } else {
+            0
+        };
+
+        let base = self.base.get_address().get_u64();
+        if limit <= base {
+            return None;
+        }
+
+        let searchable_pages = self.number_of_pages.min((limit - base) / *LV1_PAGE_SIZE);
+
+        let index = bitmap::find_clear_run(
+            &self.bitmap,
+            searchable_pages,
+            self.base.get_address().get_u64() / *LV1_PAGE_SIZE,
+            start_index,
+            number_of_pages,
+            (alignment / *LV1_PAGE_SIZE).max(1),
+        );
+
+        let Some(index) = index else {
+            //retry from the start once if the hint skipped freed pages
+            if start_index != 0 {
+                self.next_hint = 0;
+                return self.alloc(number_of_pages, alignment, limit);
+            }
+            return None;
+        };
+
+        (index..index + number_of_pages).for_each(|i| bitmap::set(&mut self.bitmap, i, true));
+        self.refcounts[index as usize] = 1;
+
+        if alignment <= *LV1_PAGE_SIZE {
+            self.next_hint = index + number_of_pages;
+        }
+
+        Some(unsafe { self.base.offset_unchecked(index as i64) })
+    }
+
+    ///Like alloc but every page is its own allocation with a refcount of 1
+    pub(super) fn alloc_contiguous(
+        &mut self,
+        number_of_pages: u64,
+        alignment: u64,
+        limit: u64,
+    ) -> Option<PhysLv1PageAddress> {
+        let page = self.alloc(number_of_pages, alignment, limit)?;
+        let index = self.get_index(page);
+
+        self.refcounts[index as usize..(index + number_of_pages) as usize].fill(1);
+
+        Some(page)
+    }
+
+    pub(super) fn multi_alloc(&mut self, page: PhysLv1PageAddress) {
+        let index = self.get_index(page);
+
+        if self.refcounts[index as usize] == 0 {
+            panic!("PMM ERROR: MULTI ALLOC OF UNALLOCATED PAGE {:?}", page);
+        }
+
+        self.refcounts[index as usize] = self.refcounts[index as usize]
+            .checked_add(1)
+            .unwrap_or_else(|| panic!("PMM ERROR: REFCOUNT OVERFLOW OF {:?}", page));
+    }
+
+    ///Drops one reference of the allocation starting at >page< and frees its >number_of_pages< pages when it was the last one
+    pub(super) fn free(&mut self, page: PhysLv1PageAddress, number_of_pages: u64) {
+        let index = self.get_index(page);
+
+        self.refcounts[index as usize] = self.refcounts[index as usize]
+            .checked_sub(1)
+            .unwrap_or_else(|| panic!("PMM ERROR: REFCOUNT UNDERFLOW OF {:?}", page));
+
+        if self.refcounts[index as usize] != 0 {
+            return;
+        }
+
+        for i in index..index + number_of_pages {
+            if !self.is_allocated(i) {
+                panic!("PMM ERROR: DOUBLE FREE OF {:?}", unsafe {
+                    self.base.offset_unchecked(i as i64)
+                });
+            }
+            bitmap::set(&mut self.bitmap, i, false);
+        }
+
+        self.next_hint = self.next_hint.min(index);
+    }
+
+    #[inline]
+    fn get_index(&self, page: PhysLv1PageAddress) -> u64 {
+        (page.get_address().get_u64() - self.base.get_address().get_u64()) / *LV1_PAGE_SIZE
+    }
+}
@@ -0,0 +1,366 @@
+//Buddy allocator used by the NUMA nodes
+//Order n is a naturally aligned block of 2^n lv1 pages, so the lv2 and lv3 page sizes are orders as well
+//Freeing all lv1 pages of a lv2 page coalesces them into a lv2 block, the same goes for lv2 into lv3
+
+use lazy_static::lazy_static;
+
+use crate::hal::memory::{
+    PhysAddress, PhysLv1PageAddress, LV1_PAGE_SIZE, LV2_PAGE_SIZE, LV2_PAGE_SUPPORTED,
+    LV3_PAGE_SIZE, LV3_PAGE_SUPPORTED,
+};
+
+use super::frame::{frame_table_size, FrameDescriptor, FRAME_FREE, FRAME_RESERVED, FRAME_ZEROED};
+
+pub(super) const MAX_REGIONS: usize = 32;
+pub(super) const MAX_ORDERS: usize = 32;
+
+//end of a free list
+const NONE: u64 = u64::MAX;
+
+lazy_static! {
+    ///None if the platform has no lv2 pages
+    pub(super) static ref LV2_ORDER: Option<u32> =
+        (*LV2_PAGE_SUPPORTED).then(|| (*LV2_PAGE_SIZE / *LV1_PAGE_SIZE).trailing_zeros());
+    ///None if the platform has no lv3 pages
+    pub(super) static ref LV3_ORDER: Option<u32> =
+        (*LV3_PAGE_SUPPORTED).then(|| (*LV3_PAGE_SIZE / *LV1_PAGE_SIZE).trailing_zeros());
+    ///Blocks never coalesce beyond the biggest supported page size
+    pub(super) static ref MAX_ORDER: u32 = LV3_ORDER.or(*LV2_ORDER).unwrap_or(0);
+}
+
+//Stored in the first bytes of every free block
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct FreeBlockLink {
+    next: u64, //page number
+    prev: u64, //page number
+}
+
+//Contiguous memory managed by the buddy allocator
+#[derive(Clone, Copy)]
+struct BuddyRegion {
+    first_page_number: u64,
+    number_of_pages: u64,
+    frames: PhysAddress, //FrameDescriptor table
+}
+
+pub(super) struct BuddyAllocator {
+    regions: [Option<BuddyRegion>; MAX_REGIONS],
+    free_lists: [u64; MAX_ORDERS],
+    free_blocks: [u64; MAX_ORDERS],
+    free_pages: u64,
+}
+
+impl BuddyAllocator {
+    pub(super) const fn new() -> BuddyAllocator {
+        BuddyAllocator {
+            regions: [None; MAX_REGIONS],
+            free_lists: [NONE; MAX_ORDERS],
+            free_blocks: [0; MAX_ORDERS],
+            free_pages: 0,
+        }
+    }
+
+    ///Adds a region in which all pages are reserved, use unreserve_pages to hand them to the allocator \
+    ///Returns false without changing anything if all MAX_REGIONS slots are in use \
+    ///Safety: >frames< has to point to frame_table_size bytes of memory that is exclusively used by this allocator
+    pub(super) unsafe fn add_region(
+        &mut self,
+        base: PhysLv1PageAddress,
+        number_of_pages: u64,
+        frames: PhysAddress,
+        node: u16,
+    ) -> bool {
+        let Some(slot) = self.regions.iter_mut().find(|region| region.is_none()) else {
+            return false;
+        };
+
+        for index in 0..number_of_pages {
+            frames
+                .offset_unchecked::<FrameDescriptor>(index as i64)
+                .write_unchecked::<FrameDescriptor>(&FrameDescriptor::new_reserved(node));
+        }
+
+        *slot = Some(BuddyRegion {
+            first_page_number: base.get_address().get_u64() / *LV1_PAGE_SIZE,
+            number_of_pages,
+            frames,
+        });
+
+        true
+    }
+
+    #[inline]
+    pub(super) fn get_free_pages(&self) -> u64 {
+        self.free_pages
+    }
+
+    #[inline]
+    pub(super) fn get_free_blocks(&self, order: u32) -> u64 {
+        self.free_blocks[order as usize]
+    }
+
+    #[inline]
+    pub(super) fn contains(&self, address: PhysAddress) -> bool {
+        self.find_region(address.get_u64() / *LV1_PAGE_SIZE)
+            .is_some()
+    }
+
+    ///Allocates >number_of_pages< contiguous lv1 pages aligned to >alignment_pages< lv1 pages \
+    ///The request is served by a single block, the unused tail is returned to the allocator \
+    ///Returns if the pages are known to be zeroed
+    pub(super) fn alloc_pages(
+        &mut self,
+        number_of_pages: u64,
+        alignment_pages: u64,
+    ) -> Option<(PhysLv1PageAddress, bool)> {
+        let order = number_of_pages
+            .next_power_of_two()
+            .trailing_zeros()
+            .max(alignment_pages.next_power_of_two().trailing_zeros());
+
+        if order > *MAX_ORDER {
+            return None;
+        }
+
+        let (page_number, zeroed) = self.alloc_block(order)?;
+
+        if (1 << order) > number_of_pages {
+            self.free_page_numbers(
+                page_number + number_of_pages,
+                (1 << order) - number_of_pages,
+                zeroed,
+            );
+        }
+
+        Some((
+            unsafe { PhysLv1PageAddress::new_unchecked(page_number * *LV1_PAGE_SIZE) },
+            zeroed,
+        ))
+    }
+
+    ///>zeroed< tells the allocator that the content of the pages is zero
+    pub(super) fn free_pages(
+        &mut self,
+        page: PhysLv1PageAddress,
+        number_of_pages: u64,
+        zeroed: bool,
+    ) {
+        self.free_page_numbers(
+            page.get_address().get_u64() / *LV1_PAGE_SIZE,
+            number_of_pages,
+            zeroed,
+        );
+    }
+
+    ///Hands reserved pages to the allocator
+    pub(super) fn unreserve_pages(&mut self, page: PhysLv1PageAddress, number_of_pages: u64) {
+        let first_page_number = page.get_address().get_u64() / *LV1_PAGE_SIZE;
+
+        for page_number in first_page_number..first_page_number + number_of_pages {
+            let mut frame = self.get_frame(page_number);
+            frame.flags &= !FRAME_RESERVED;
+            self.set_frame(page_number, frame);
+        }
+
+        //the content of firmware and bootloader memory is unknown
+        self.free_page_numbers(first_page_number, number_of_pages, false);
+    }
+
+    ///Returns the descriptor of a page managed by this allocator
+    #[inline]
+    pub(super) fn get_frame(&self, page_number: u64) -> FrameDescriptor {
+        unsafe {
+            self.frame_address(page_number)
+                .read_unchecked::<FrameDescriptor>()
+        }
+    }
+
+    #[inline]
+    pub(super) fn set_frame(&mut self, page_number: u64, frame: FrameDescriptor) {
+        unsafe {
+            self.frame_address(page_number)
+                .write_unchecked::<FrameDescriptor>(&frame)
+        }
+    }
+
+    //splits the range into the biggest naturally aligned blocks
+    fn free_page_numbers(&mut self, first_page_number: u64, number_of_pages: u64, zeroed: bool) {
+        let end = first_page_number + number_of_pages;
+        let mut page_number = first_page_number;
+
+        while page_number < end {
+            let mut order = page_number.trailing_zeros().min(*MAX_ORDER);
+            while (1 << order) > end - page_number {
+                order -= 1;
+            }
+
+            self.free_block(page_number, order, zeroed);
+            page_number += 1 << order;
+        }
+    }
+
+    fn alloc_block(&mut self, order: u32) -> Option<(u64, bool)> {
+        let current =
+            (order..=*MAX_ORDER).find(|current| self.free_lists[*current as usize] != NONE)?;
+
+        let page_number = self.free_lists[current as usize];
+        let zeroed = self.get_frame(page_number).is_zeroed();
+        self.remove(page_number, current);
+
+        //hands back the upper halves until the block has the requested size
+        for split in (order..current).rev() {
+            self.push(page_number + (1 << split), split, zeroed);
+        }
+
+        self.free_pages -= 1 << order;
+        Some((page_number, zeroed))
+    }
+
+    fn free_block(&mut self, page_number: u64, order: u32, zeroed: bool) {
+        let frame = self.get_frame(page_number);
+
+        if frame.is_free() {
+            panic!("PMM ERROR: DOUBLE FREE OF {:?}", unsafe {
+                PhysLv1PageAddress::new_unchecked(page_number * *LV1_PAGE_SIZE)
+            });
+        }
+
+        if frame.is_reserved() {
+            panic!("PMM ERROR: FREE OF RESERVED PAGE {:?}", unsafe {
+                PhysLv1PageAddress::new_unchecked(page_number * *LV1_PAGE_SIZE)
+            });
+        }
+
+        self.free_pages += 1 << order;
+
+        let mut page_number = page_number;
+        let mut order = order;
+        let mut zeroed = zeroed;
+
+        while order < *MAX_ORDER {
+            let buddy = page_number ^ (1 << order);
+
+            if self.find_region(buddy).is_none() {
+                break;
+            }
+
+            let buddy_frame = self.get_frame(buddy);
+
+            if !buddy_frame.is_free() || buddy_frame.order != order as u8 {
+                break;
+            }
+
+            self.remove(buddy, order);
+            zeroed &= buddy_frame.is_zeroed();
+            page_number = page_number.min(buddy);
+            order += 1;
+        }
+
+        self.push(page_number, order, zeroed);
+    }
+
+    fn find_region(&self, page_number: u64) -> Option<&BuddyRegion> {
+        self.regions.iter().flatten().find(|region| {
+            page_number >= region.first_page_number
+                && page_number < region.first_page_number + region.number_of_pages
+        })
+    }
+
+    #[inline]
+    fn frame_address(&self, page_number: u64) -> PhysAddress {
+        let region = self
+            .find_region(page_number)
+            .expect("PMM ERROR: PAGE NOT MANAGED BY BUDDY ALLOCATOR");
+
+        unsafe {
+            region.frames.offset_unchecked::<FrameDescriptor>(
+                (page_number - region.first_page_number) as i64,
+            )
+        }
+    }
+
+    fn push(&mut self, page_number: u64, order: u32, zeroed: bool) {
+        let head = self.free_lists[order as usize];
+
+        write_link(
+            page_number,
+            FreeBlockLink {
+                next: head,
+                prev: NONE,
+            },
+        );
+
+        if head != NONE {
+            let mut link = read_link(head);
+            link.prev = page_number;
+            write_link(head, link);
+        }
+
+        self.free_lists[order as usize] = page_number;
+        self.free_blocks[order as usize] += 1;
+
+        let mut frame = self.get_frame(page_number);
+        frame.flags |= FRAME_FREE;
+        frame.flags = if zeroed {
+            frame.flags | FRAME_ZEROED
+        } else {
+            frame.flags & !FRAME_ZEROED
+        };
+        frame.order = order as u8;
+        frame.refcount = 0;
+        self.set_frame(page_number, frame);
+    }
+
+    fn remove(&mut self, page_number: u64, order: u32) {
+        let link = read_link(page_number);
+
+        if link.prev == NONE {
+            self.free_lists[order as usize] = link.next;
+        } else {
+            let mut prev = read_link(link.prev);
+            prev.next = link.next;
+            write_link(link.prev, prev);
+        }
+
+        if link.next != NONE {
+            let mut next = read_link(link.next);
+            next.prev = link.prev;
+            write_link(link.next, next);
+        }
+
+        self.free_blocks[order as usize] -= 1;
+
+        let mut frame = self.get_frame(page_number);
+
+        //the link is the only non zero part of a zeroed block
+        if frame.is_zeroed() {
+            write_link(page_number, FreeBlockLink { next: 0, prev: 0 });
+        }
+
+        frame.flags &= !(FRAME_FREE | FRAME_ZEROED);
+        frame.order = 0;
+        self.set_frame(page_number, frame);
+    }
+}
+
+#[inline]
+fn read_link(page_number: u64) -> FreeBlockLink {
+    unsafe {
+        PhysAddress::new_unchecked(page_number * *LV1_PAGE_SIZE).read_unchecked::<FreeBlockLink>()
+    }
+}
+
+#[inline]
+fn write_link(page_number: u64, link: FreeBlockLink) {
+    unsafe {
+        PhysAddress::new_unchecked(page_number * *LV1_PAGE_SIZE)
+            .write_unchecked::<FreeBlockLink>(&link)
+    }
+}
+
+///Size in bytes of the metadata of a region with >number_of_pages< pages
+#[inline]
+pub(super) fn metadata_size(number_of_pages: u64) -> u64 {
+    frame_table_size(number_of_pages)
+}
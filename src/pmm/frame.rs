@@ -0,0 +1,71 @@
+//Per frame metadata, every lv1 page managed by a NUMA node has one descriptor
+//The descriptors live in the node's own memory next to each other, one table per buddy region
+//For lv2/lv3 pages only the descriptor of the first lv1 page (the head) carries the refcount
+
+use core::mem::size_of;
+
+pub const FRAME_FREE: u8 = 1 << 0; //head of a free buddy block, >order< holds the block order
+pub const FRAME_RESERVED: u8 = 1 << 1; //never handed out by the allocator (allocator metadata, firmware)
+pub const FRAME_PINNED: u8 = 1 << 2; //has to stay allocated, the last free panics instead
+pub const FRAME_ZEROED: u8 = 1 << 3; //head of a free block whose content is known to be zero
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct FrameDescriptor {
+    pub(super) refcount: u32,
+    pub(super) node: u16,
+    pub(super) flags: u8,
+    pub(super) order: u8, //free blocks: buddy order, allocated heads: order of the allocated page level
+}
+
+impl FrameDescriptor {
+    pub(super) const fn new_reserved(node: u16) -> FrameDescriptor {
+        FrameDescriptor {
+            refcount: 0,
+            node,
+            flags: FRAME_RESERVED,
+            order: 0,
+        }
+    }
+
+    #[inline]
+    pub fn get_refcount(&self) -> u32 {
+        self.refcount
+    }
+
+    #[inline]
+    pub fn get_node(&self) -> u32 {
+        self.node as u32
+    }
+
+    #[inline]
+    pub fn get_order(&self) -> u8 {
+        self.order
+    }
+
+    #[inline]
+    pub fn is_free(&self) -> bool {
+        self.flags & FRAME_FREE != 0
+    }
+
+    #[inline]
+    pub fn is_reserved(&self) -> bool {
+        self.flags & FRAME_RESERVED != 0
+    }
+
+    #[inline]
+    pub fn is_pinned(&self) -> bool {
+        self.flags & FRAME_PINNED != 0
+    }
+
+    #[inline]
+    pub fn is_zeroed(&self) -> bool {
+        self.flags & FRAME_ZEROED != 0
+    }
+}
+
+///Size in bytes of the descriptor table for >number_of_pages< pages
+#[inline]
+pub(super) fn frame_table_size(number_of_pages: u64) -> u64 {
+    number_of_pages * size_of::<FrameDescriptor>() as u64
+}
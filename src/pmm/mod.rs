@@ -0,0 +1,530 @@
+//Physical Memory Manager
+//Phase 1 (Bootstrap): manages a small region carved from the bootloader memory map, used until the heap and ACPI are up
+//Phase 2 (NUMA): manages all usable memory with one buddy allocator per NUMA node, set up once the topology is known (ACPI SRAT)
+
+mod bitmap;
+mod bootstrap;
+mod buddy;
+mod frame;
+mod numa;
+mod physical_map;
+mod stats;
+mod zone;
+
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use alloc::vec::Vec;
+use enumn::N;
+
+use crate::{
+    bal::{hhdm::HHDM_OFFSET, memory_map::MemoryMapEntryType, set_bootloader_memory_reclaimed},
+    hal::{
+        interrupt::MASK_ALL,
+        memory::{
+            utility, PhysLv1PageAddress, PhysLv2PageAddress, PhysLv3PageAddress, PhysRange,
+            LV1_PAGE_SIZE,
+        },
+    },
+    kprintln,
+    sync::spinlock::Spinlock,
+};
+
+use bootstrap::BootstrapAllocator;
+use buddy::{LV2_ORDER, LV3_ORDER};
+use numa::{NumaNode, NODES};
+
+pub use frame::FrameDescriptor;
+pub use numa::{NumaMemoryRange, MAX_NUMA_NODES};
+pub use zone::MemoryZone;
+
+//the interface of the pmm, the boot code does not use all of it yet
+#[allow(unused_imports)]
+pub use bootstrap::BOOTSTRAP_SIZE;
+#[allow(unused_imports)]
+pub use stats::{
+    dump_physical_map, get_memory_map_statistics, get_node_statistics, get_statistics,
+    MemoryMapStatistics, PageLevelStatistics, PmmStatistics,
+};
+
+#[derive(N, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum PmmPhase {
+    Uninitialized,
+    Bootstrap,
+    Numa,
+}
+
+static PMM_PHASE: AtomicU8 = AtomicU8::new(PmmPhase::Uninitialized as u8);
+static BOOTSTRAP: Spinlock<Option<BootstrapAllocator>> = Spinlock::new(None, MASK_ALL);
+
+#[inline]
+fn get_phase() -> PmmPhase {
+    PmmPhase::n(PMM_PHASE.load(Ordering::Acquire)).unwrap()
+}
+
+///Phase 1 \
+///Called early in OS Boot, only needs the bootloader memory map and the HHDM
+pub fn init_bootstrap() {
+    if get_phase() != PmmPhase::Uninitialized {
+        panic!("PMM ERROR: ALREADY INITIALIZED");
+    }
+
+    physical_map::copy_from_bootloader();
+    unsafe { *BOOTSTRAP.lock() = Some(BootstrapAllocator::new()) };
+    PMM_PHASE.store(PmmPhase::Bootstrap as u8, Ordering::Release);
+}
+
+///Phase 2 \
+///>affinity< are the memory affinity ranges from the ACPI SRAT, an empty slice puts all memory into node 0 \
+///Safety: Has to be called once before other cores are started
+pub unsafe fn init_numa(affinity: &[NumaMemoryRange]) {
+    if get_phase() != PmmPhase::Bootstrap {
+        panic!("PMM ERROR: BOOTSTRAP PHASE NOT ACTIVE");
+    }
+
+    numa::save_affinity(affinity);
+
+    let mut bootstrap = BOOTSTRAP.lock();
+    let bootstrap_allocator = bootstrap.as_ref().unwrap();
+
+    let mut slot: usize = 0;
+
+    for node_id in 0..=affinity.iter().map(|range| range.node).max().unwrap_or(0) {
+        let Some(node) = NumaNode::new(node_id, affinity, bootstrap_allocator) else {
+            continue;
+        };
+
+        if slot == MAX_NUMA_NODES {
+            panic!("PMM ERROR: MORE THAN {} NUMA NODES", MAX_NUMA_NODES);
+        }
+
+        *NODES[slot].lock() = Some(node);
+        slot += 1;
+    }
+
+    //the bootstrap region is now owned by the node allocators
+    *bootstrap = None;
+    PMM_PHASE.store(PmmPhase::Numa as u8, Ordering::Release);
+}
+
+///Hands the bootloader reclaimable memory to the pmm, the bal can not be used afterwards \
+///Before the NUMA phase the memory is picked up by init_numa \
+///Safety: Nothing may use the bootloader responses, page tables or stack anymore
+pub unsafe fn reclaim_bootloader_memory() {
+    if get_phase() == PmmPhase::Uninitialized {
+        panic!("PMM ERROR: NOT INITIALIZED");
+    }
+
+    //the HHDM offset is the only bootloader value that is still read afterwards
+    lazy_static::initialize(&HHDM_OFFSET);
+    set_bootloader_memory_reclaimed();
+
+    let reclaimed = MemoryMapEntryType::BootloaderReclaimable;
+
+    if get_phase() == PmmPhase::Numa {
+        //adjacent entries are added as a single range, every range needs a buddy region and its own descriptors
+        let mut pending: Option<PhysRange> = None;
+        let mut unmanaged_pages: u64 = 0;
+
+        for range in physical_map::iter()
+            .filter(|entry| entry.entry_type == reclaimed)
+            .filter_map(|entry| entry.get_range().ok())
+        {
+            match pending.and_then(|pending| pending.join(&range)) {
+                Some(joined) => pending = Some(joined),
+                None => {
+                    if let Some(pending) = pending {
+                        unmanaged_pages += add_reclaimed_range(pending);
+                    }
+                    pending = Some(range);
+                }
+            }
+        }
+
+        if let Some(pending) = pending {
+            unmanaged_pages += add_reclaimed_range(pending);
+        }
+
+        if unmanaged_pages != 0 {
+            kprintln!(
+                "PMM WARNING: {} RECLAIMED PAGES COULD NOT BE MANAGED",
+                unmanaged_pages
+            );
+        }
+    }
+
+    physical_map::retype_entries(reclaimed, MemoryMapEntryType::Usable);
+}
+
+//hands >range< to the nodes that own its parts, returns the number of pages that could not be managed
+fn add_reclaimed_range(range: PhysRange) -> u64 {
+    let (affinity, affinity_count) = numa::get_affinity();
+    let mut unmanaged_pages: u64 = 0;
+
+    for slot in NODES.iter() {
+        let mut guard = unsafe { slot.lock() };
+        let Some(node) = guard.as_mut() else {
+            continue;
+        };
+
+        let id = node.get_id();
+        numa::for_each_node_range(id, &affinity[..affinity_count], range, |range| {
+            unmanaged_pages += node.add_memory(range)
+        });
+    }
+
+    unmanaged_pages
+}
+
+//TODO: read the node from the core local struct once it exists
+#[inline]
+fn get_local_node() -> u32 {
+    0
+}
+
+///Allocates a naturally aligned page of 2^>order< lv1 pages inside >zone< \
+///Tries >node< first and falls back to all other nodes if >fallback< is set
+fn alloc_page(
+    node: u32,
+    fallback: bool,
+    order: u32,
+    zone: MemoryZone,
+    zeroed: bool,
+) -> Option<PhysLv1PageAddress> {
+    let number_of_pages: u64 = 1 << order;
+
+    let (page, already_zeroed) = match get_phase() {
+        PmmPhase::Uninitialized => panic!("PMM ERROR: NOT INITIALIZED"),
+        PmmPhase::Bootstrap => (
+            unsafe { BOOTSTRAP.lock() }.as_mut().unwrap().alloc(
+                number_of_pages,
+                number_of_pages * *LV1_PAGE_SIZE,
+                zone.get_limit(),
+            )?,
+            false,
+        ),
+        PmmPhase::Numa => {
+            find_node(node, fallback, |numa_node| numa_node.alloc(order, zone))?
+        }
+    };
+
+    if zeroed && !already_zeroed {
+        zero_pages(page, number_of_pages);
+    }
+
+    Some(page)
+}
+
+///Runs >f< on >node< first and on all other nodes if >fallback< is set until it returns Some
+fn find_node<R>(
+    node: u32,
+    fallback: bool,
+    mut f: impl FnMut(&mut NumaNode) -> Option<R>,
+) -> Option<R> {
+    let mut try_node = |slot: &Spinlock<Option<NumaNode>>, local: bool| {
+        let mut guard = unsafe { slot.lock() };
+        let numa_node = guard.as_mut()?;
+
+        if (numa_node.get_id() == node) != local {
+            return None;
+        }
+
+        f(numa_node)
+    };
+
+    NODES.iter().find_map(|slot| try_node(slot, true)).or_else(|| {
+        if !fallback {
+            return None;
+        }
+        NODES.iter().find_map(|slot| try_node(slot, false))
+    })
+}
+
+///Runs >f< on the node that owns >page<
+fn with_owning_node<R>(page: PhysLv1PageAddress, f: impl FnOnce(&mut NumaNode) -> R) -> R {
+    for slot in NODES.iter() {
+        let mut guard = unsafe { slot.lock() };
+
+        if let Some(node) = guard.as_mut()
+            && node.contains(page.get_address())
+        {
+            return f(node);
+        }
+    }
+
+    panic!("PMM ERROR: PAGE {:?} IS NOT MANAGED BY THE PMM", page);
+}
+
+fn multi_alloc_page(page: PhysLv1PageAddress, order: u32) {
+    match get_phase() {
+        PmmPhase::Uninitialized => panic!("PMM ERROR: NOT INITIALIZED"),
+        PmmPhase::Bootstrap => {
+            let mut bootstrap = unsafe { BOOTSTRAP.lock() };
+            let bootstrap = bootstrap.as_mut().unwrap();
+
+            if !bootstrap.contains(page.get_address()) {
+                panic!("PMM ERROR: PAGE {:?} IS NOT MANAGED BY THE PMM", page);
+            }
+
+            bootstrap.multi_alloc(page);
+        }
+        PmmPhase::Numa => with_owning_node(page, |node| node.multi_alloc(page, order)),
+    }
+}
+
+fn free_page(page: PhysLv1PageAddress, order: u32, zeroed: bool) {
+    match get_phase() {
+        PmmPhase::Uninitialized => panic!("PMM ERROR: NOT INITIALIZED"),
+        PmmPhase::Bootstrap => {
+            let mut bootstrap = unsafe { BOOTSTRAP.lock() };
+            let bootstrap = bootstrap.as_mut().unwrap();
+
+            if !bootstrap.contains(page.get_address()) {
+                panic!("PMM ERROR: PAGE {:?} IS NOT MANAGED BY THE PMM", page);
+            }
+
+            //the bootstrap allocator does not track zeroed pages
+            bootstrap.free(page, 1 << order);
+        }
+        PmmPhase::Numa => with_owning_node(page, |node| node.free(page, order, zeroed)),
+    }
+}
+
+fn zero_pages(page: PhysLv1PageAddress, number_of_pages: u64) {
+    let range = PhysRange::from_pages(page, number_of_pages).expect("PMM ERROR: INVALID PAGE RANGE");
+
+    unsafe { utility::zero_range(range) };
+}
+
+#[inline]
+fn align_up(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
+//pages of an unsupported level can not be allocated, so they can not be shared or freed either
+#[inline]
+fn get_supported_order(order: Option<u32>) -> u32 {
+    order.expect("PMM ERROR: PAGE LEVEL NOT SUPPORTED")
+}
+
+#[inline]
+fn lv2_as_lv1(page: PhysLv2PageAddress) -> PhysLv1PageAddress {
+    unsafe { PhysLv1PageAddress::new_unchecked(page.get_address().get_u64()) }
+}
+
+#[inline]
+fn lv3_as_lv1(page: PhysLv3PageAddress) -> PhysLv1PageAddress {
+    unsafe { PhysLv1PageAddress::new_unchecked(page.get_address().get_u64()) }
+}
+
+//returns a page
+pub fn alloc_lv1(zeroed: bool) -> PhysLv1PageAddress {
+    try_alloc_lv1(zeroed).expect("PMM ERROR: OUT OF MEMORY")
+}
+
+pub fn alloc_lv2(zeroed: bool) -> PhysLv2PageAddress {
+    try_alloc_lv2(zeroed).expect("PMM ERROR: OUT OF MEMORY")
+}
+
+pub fn alloc_lv3(zeroed: bool) -> PhysLv3PageAddress {
+    try_alloc_lv3(zeroed).expect("PMM ERROR: OUT OF MEMORY")
+}
+
+//returns None instead of panicking when no memory is left or the platform has no pages of the level
+pub fn try_alloc_lv1(zeroed: bool) -> Option<PhysLv1PageAddress> {
+    alloc_page(get_local_node(), true, 0, MemoryZone::Any, zeroed)
+}
+
+pub fn try_alloc_lv2(zeroed: bool) -> Option<PhysLv2PageAddress> {
+    let page = alloc_page(get_local_node(), true, (*LV2_ORDER)?, MemoryZone::Any, zeroed)?;
+    Some(unsafe { PhysLv2PageAddress::new_unchecked(page.get_address().get_u64()) })
+}
+
+pub fn try_alloc_lv3(zeroed: bool) -> Option<PhysLv3PageAddress> {
+    let page = alloc_page(get_local_node(), true, (*LV3_ORDER)?, MemoryZone::Any, zeroed)?;
+    Some(unsafe { PhysLv3PageAddress::new_unchecked(page.get_address().get_u64()) })
+}
+
+//only returns memory of the given node, None if the node has no memory left or the level is not supported
+pub fn alloc_lv1_on_node(node: u32, zeroed: bool) -> Option<PhysLv1PageAddress> {
+    alloc_page(node, false, 0, MemoryZone::Any, zeroed)
+}
+
+pub fn alloc_lv2_on_node(node: u32, zeroed: bool) -> Option<PhysLv2PageAddress> {
+    let page = alloc_page(node, false, (*LV2_ORDER)?, MemoryZone::Any, zeroed)?;
+    Some(unsafe { PhysLv2PageAddress::new_unchecked(page.get_address().get_u64()) })
+}
+
+pub fn alloc_lv3_on_node(node: u32, zeroed: bool) -> Option<PhysLv3PageAddress> {
+    let page = alloc_page(node, false, (*LV3_ORDER)?, MemoryZone::Any, zeroed)?;
+    Some(unsafe { PhysLv3PageAddress::new_unchecked(page.get_address().get_u64()) })
+}
+
+//only returns memory inside the given zone, None if no node has memory left in it or the level is not supported
+pub fn alloc_lv1_in_zone(zone: MemoryZone, zeroed: bool) -> Option<PhysLv1PageAddress> {
+    alloc_page(get_local_node(), true, 0, zone, zeroed)
+}
+
+pub fn alloc_lv2_in_zone(zone: MemoryZone, zeroed: bool) -> Option<PhysLv2PageAddress> {
+    let page = alloc_page(get_local_node(), true, (*LV2_ORDER)?, zone, zeroed)?;
+    Some(unsafe { PhysLv2PageAddress::new_unchecked(page.get_address().get_u64()) })
+}
+
+///Allocates >number_of_pages< physically contiguous lv1 pages inside >zone< for DMA buffers and similar \
+///The first page is aligned to >alignment< bytes, the range may not be bigger than the biggest supported page \
+///Every page is a lv1 allocation of its own, free them with free_contiguous or page by page with free_lv1
+pub fn alloc_contiguous(
+    number_of_pages: u64,
+    alignment: u64,
+    zone: MemoryZone,
+    zeroed: bool,
+) -> Option<Range<PhysLv1PageAddress>> {
+    if number_of_pages == 0 {
+        return None;
+    }
+
+    let (page, already_zeroed) = match get_phase() {
+        PmmPhase::Uninitialized => panic!("PMM ERROR: NOT INITIALIZED"),
+        PmmPhase::Bootstrap => (
+            unsafe { BOOTSTRAP.lock() }.as_mut().unwrap().alloc_contiguous(
+                number_of_pages,
+                alignment,
+                zone.get_limit(),
+            )?,
+            false,
+        ),
+        PmmPhase::Numa => find_node(get_local_node(), true, |numa_node| {
+            numa_node.alloc_contiguous(number_of_pages, alignment, zone)
+        })?,
+    };
+
+    if zeroed && !already_zeroed {
+        zero_pages(page, number_of_pages);
+    }
+
+    Some(page..unsafe { page.offset_unchecked(number_of_pages as i64) })
+}
+
+///Drops one reference of every page in >range<
+pub fn free_contiguous(range: Range<PhysLv1PageAddress>) {
+    let mut page = range.start;
+
+    while page < range.end {
+        free_page(page, 0, false);
+        page = unsafe { page.offset_unchecked(1) };
+    }
+}
+
+//tells the pmm that the give page is allocated again
+//panics if the page is not allocated or the refcount overflows
+pub fn multi_alloc_lv1(page: PhysLv1PageAddress) {
+    multi_alloc_page(page, 0);
+}
+
+pub fn multi_alloc_lv2(page: PhysLv2PageAddress) {
+    multi_alloc_page(lv2_as_lv1(page), get_supported_order(*LV2_ORDER));
+}
+
+pub fn multi_alloc_lv3(page: PhysLv3PageAddress) {
+    multi_alloc_page(lv3_as_lv1(page), get_supported_order(*LV3_ORDER));
+}
+
+//frees the give page when the last user of the page calls this function
+//panics if the refcount underflows
+pub fn free_lv1(page: PhysLv1PageAddress) {
+    free_page(page, 0, false);
+}
+
+pub fn free_lv2(page: PhysLv2PageAddress) {
+    free_page(lv2_as_lv1(page), get_supported_order(*LV2_ORDER), false);
+}
+
+pub fn free_lv3(page: PhysLv3PageAddress) {
+    free_page(lv3_as_lv1(page), get_supported_order(*LV3_ORDER), false);
+}
+
+//like free_* for pages whose content is known to be zero (for example emptied page tables)
+//zeroed allocations of the page skip the zeroing then
+pub fn free_zeroed_lv1(page: PhysLv1PageAddress) {
+    free_page(page, 0, true);
+}
+
+pub fn free_zeroed_lv2(page: PhysLv2PageAddress) {
+    free_page(lv2_as_lv1(page), get_supported_order(*LV2_ORDER), true);
+}
+
+pub fn free_zeroed_lv3(page: PhysLv3PageAddress) {
+    free_page(lv3_as_lv1(page), get_supported_order(*LV3_ORDER), true);
+}
+
+///Allocates >count< lv1 pages for new page tables, either all of them or none \
+///None if no memory is left, so it can be used while a page root is locked
+pub fn try_alloc_pt_pages(count: u64) -> Option<Vec<PhysLv1PageAddress>> {
+    let mut pt_pages: Vec<PhysLv1PageAddress> = Vec::new();
+
+    for _ in 0..count {
+        match try_alloc_lv1(false) {
+            Some(page) => pt_pages.push(page),
+            None => {
+                for page in pt_pages {
+                    free_lv1(page);
+                }
+                return None;
+            }
+        }
+    }
+
+    Some(pt_pages)
+}
+
+///Allocates >count< pt pages and passes them to >map<, which calls one of the slice map_* functions of the hal \
+///The pages >map< did not take are freed afterwards, all of them if the mapping failed \
+///None if no memory is left, >map< is not called then
+pub fn map_with_pt_pages<E>(
+    count: u64,
+    map: impl FnOnce(&mut [PhysLv1PageAddress]) -> Result<usize, E>,
+) -> Option<Result<usize, E>> {
+    let mut pt_pages = try_alloc_pt_pages(count)?;
+
+    let result = map(&mut pt_pages);
+
+    //the count is exact unless the mapping failed
+    let used = *result.as_ref().unwrap_or(&0);
+    for page in pt_pages.drain(used..) {
+        free_lv1(page);
+    }
+
+    Some(result)
+}
+
+///Returns a copy of the descriptor of the given page \
+///Only availible once the NUMA phase is active, None for pages not managed by the pmm
+pub fn get_frame_descriptor(page: PhysLv1PageAddress) -> Option<FrameDescriptor> {
+    if get_phase() != PmmPhase::Numa {
+        return None;
+    }
+
+    NODES.iter().find_map(|slot| {
+        let guard = unsafe { slot.lock() };
+        let node = guard.as_ref()?;
+
+        if !node.contains(page.get_address()) {
+            return None;
+        }
+
+        Some(node.get_frame(page))
+    })
+}
+
+///Pinned pages panic instead of being freed, >page< has to be the first lv1 page of an allocation \
+///Only availible once the NUMA phase is active
+pub fn set_pinned(page: PhysLv1PageAddress, pinned: bool) {
+    if get_phase() != PmmPhase::Numa {
+        panic!("PMM ERROR: PINNING REQUIRES THE NUMA PHASE");
+    }
+
+    with_owning_node(page, |node| node.set_pinned(page, pinned));
+}
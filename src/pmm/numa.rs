@@ -0,0 +1,503 @@
+use crate::{
+    bal::memory_map::MemoryMapEntryType,
+    hal::{
+        interrupt::MASK_ALL,
+        memory::{
+            Lv1, MemoryAddressErrors, PhysAddress, PhysLv1PageAddress, PhysRange, LV1_PAGE_SIZE,
+        },
+    },
+    sync::spinlock::Spinlock,
+};
+
+use super::{
+    align_up,
+    bootstrap::BootstrapAllocator,
+    buddy::{self, BuddyAllocator},
+    frame::{FrameDescriptor, FRAME_PINNED, FRAME_RESERVED},
+    physical_map,
+    zone::{get_zone_bounds, get_zone_index, MemoryZone, ZONE_COUNT},
+};
+
+pub const MAX_NUMA_NODES: usize = 32;
+
+///Memory affinity range as reported by the firmware (ACPI SRAT) \
+///Ranges may include non usable memory, only the intersection with usable memory map entries gets managed
+#[derive(Clone, Copy, Debug)]
+pub struct NumaMemoryRange {
+    pub node: u32,
+    pub base: PhysAddress,
+    pub length: u64,
+}
+
+impl NumaMemoryRange {
+    #[inline]
+    pub fn get_range(&self) -> Result<PhysRange, MemoryAddressErrors> {
+        PhysRange::with_size(self.base.get_u64(), self.length)
+    }
+}
+
+const MAX_AFFINITY_RANGES: usize = 128;
+
+static AFFINITY: Spinlock<([NumaMemoryRange; MAX_AFFINITY_RANGES], usize)> = Spinlock::new(
+    (
+        [NumaMemoryRange {
+            node: 0,
+            base: unsafe { PhysAddress::new_unchecked(0) },
+            length: 0,
+        }; MAX_AFFINITY_RANGES],
+        0,
+    ),
+    MASK_ALL,
+);
+
+//One lock per node so that cores on different nodes dont contend
+pub(super) static NODES: [Spinlock<Option<NumaNode>>; MAX_NUMA_NODES] =
+    [const { Spinlock::new(None, MASK_ALL) }; MAX_NUMA_NODES];
+
+pub(super) struct NumaNode {
+    id: u32,
+    zones: [BuddyAllocator; ZONE_COUNT],
+    total_pages: u64,
+    allocations: [u64; buddy::MAX_ORDERS], //live allocations per order
+}
+
+impl NumaNode {
+    ///Creates the node from the usable memory inside its affinity ranges \
+    ///The allocator datastructures are placed inside the node's own memory \
+    ///Pages that the bootstrap allocator handed out stay allocated
+    pub(super) fn new(
+        id: u32,
+        affinity: &[NumaMemoryRange],
+        bootstrap: &BootstrapAllocator,
+    ) -> Option<NumaNode> {
+        let mut ranges: [PhysRange; buddy::MAX_REGIONS] = [PhysRange::empty(); buddy::MAX_REGIONS];
+        let mut range_count: usize = 0;
+
+        for entry in physical_map::iter() {
+            if entry.entry_type != MemoryMapEntryType::Usable {
+                continue;
+            }
+
+            let Ok(entry_range) = entry.get_range() else {
+                continue;
+            };
+
+            for_each_node_range(id, affinity, entry_range, |range| {
+                //Regions beyond the limit are left unmanaged, firmware with that many holes per node is not expected
+                if range_count < buddy::MAX_REGIONS {
+                    ranges[range_count] = range;
+                    range_count += 1;
+                }
+            });
+        }
+
+        if range_count == 0 {
+            return None;
+        }
+
+        let ranges = &ranges[..range_count];
+
+        let metadata_size = align_up(
+            ranges
+                .iter()
+                .map(|range| buddy::metadata_size(range.get_number_of_pages::<Lv1>()))
+                .sum(),
+            *LV1_PAGE_SIZE,
+        );
+
+        //The metadata is carved from the end of the first region that doesnt overlap the bootstrap region
+        let metadata = ranges
+            .iter()
+            .filter(|range| range.get_size() >= metadata_size)
+            .map(|range| unsafe {
+                PhysRange::with_size_unchecked(range.get_end() - metadata_size, metadata_size)
+            })
+            .find(|metadata| !metadata.overlaps(&bootstrap.get_range()))
+            .expect("PMM ERROR: NO SPACE FOR NUMA NODE METADATA");
+
+        let mut node = NumaNode {
+            id,
+            zones: [const { BuddyAllocator::new() }; ZONE_COUNT],
+            total_pages: 0,
+            allocations: [0; buddy::MAX_ORDERS],
+        };
+
+        let mut metadata_address = metadata.get_start();
+        for range in ranges.iter() {
+            (metadata_address, _) = node.add_range(*range, metadata_address);
+        }
+
+        let bootstrap_index = |address: u64| {
+            (address - bootstrap.get_base().get_address().get_u64()) / *LV1_PAGE_SIZE
+        };
+
+        let is_used = |address: u64| {
+            if metadata.contains_address(address) {
+                return true;
+            }
+
+            bootstrap.contains(PhysAddress::new_maskoff(address))
+                && bootstrap.is_allocated(bootstrap_index(address))
+        };
+
+        for range in ranges.iter() {
+            node.unreserve_range(*range, is_used);
+        }
+
+        //takes over the allocations of the bootstrap allocator
+        for (page, number_of_pages, refcount) in bootstrap.iter_allocations() {
+            if !node.contains(page.get_address()) {
+                continue;
+            }
+
+            let first_page_number = page.get_address().get_u64() / *LV1_PAGE_SIZE;
+
+            for page_number in first_page_number..first_page_number + number_of_pages {
+                let mut frame = node.get_frame_by_number(page_number);
+                frame.flags &= !FRAME_RESERVED;
+                node.set_frame_by_number(page_number, frame);
+            }
+
+            let order = number_of_pages.trailing_zeros();
+
+            let mut frame = node.get_frame_by_number(first_page_number);
+            frame.refcount = refcount as u32;
+            frame.order = order as u8;
+            node.set_frame_by_number(first_page_number, frame);
+
+            node.allocations[order as usize] += 1;
+        }
+
+        Some(node)
+    }
+
+    ///Adds memory that was not usable when the node was created (reclaimed bootloader memory) \
+    ///The descriptors are carved from the end of the range \
+    ///Returns the number of pages that could not be managed, either the range is too small to hold its descriptors \
+    ///or a zone has no free buddy region left
+    pub(super) fn add_memory(&mut self, range: PhysRange) -> u64 {
+        let metadata_size = align_up(
+            buddy::metadata_size(range.get_number_of_pages::<Lv1>()),
+            *LV1_PAGE_SIZE,
+        );
+
+        if range.get_size() <= metadata_size {
+            return range.get_number_of_pages::<Lv1>();
+        }
+
+        let metadata_start = range.get_end() - metadata_size;
+
+        let (_, unmanaged_pages) = self.add_range(range, metadata_start);
+        self.unreserve_range(range, |address| address >= metadata_start);
+
+        unmanaged_pages
+    }
+
+    //adds the range as reserved pages, regions are split at the zone boundaries so that the zones never share a buddy block
+    //returns the address after the used metadata and the number of pages that were left out because a zone had no free region
+    fn add_range(&mut self, range: PhysRange, metadata_address: u64) -> (u64, u64) {
+        let descriptor_node: u16 = self.id.try_into().expect("PMM ERROR: NUMA NODE ID TOO BIG");
+        let mut metadata_address = metadata_address;
+        let mut unmanaged_pages: u64 = 0;
+
+        for zone_index in 0..ZONE_COUNT {
+            let Some(region) = range.intersection(&get_zone_bounds(zone_index)) else {
+                continue;
+            };
+
+            let number_of_pages = region.get_number_of_pages::<Lv1>();
+
+            let added = unsafe {
+                self.zones[zone_index].add_region(
+                    PhysLv1PageAddress::new_maskoff(region.get_start()),
+                    number_of_pages,
+                    PhysAddress::new_maskoff(metadata_address),
+                    descriptor_node,
+                )
+            };
+
+            if !added {
+                unmanaged_pages += number_of_pages;
+                continue;
+            }
+
+            metadata_address += buddy::metadata_size(number_of_pages);
+            self.total_pages += number_of_pages;
+        }
+
+        (metadata_address, unmanaged_pages)
+    }
+
+    //hands all pages of the range that are not used to the buddy allocators in runs so that they coalesce right away
+    //pages of regions that add_range could not add are skipped
+    fn unreserve_range(&mut self, range: PhysRange, is_used: impl Fn(u64) -> bool) {
+        let end = range.get_end();
+        let mut run_start = range.get_start();
+        let mut address = range.get_start();
+
+        while address <= end {
+            let zone_boundary =
+                address > run_start && get_zone_index(address) != get_zone_index(run_start);
+            let used = address < end
+                && (is_used(address) || !self.contains(PhysAddress::new_maskoff(address)));
+
+            if address == end || zone_boundary || used {
+                if address > run_start {
+                    self.zones[get_zone_index(run_start)].unreserve_pages(
+                        PhysLv1PageAddress::new_maskoff(run_start),
+                        (address - run_start) / *LV1_PAGE_SIZE,
+                    );
+                }
+
+                run_start = if zone_boundary && !used {
+                    address
+                } else {
+                    address + *LV1_PAGE_SIZE
+                };
+            }
+
+            address += *LV1_PAGE_SIZE;
+        }
+    }
+
+    #[inline]
+    pub(super) fn get_id(&self) -> u32 {
+        self.id
+    }
+
+    #[inline]
+    pub(super) fn get_total_pages(&self) -> u64 {
+        self.total_pages
+    }
+
+    #[inline]
+    pub(super) fn get_free_pages(&self) -> u64 {
+        self.zones.iter().map(|zone| zone.get_free_pages()).sum()
+    }
+
+    ///Number of free buddy blocks of >order< over all zones
+    #[inline]
+    pub(super) fn get_free_blocks(&self, order: u32) -> u64 {
+        self.zones
+            .iter()
+            .map(|zone| zone.get_free_blocks(order))
+            .sum()
+    }
+
+    ///Number of live allocations of >order<
+    #[inline]
+    pub(super) fn get_allocations(&self, order: u32) -> u64 {
+        self.allocations[order as usize]
+    }
+
+    #[inline]
+    pub(super) fn contains(&self, address: PhysAddress) -> bool {
+        self.zones[get_zone_index(address.get_u64())].contains(address)
+    }
+
+    #[inline]
+    pub(super) fn get_frame(&self, page: PhysLv1PageAddress) -> FrameDescriptor {
+        self.get_frame_by_number(page.get_address().get_u64() / *LV1_PAGE_SIZE)
+    }
+
+    ///Allocates a naturally aligned page of the given order inside >zone<, its head descriptor starts with a refcount of 1 \
+    ///Returns if the page is already zeroed
+    pub(super) fn alloc(
+        &mut self,
+        order: u32,
+        zone: MemoryZone,
+    ) -> Option<(PhysLv1PageAddress, bool)> {
+        let (page, zeroed) = zone.get_candidate_zones().iter().find_map(|zone_index| {
+            let buddy = &mut self.zones[*zone_index];
+
+            if buddy.get_free_pages() < 1 << order {
+                return None;
+            }
+
+            buddy.alloc_pages(1 << order, 1 << order)
+        })?;
+
+        let page_number = page.get_address().get_u64() / *LV1_PAGE_SIZE;
+
+        let mut frame = self.get_frame_by_number(page_number);
+        frame.refcount = 1;
+        frame.order = order as u8;
+        self.set_frame_by_number(page_number, frame);
+
+        self.allocations[order as usize] += 1;
+        Some((page, zeroed))
+    }
+
+    ///Allocates >number_of_pages< physically contiguous lv1 pages inside >zone< whose start is aligned to >alignment< bytes \
+    ///Every page is its own lv1 allocation with a refcount of 1 \
+    ///Returns if the pages are already zeroed
+    pub(super) fn alloc_contiguous(
+        &mut self,
+        number_of_pages: u64,
+        alignment: u64,
+        zone: MemoryZone,
+    ) -> Option<(PhysLv1PageAddress, bool)> {
+        let alignment_pages = (alignment / *LV1_PAGE_SIZE).max(1);
+
+        let (page, zeroed) = zone.get_candidate_zones().iter().find_map(|zone_index| {
+            let buddy = &mut self.zones[*zone_index];
+
+            if buddy.get_free_pages() < number_of_pages {
+                return None;
+            }
+
+            buddy.alloc_pages(number_of_pages, alignment_pages)
+        })?;
+
+        let first_page_number = page.get_address().get_u64() / *LV1_PAGE_SIZE;
+
+        for page_number in first_page_number..first_page_number + number_of_pages {
+            let mut frame = self.get_frame_by_number(page_number);
+            frame.refcount = 1;
+            frame.order = 0;
+            self.set_frame_by_number(page_number, frame);
+        }
+
+        self.allocations[0] += number_of_pages;
+        Some((page, zeroed))
+    }
+
+    pub(super) fn multi_alloc(&mut self, page: PhysLv1PageAddress, order: u32) {
+        let page_number = page.get_address().get_u64() / *LV1_PAGE_SIZE;
+        let mut frame = self.check_allocated_head(page, order);
+
+        if frame.refcount == 0 {
+            panic!("PMM ERROR: MULTI ALLOC OF UNALLOCATED PAGE {:?}", page);
+        }
+
+        frame.refcount = frame
+            .refcount
+            .checked_add(1)
+            .unwrap_or_else(|| panic!("PMM ERROR: REFCOUNT OVERFLOW OF {:?}", page));
+
+        self.set_frame_by_number(page_number, frame);
+    }
+
+    ///Drops one reference and frees the page when it was the last one \
+    ///>zeroed< is only used if the page is freed
+    pub(super) fn free(&mut self, page: PhysLv1PageAddress, order: u32, zeroed: bool) {
+        let page_number = page.get_address().get_u64() / *LV1_PAGE_SIZE;
+        let mut frame = self.check_allocated_head(page, order);
+
+        frame.refcount = frame
+            .refcount
+            .checked_sub(1)
+            .unwrap_or_else(|| panic!("PMM ERROR: REFCOUNT UNDERFLOW OF {:?}", page));
+
+        if frame.refcount != 0 {
+            self.set_frame_by_number(page_number, frame);
+            return;
+        }
+
+        if frame.is_pinned() {
+            panic!("PMM ERROR: FREE OF PINNED PAGE {:?}", page);
+        }
+
+        frame.order = 0;
+        self.set_frame_by_number(page_number, frame);
+        self.allocations[order as usize] -= 1;
+        self.zones[get_zone_index(page.get_address().get_u64())].free_pages(
+            page,
+            1 << order,
+            zeroed,
+        );
+    }
+
+    pub(super) fn set_pinned(&mut self, page: PhysLv1PageAddress, pinned: bool) {
+        let page_number = page.get_address().get_u64() / *LV1_PAGE_SIZE;
+        let mut frame = self.get_frame_by_number(page_number);
+
+        if frame.refcount == 0 {
+            panic!("PMM ERROR: PIN OF UNALLOCATED PAGE {:?}", page);
+        }
+
+        frame.flags = if pinned {
+            frame.flags | FRAME_PINNED
+        } else {
+            frame.flags & !FRAME_PINNED
+        };
+        self.set_frame_by_number(page_number, frame);
+    }
+
+    #[inline]
+    fn get_frame_by_number(&self, page_number: u64) -> FrameDescriptor {
+        self.zones[get_zone_index(page_number * *LV1_PAGE_SIZE)].get_frame(page_number)
+    }
+
+    #[inline]
+    fn set_frame_by_number(&mut self, page_number: u64, frame: FrameDescriptor) {
+        self.zones[get_zone_index(page_number * *LV1_PAGE_SIZE)].set_frame(page_number, frame)
+    }
+
+    fn check_allocated_head(&self, page: PhysLv1PageAddress, order: u32) -> FrameDescriptor {
+        let frame = self.get_frame(page);
+
+        if frame.is_free() || frame.is_reserved() {
+            panic!("PMM ERROR: PAGE {:?} IS NOT ALLOCATED", page);
+        }
+
+        //tails of bigger pages have a refcount of 0 and are caught by the underflow check
+        if frame.refcount != 0 && frame.order != order as u8 {
+            panic!("PMM ERROR: PAGE LEVEL MISMATCH FOR {:?}", page);
+        }
+
+        frame
+    }
+}
+
+///Calls >f< with the page aligned parts of >entry_range< that belong to node >id< \
+///Without a SRAT all memory belongs to node 0
+pub(super) fn for_each_node_range(
+    id: u32,
+    affinity: &[NumaMemoryRange],
+    entry_range: PhysRange,
+    mut f: impl FnMut(PhysRange),
+) {
+    let mut call = |range: PhysRange| {
+        let range = range.align_inward::<Lv1>();
+
+        if !range.is_empty() {
+            f(range);
+        }
+    };
+
+    if affinity.is_empty() {
+        if id == 0 {
+            call(entry_range);
+        }
+        return;
+    }
+
+    for node_range in affinity.iter().filter(|range| range.node == id) {
+        if let Ok(node_range) = node_range.get_range()
+            && let Some(range) = entry_range.intersection(&node_range)
+        {
+            call(range);
+        }
+    }
+}
+
+///Keeps the affinity ranges for memory that is added after the NUMA phase started
+pub(super) fn save_affinity(affinity: &[NumaMemoryRange]) {
+    let mut saved = unsafe { AFFINITY.lock() };
+
+    if affinity.len() > MAX_AFFINITY_RANGES {
+        panic!(
+            "PMM ERROR: MORE THAN {} NUMA AFFINITY RANGES",
+            MAX_AFFINITY_RANGES
+        );
+    }
+
+    saved.0[..affinity.len()].copy_from_slice(affinity);
+    saved.1 = affinity.len();
+}
+
+///Returns a copy of the saved affinity ranges and their count
+pub(super) fn get_affinity() -> ([NumaMemoryRange; MAX_AFFINITY_RANGES], usize) {
+    *unsafe { AFFINITY.lock() }
+}
@@ -0,0 +1,46 @@
+//Copy of the bootloader memory map owned by the pmm
+//The bootloader responses live in bootloader reclaimable memory, so the pmm cant read them after the reclamation
+
+use crate::{
+    bal::memory_map::{iter_memory_map, MemoryMapEntry, MemoryMapEntryType},
+    hal::interrupt::MASK_ALL,
+    sync::spinlock::Spinlock,
+};
+
+pub(super) const MAX_MEMORY_MAP_ENTRIES: usize = 256;
+
+static PHYSICAL_MAP: Spinlock<[Option<MemoryMapEntry>; MAX_MEMORY_MAP_ENTRIES]> =
+    Spinlock::new([None; MAX_MEMORY_MAP_ENTRIES], MASK_ALL);
+
+pub(super) fn copy_from_bootloader() {
+    let mut map = unsafe { PHYSICAL_MAP.lock() };
+    for (index, entry) in iter_memory_map().enumerate() {
+        if index == MAX_MEMORY_MAP_ENTRIES {
+            panic!(
+                "PMM ERROR: MORE THAN {} MEMORY MAP ENTRIES",
+                MAX_MEMORY_MAP_ENTRIES
+            );
+        }
+
+        map[index] = Some(entry);
+    }
+}
+
+#[inline]
+pub(super) fn get_entry(index: usize) -> Option<MemoryMapEntry> {
+    unsafe { PHYSICAL_MAP.lock() }.get(index).copied().flatten()
+}
+
+///Iterates over the copied memory map, same order as the bootloader memory map
+pub(super) fn iter() -> impl Iterator<Item = MemoryMapEntry> {
+    (0..MAX_MEMORY_MAP_ENTRIES).map_while(get_entry)
+}
+
+///Turns all entries of type >from< into >to<
+pub(super) fn retype_entries(from: MemoryMapEntryType, to: MemoryMapEntryType) {
+    for entry in unsafe { PHYSICAL_MAP.lock() }.iter_mut().flatten() {
+        if entry.entry_type == from {
+            entry.entry_type = to;
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//Introspection of the memory owned by the pmm, meant for diagnosing leaks
+//Page counts are in lv1 pages unless they belong to a specific page level
+
+use crate::{
+    bal::memory_map::MemoryMapEntryType,
+    hal::memory::LV1_PAGE_SIZE,
+    kprintln,
+};
+
+use super::{
+    buddy::{LV2_ORDER, LV3_ORDER, MAX_ORDER},
+    get_phase,
+    numa::NumaNode,
+    physical_map, PmmPhase, BOOTSTRAP, NODES,
+};
+
+pub const PAGE_LEVEL_COUNT: usize = 3;
+
+///Counts in pages of the level
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PageLevelStatistics {
+    pub allocated: u64, //live allocations of this level
+    pub free: u64,      //pages of this level that could be allocated right now
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PmmStatistics {
+    pub total_pages: u64,
+    pub free_pages: u64,
+    pub allocated_pages: u64,
+    pub reserved_pages: u64, //pmm metadata and holes that are never handed out
+    pub levels: [PageLevelStatistics; PAGE_LEVEL_COUNT], //index 0 is lv1
+}
+
+///Bytes per memory map entry type as reported by the bootloader
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MemoryMapStatistics {
+    pub usable: u64,
+    pub reserved: u64,
+    pub acpi_reclaimable: u64,
+    pub acpi_nvs: u64,
+    pub bad_memory: u64,
+    pub bootloader_reclaimable: u64,
+    pub kernel_and_modules: u64,
+    pub framebuffer: u64,
+}
+
+impl PmmStatistics {
+    fn add(&mut self, other: &PmmStatistics) {
+        self.total_pages += other.total_pages;
+        self.free_pages += other.free_pages;
+        self.allocated_pages += other.allocated_pages;
+        self.reserved_pages += other.reserved_pages;
+
+        for (level, other_level) in self.levels.iter_mut().zip(other.levels.iter()) {
+            level.allocated += other_level.allocated;
+            level.free += other_level.free;
+        }
+    }
+}
+
+///Returns the orders of the supported page levels, None for unsupported levels
+fn get_level_orders() -> [Option<u32>; PAGE_LEVEL_COUNT] {
+    [Some(0), *LV2_ORDER, *LV3_ORDER]
+}
+
+fn get_numa_node_statistics(node: &NumaNode) -> PmmStatistics {
+    let mut statistics = PmmStatistics {
+        total_pages: node.get_total_pages(),
+        free_pages: node.get_free_pages(),
+        ..Default::default()
+    };
+
+    statistics.allocated_pages = (0..=*MAX_ORDER)
+        .map(|order| node.get_allocations(order) << order)
+        .sum();
+    statistics.reserved_pages =
+        statistics.total_pages - statistics.free_pages - statistics.allocated_pages;
+
+    for (level, order) in statistics.levels.iter_mut().zip(get_level_orders()) {
+        let Some(order) = order else {
+            continue;
+        };
+
+        level.allocated = node.get_allocations(order);
+        level.free = (order..=*MAX_ORDER)
+            .map(|free_order| node.get_free_blocks(free_order) << (free_order - order))
+            .sum();
+    }
+
+    statistics
+}
+
+//the bootstrap allocator has no free lists, only free lv1 pages are counted
+fn get_bootstrap_statistics() -> PmmStatistics {
+    let bootstrap = unsafe { BOOTSTRAP.lock() };
+    let bootstrap = bootstrap.as_ref().unwrap();
+    let level_orders = get_level_orders();
+
+    let mut statistics = PmmStatistics {
+        total_pages: bootstrap.get_number_of_pages(),
+        ..Default::default()
+    };
+
+    for (_, number_of_pages, _) in bootstrap.iter_allocations() {
+        statistics.allocated_pages += number_of_pages;
+
+        if let Some(level) = level_orders
+            .iter()
+            .position(|order| *order == Some(number_of_pages.trailing_zeros()))
+            && number_of_pages.is_power_of_two()
+        {
+            statistics.levels[level].allocated += 1;
+        }
+    }
+
+    statistics.free_pages = statistics.total_pages - statistics.allocated_pages;
+    statistics.levels[0].free = statistics.free_pages;
+
+    statistics
+}
+
+///Totals over all NUMA nodes (Bootstrap phase: the bootstrap region)
+pub fn get_statistics() -> PmmStatistics {
+    match get_phase() {
+        PmmPhase::Uninitialized => panic!("PMM ERROR: NOT INITIALIZED"),
+        PmmPhase::Bootstrap => get_bootstrap_statistics(),
+        PmmPhase::Numa => {
+            let mut statistics = PmmStatistics::default();
+
+            for slot in NODES.iter() {
+                if let Some(node) = unsafe { slot.lock() }.as_ref() {
+                    statistics.add(&get_numa_node_statistics(node));
+                }
+            }
+
+            statistics
+        }
+    }
+}
+
+///None if the node has no memory \
+///During the bootstrap phase all memory belongs to node 0
+pub fn get_node_statistics(node: u32) -> Option<PmmStatistics> {
+    match get_phase() {
+        PmmPhase::Uninitialized => panic!("PMM ERROR: NOT INITIALIZED"),
+        PmmPhase::Bootstrap => (node == 0).then(get_bootstrap_statistics),
+        PmmPhase::Numa => NODES.iter().find_map(|slot| {
+            let guard = unsafe { slot.lock() };
+            let numa_node = guard.as_ref()?;
+
+            (numa_node.get_id() == node).then(|| get_numa_node_statistics(numa_node))
+        }),
+    }
+}
+
+pub fn get_memory_map_statistics() -> MemoryMapStatistics {
+    let mut statistics = MemoryMapStatistics::default();
+
+    for entry in physical_map::iter() {
+        let total = match entry.entry_type {
+            MemoryMapEntryType::Usable => &mut statistics.usable,
+            MemoryMapEntryType::Reserved => &mut statistics.reserved,
+            MemoryMapEntryType::AcpiReclaimable => &mut statistics.acpi_reclaimable,
+            MemoryMapEntryType::AcpiNvs => &mut statistics.acpi_nvs,
+            MemoryMapEntryType::BadMemory => &mut statistics.bad_memory,
+            MemoryMapEntryType::BootloaderReclaimable => &mut statistics.bootloader_reclaimable,
+            MemoryMapEntryType::KernelAndModules => &mut statistics.kernel_and_modules,
+            MemoryMapEntryType::Framebuffer => &mut statistics.framebuffer,
+        };
+
+        *total += entry.length;
+    }
+
+    statistics
+}
+
+///Writes the bootloader memory map and the pmm statistics to the kernel log
+pub fn dump_physical_map() {
+    kprintln!("PMM: PHYSICAL MEMORY MAP");
+
+    for entry in physical_map::iter() {
+        kprintln!(
+            "  [{:#018x} - {:#018x}) {:>10} KiB {:?}",
+            entry.base.get_u64(),
+            entry.get_end(),
+            entry.length / 1024,
+            entry.entry_type
+        );
+    }
+
+    let map = get_memory_map_statistics();
+    kprintln!("PMM: MEMORY MAP TOTALS (KiB)");
+    kprintln!("  usable:                 {:>10}", map.usable / 1024);
+    kprintln!("  bootloader reclaimable: {:>10}", map.bootloader_reclaimable / 1024);
+    kprintln!("  acpi reclaimable:       {:>10}", map.acpi_reclaimable / 1024);
+    kprintln!("  acpi nvs:               {:>10}", map.acpi_nvs / 1024);
+    kprintln!("  kernel and modules:     {:>10}", map.kernel_and_modules / 1024);
+    kprintln!("  framebuffer:            {:>10}", map.framebuffer / 1024);
+    kprintln!("  reserved:               {:>10}", map.reserved / 1024);
+    kprintln!("  bad memory:             {:>10}", map.bad_memory / 1024);
+
+    if get_phase() == PmmPhase::Uninitialized {
+        return;
+    }
+
+    dump_statistics("TOTAL", &get_statistics());
+
+    if get_phase() == PmmPhase::Numa {
+        for slot in NODES.iter() {
+            let statistics = {
+                let guard = unsafe { slot.lock() };
+                guard.as_ref().map(|node| (node.get_id(), get_numa_node_statistics(node)))
+            };
+
+            //the lock is not held while writing to the log
+            if let Some((id, statistics)) = statistics {
+                kprintln!("PMM: NODE {}", id);
+                dump_statistics("NODE", &statistics);
+            }
+        }
+    }
+}
+
+fn dump_statistics(name: &str, statistics: &PmmStatistics) {
+    kprintln!(
+        "  {}: {} KiB total, {} KiB free, {} KiB allocated, {} KiB reserved",
+        name,
+        statistics.total_pages * *LV1_PAGE_SIZE / 1024,
+        statistics.free_pages * *LV1_PAGE_SIZE / 1024,
+        statistics.allocated_pages * *LV1_PAGE_SIZE / 1024,
+        statistics.reserved_pages * *LV1_PAGE_SIZE / 1024
+    );
+
+    for (index, level) in statistics.levels.iter().enumerate() {
+        kprintln!(
+            "    lv{}: {} allocated, {} free",
+            index + 1,
+            level.allocated,
+            level.free
+        );
+    }
+}
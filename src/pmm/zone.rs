@@ -0,0 +1,60 @@
+//Every NUMA node keeps one buddy allocator per zone so that constrained allocations dont have to search
+
+use crate::hal::memory::PhysRange;
+
+///Constrains where in physical memory an allocation is placed
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MemoryZone {
+    Any,
+    Below4GiB, //reachable by devices that only support 32bit DMA
+    Below1MiB, //reachable in real mode (AP trampolines)
+}
+
+pub(super) const ZONE_COUNT: usize = 3;
+pub(super) const ZONE_LOW: usize = 0; //below 1MiB
+pub(super) const ZONE_DMA32: usize = 1; //1MiB to 4GiB
+pub(super) const ZONE_NORMAL: usize = 2; //above 4GiB
+
+//First address after each zone
+const ZONE_ENDS: [u64; ZONE_COUNT] = [0x10_0000, 0x1_0000_0000, u64::MAX];
+
+impl MemoryZone {
+    ///Returns the first physical address that is outside of the zone
+    #[inline]
+    pub fn get_limit(&self) -> u64 {
+        match self {
+            MemoryZone::Any => ZONE_ENDS[ZONE_NORMAL],
+            MemoryZone::Below4GiB => ZONE_ENDS[ZONE_DMA32],
+            MemoryZone::Below1MiB => ZONE_ENDS[ZONE_LOW],
+        }
+    }
+
+    ///Zones that can serve the constraint, the scarce low zones are used last
+    #[inline]
+    pub(super) fn get_candidate_zones(&self) -> &'static [usize] {
+        match self {
+            MemoryZone::Any => &[ZONE_NORMAL, ZONE_DMA32, ZONE_LOW],
+            MemoryZone::Below4GiB => &[ZONE_DMA32, ZONE_LOW],
+            MemoryZone::Below1MiB => &[ZONE_LOW],
+        }
+    }
+}
+
+#[inline]
+pub(super) fn get_zone_index(address: u64) -> usize {
+    ZONE_ENDS
+        .iter()
+        .position(|end| address < *end)
+        .unwrap_or(ZONE_NORMAL)
+}
+
+///Returns the part of the physical address space that belongs to the zone
+#[inline]
+pub(super) fn get_zone_bounds(zone_index: usize) -> PhysRange {
+    let start = if zone_index == 0 { 0 } else { ZONE_ENDS[zone_index - 1] };
+
+    //ZONE_NORMAL reaches beyond the physical address space
+    let end = ZONE_ENDS[zone_index].min(PhysRange::get_address_space().get_end());
+
+    PhysRange::new(start, end).unwrap_or(PhysRange::empty())
+}
@@ -1,4 +1,4 @@
-use crate::hal::interrupt::{bump_irq_level, set_irq_level, IrqLevel};
+use crate::hal::interrupt::{bump_irq_level, service_pending_ipis, set_irq_level, IrqLevel};
 use core::{
     cell::UnsafeCell,
     fmt, hint,
@@ -66,6 +66,8 @@ impl<T> RwSpinlock<T> {
 
         //wait for read enter
         while ticket != self.ticket_enter.load(Ordering::Acquire) {
+            //the lock holder can wait for this cpu to handle its IPIs
+            service_pending_ipis();
             hint::spin_loop();
         }
 
@@ -74,7 +76,7 @@ impl<T> RwSpinlock<T> {
 
         ReadGuard {
             ticket_exit: &self.ticket_exit,
-            old_irqlv: old_irqlv,
+            old_irqlv,
             data: unsafe { &*self.data.get() },
         }
     }
@@ -89,12 +91,14 @@ impl<T> RwSpinlock<T> {
 
         //wait for read enter
         while ticket != self.ticket_enter.load(Ordering::Acquire) {
+            service_pending_ipis();
             hint::spin_loop();
         }
 
         //Waits until all Readers have left the critical section
         while self.ticket_enter.load(Ordering::Acquire) != self.ticket_exit.load(Ordering::Acquire)
         {
+            service_pending_ipis();
             hint::spin_loop();
         }
 
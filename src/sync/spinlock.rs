@@ -1,4 +1,4 @@
-use crate::hal::interrupt::{bump_irq_level, set_irq_level, IrqLevel};
+use crate::hal::interrupt::{bump_irq_level, service_pending_ipis, set_irq_level, IrqLevel};
 use core::{
     cell::UnsafeCell,
     fmt, hint,
@@ -47,6 +47,8 @@ impl<T> Spinlock<T> {
 
         //wait for turn
         while ticket != self.ticket_enter.load(Ordering::Acquire) {
+            //the lock holder can wait for this cpu to handle its IPIs
+            service_pending_ipis();
             hint::spin_loop();
         }
 
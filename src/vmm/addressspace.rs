@@ -0,0 +1,336 @@
+//Virtual address space of a root and the segments placed in it
+//Segments are kept in a BTreeMap keyed by their base address, the segment of an address is the last one that starts at or below it
+//A segment occupies its allocated and its reserved size, no other segment may be placed inside of that range
+//The upper half is shared by all roots, so kernel segments live in the kernel addressspace, which maps them in the kernel root
+//Every other addressspace only holds segments of its user half
+
+use core::{
+    ops::Bound,
+    ptr::null_mut,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use alloc::{alloc::Global, collections::BTreeMap};
+use lazy_static::lazy_static;
+
+use crate::{
+    hal::{
+        cpu::{get_cpu_id, MAX_CPUS},
+        interrupt::MASK_ALL,
+        memory::*,
+        paging::{self, PageRoot},
+    },
+    sync::spinlock::Spinlock,
+};
+
+use super::{Segment, SegmentError};
+
+//the addressspace whose root is loaded on the cpu, set by activate_addressspace
+static CURRENT_ADDRESSSPACES: [AtomicPtr<Spinlock<Addressspace>>; MAX_CPUS] =
+    [const { AtomicPtr::new(null_mut()) }; MAX_CPUS];
+
+lazy_static! {
+    static ref KERNEL_ADDRESSSPACE: Spinlock<Addressspace> = Spinlock::new(
+        Addressspace {
+            page_root: AddressspaceRoot::Kernel,
+            segments: BTreeMap::new_in(Global),
+        },
+        MASK_ALL
+    );
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressspaceHalf {
+    User,   //lower half, every root has its own
+    Kernel, //kernel segment window in the upper half
+}
+
+#[derive(Debug)]
+pub enum AddressspaceError {
+    SegmentOverlaps,
+    SegmentOutsideOfHalf, //the segment is not inside of the half of the addressspace
+    EmptySegment,
+    NoSuchSegment,
+    NoFreeRange,
+    InvalidAlignment, //has to be a power of two
+    Segment(SegmentError),
+}
+
+pub struct Addressspace {
+    page_root: AddressspaceRoot,
+    segments: BTreeMap<u64, Segment, Global>, //keyed by the base address
+}
+
+enum AddressspaceRoot {
+    Own(PageRoot), //Opaque Struct that contains arch dependant stuff
+    Kernel,        //the segments are mapped in the kernel root, see paging::with_kernel_page_root
+}
+
+impl AddressspaceHalf {
+    pub fn get_range(&self) -> VirtRange {
+        match self {
+            AddressspaceHalf::User => VirtRange::get_user_half(),
+            AddressspaceHalf::Kernel => VirtRange::get_kernel_segment_window(),
+        }
+    }
+}
+
+impl Addressspace {
+    ///Safety: the addressspace manages >page_root< from now on, the kernel half of the root has to be the one of the kernel root
+    pub unsafe fn new(page_root: PageRoot) -> Addressspace {
+        Addressspace {
+            page_root: AddressspaceRoot::Own(page_root),
+            segments: BTreeMap::new_in(Global),
+        }
+    }
+
+    ///The user half for every addressspace except the kernel addressspace
+    #[inline]
+    pub fn get_half(&self) -> AddressspaceHalf {
+        match self.page_root {
+            AddressspaceRoot::Own(_) => AddressspaceHalf::User,
+            AddressspaceRoot::Kernel => AddressspaceHalf::Kernel,
+        }
+    }
+
+    ///Calls >f< with the root the segments of the addressspace are mapped in \
+    ///Segments are created in this root and handed to insert_segment afterwards
+    pub fn with_page_root<R>(&mut self, f: impl FnOnce(&mut PageRoot) -> R) -> R {
+        with_root(&mut self.page_root, f)
+    }
+
+    ///Takes over >segment<, its occupied range has to be free and inside of the half of the addressspace \
+    ///A rejected segment is destroyed, use find_free_range or is_range_free before the segment is created
+    pub fn insert_segment(&mut self, segment: Segment) -> Result<(), AddressspaceError> {
+        let range = segment.get_occupied_range();
+
+        let result = if range.is_empty() {
+            Err(AddressspaceError::EmptySegment)
+        } else if !self.get_half().get_range().contains(&range) {
+            Err(AddressspaceError::SegmentOutsideOfHalf)
+        } else if !self.is_range_free(range) {
+            Err(AddressspaceError::SegmentOverlaps)
+        } else {
+            Ok(())
+        };
+
+        match result {
+            Ok(()) => {
+                self.segments.insert(range.get_start(), segment);
+                Ok(())
+            }
+            Err(error) => {
+                //the segment was never reachable through the addressspace
+                with_root(&mut self.page_root, |root| unsafe { segment.destroy(root) });
+                Err(error)
+            }
+        }
+    }
+
+    ///Unmaps the segment that starts at >base_address< and frees its range \
+    ///Safety: nothing may access the memory of the segment anymore
+    pub unsafe fn remove_segment(
+        &mut self,
+        base_address: VirtLv1PageAddress,
+    ) -> Result<(), AddressspaceError> {
+        let segment = self
+            .segments
+            .remove(&base_address.get_address().get_u64())
+            .ok_or(AddressspaceError::NoSuchSegment)?;
+
+        with_root(&mut self.page_root, |root| segment.destroy(root));
+        Ok(())
+    }
+
+    ///Shares the DataSegment at >base_address< copy on write with >other<, the clone is placed at the same address \
+    ///Only the user half is private to a root, so neither addressspace can be the kernel addressspace
+    pub fn clone_segment_cow(
+        &mut self,
+        base_address: VirtLv1PageAddress,
+        other: &mut Addressspace,
+    ) -> Result<(), AddressspaceError> {
+        let (AddressspaceRoot::Own(root), AddressspaceRoot::Own(other_root)) =
+            (&mut self.page_root, &mut other.page_root)
+        else {
+            return Err(AddressspaceError::SegmentOutsideOfHalf);
+        };
+
+        let segment = self
+            .segments
+            .get_mut(&base_address.get_address().get_u64())
+            .ok_or(AddressspaceError::NoSuchSegment)?;
+
+        let range = segment.get_occupied_range();
+        if !is_range_free(&other.segments, range) {
+            return Err(AddressspaceError::SegmentOverlaps);
+        }
+
+        let clone = segment
+            .clone_cow(root, other_root)
+            .map_err(AddressspaceError::Segment)?;
+
+        other.insert_segment(clone)
+    }
+
+    ///Returns the segment whose allocated size contains >address<
+    pub fn find_segment(&self, address: u64) -> Option<&Segment> {
+        self.segments
+            .range(..=address)
+            .next_back()
+            .map(|(_, segment)| segment)
+            .filter(|segment| segment.contains_address(address))
+    }
+
+    //find_segment with the root of the segment borrowed next to it, for changes of the segment
+    fn with_segment<R>(
+        &mut self,
+        address: u64,
+        f: impl FnOnce(&mut PageRoot, Option<&mut Segment>) -> R,
+    ) -> R {
+        let segment = self
+            .segments
+            .range_mut(..=address)
+            .next_back()
+            .map(|(_, segment)| segment)
+            .filter(|segment| segment.contains_address(address));
+
+        with_root(&mut self.page_root, |root| f(root, segment))
+    }
+
+    ///Whether no segment occupies a part of >range<
+    pub fn is_range_free(&self, range: VirtRange) -> bool {
+        is_range_free(&self.segments, range)
+    }
+
+    ///Finds the lowest free range of >size< bytes in the half of the addressspace that starts at a multiple of >alignment< \
+    ///>size< is rounded up to a lv1 page boundary and >alignment< is at least the lv1 page size
+    pub fn find_free_range(
+        &self,
+        size: u64,
+        alignment: u64,
+    ) -> Result<VirtRange, AddressspaceError> {
+        if !alignment.is_power_of_two() {
+            return Err(AddressspaceError::InvalidAlignment);
+        }
+
+        let alignment = alignment.max(*LV1_PAGE_SIZE);
+        let size = size
+            .checked_next_multiple_of(*LV1_PAGE_SIZE)
+            .filter(|size| *size > 0)
+            .ok_or(AddressspaceError::NoFreeRange)?;
+
+        let window = self.get_half().get_range();
+
+        //start of the range if it fits between >free_start< and >free_end<
+        let fit = |free_start: u64, free_end: u64| -> Option<VirtRange> {
+            let start = free_start.checked_next_multiple_of(alignment)?;
+            (start.checked_add(size)? <= free_end)
+                .then(|| unsafe { VirtRange::with_size_unchecked(start, size) })
+        };
+
+        let mut free_start = window.get_start();
+
+        let occupied = self
+            .segments
+            .range(window.get_start()..)
+            .map(|(_, segment)| segment.get_occupied_range())
+            .take_while(|range| window.contains(range));
+
+        for range in occupied {
+            if let Some(free) = fit(free_start, range.get_start()) {
+                return Ok(free);
+            }
+
+            free_start = range.get_end();
+        }
+
+        fit(free_start, window.get_end()).ok_or(AddressspaceError::NoFreeRange)
+    }
+
+    ///Destroys all segments and returns the root, the root is still active on every cpu it was activated on \
+    ///Safety: nothing may access the memory of the segments anymore
+    pub unsafe fn destroy(mut self) -> PageRoot {
+        let AddressspaceRoot::Own(mut page_root) = self.page_root else {
+            panic!("VMM ERROR: DESTROY OF THE KERNEL ADDRESSSPACE");
+        };
+
+        while let Some((_, segment)) = self.segments.pop_first() {
+            segment.destroy(&mut page_root);
+        }
+
+        page_root
+    }
+}
+
+fn with_root<R>(page_root: &mut AddressspaceRoot, f: impl FnOnce(&mut PageRoot) -> R) -> R {
+    match page_root {
+        AddressspaceRoot::Own(page_root) => f(page_root),
+        AddressspaceRoot::Kernel => {
+            paging::with_kernel_page_root(f).expect("VMM ERROR: NO KERNEL ROOT")
+        }
+    }
+}
+
+fn is_range_free(segments: &BTreeMap<u64, Segment, Global>, range: VirtRange) -> bool {
+    //occupied ranges never overlap, so only the neighbours of the start have to be checked
+    let below = segments.range(..=range.get_start()).next_back();
+    let above = segments
+        .range((Bound::Excluded(range.get_start()), Bound::Unbounded))
+        .next();
+
+    [below, above]
+        .into_iter()
+        .flatten()
+        .all(|(_, segment)| !segment.get_occupied_range().overlaps(&range))
+}
+
+///Holds the segments of the kernel segment window, they are mapped in the kernel root and reachable from every root
+pub fn get_kernel_addressspace() -> &'static Spinlock<Addressspace> {
+    &KERNEL_ADDRESSSPACE
+}
+
+///Loads the root of >addressspace< on the executing cpu, page faults of the cpu are resolved against it from now on \
+///Safety: >addressspace< has to stay alive until another addressspace is activated on the cpu
+pub unsafe fn activate_addressspace(addressspace: &Spinlock<Addressspace>) {
+    let current = &CURRENT_ADDRESSSPACES[get_cpu_id() as usize];
+    let addressspace = addressspace as *const Spinlock<Addressspace> as *mut Spinlock<Addressspace>;
+
+    //only one of the two addressspaces is locked at a time, two cpus that swap them must not deadlock
+    {
+        let addressspace = (*addressspace).lock();
+        let AddressspaceRoot::Own(page_root) = &addressspace.page_root else {
+            panic!("VMM ERROR: THE KERNEL ADDRESSSPACE HAS NO ROOT OF ITS OWN");
+        };
+        paging::activate_page_root(page_root);
+    }
+
+    let previous = current.swap(addressspace, Ordering::AcqRel);
+    if !previous.is_null() && previous != addressspace {
+        let previous = (*previous).lock();
+        if let AddressspaceRoot::Own(page_root) = &previous.page_root {
+            paging::deactivate_page_root(page_root);
+        }
+    }
+}
+
+///Calls >f< with the segment that contains >address< and the root it is mapped in, its addressspace is locked meanwhile \
+///Upper half addresses are looked up in the kernel addressspace, the others in the addressspace that is active on the executing cpu \
+///None if the address is in the lower half and no addressspace is active
+pub(super) fn find_segment_with_root<R>(
+    address: u64,
+    f: impl FnOnce(&mut PageRoot, Option<&mut Segment>) -> R,
+) -> Option<R> {
+    let addressspace = if (address as i64).is_negative() {
+        get_kernel_addressspace()
+    } else {
+        get_current_addressspace()?
+    };
+
+    Some(unsafe { addressspace.lock() }.with_segment(address, f))
+}
+
+//the addressspace that is active on the executing cpu
+fn get_current_addressspace() -> Option<&'static Spinlock<Addressspace>> {
+    let current = CURRENT_ADDRESSSPACES[get_cpu_id() as usize].load(Ordering::Acquire);
+    unsafe { current.as_ref() }
+}
@@ -0,0 +1,266 @@
+//Page fault resolution
+//A fault in the upper half is resolved against the kernel addressspace, any other against the addressspace that is active on the faulting cpu
+//The lock of that addressspace is held during the resolution
+//Changes of a segment are done under the same lock, so a fault that raced with a change of its page on another cpu
+//finds the change completed once it holds the lock. If the page table allows the access by then the fault was a false positive and the access is retried
+//Everything that can not be resolved ends in an oops
+//The kernel must not fault on the pages of an addressspace while it holds the lock of that addressspace
+
+use crate::{
+    hal::{
+        memory::*,
+        paging::{self, PageAttributes, PageFault, PageRoot},
+    },
+    kprintln,
+};
+
+use super::{
+    addressspace::find_segment_with_root, free_page, get_refcount, try_alloc_page, Page, Segment,
+    SegmentBehavior, SegmentError, SegmentsTypes,
+};
+
+#[derive(Debug)]
+enum FaultError {
+    NoAddressspace,
+    NoSegment,
+    AccessViolation, //the segment does not allow the access
+    Inconsistent,    //the page table does not match the segment
+    Segment(SegmentError),
+}
+
+///Called by the page fault handler of the arch \
+///Returns if the fault was resolved and the access can be retried, otherwise the kernel oopses
+pub fn handle_page_fault(fault: &PageFault) {
+    let resolved = find_segment_with_root(fault.address, |page_root, segment| {
+        let Some(segment) = segment else {
+            oops(fault, None, FaultError::NoSegment);
+        };
+
+        if let Err(error) = segment.resolve_fault(page_root, fault) {
+            oops(fault, Some(segment), error);
+        }
+    });
+
+    if resolved.is_none() {
+        oops(fault, None, FaultError::NoAddressspace);
+    }
+}
+
+fn oops(fault: &PageFault, segment: Option<&Segment>, error: FaultError) -> ! {
+    kprintln!("VMM OOPS: UNRESOLVED PAGE FAULT ({:?})", error);
+    kprintln!(
+        "  address {:#018x} ip {:#018x} {}{}{}{}{}{}",
+        fault.address,
+        fault.instruction_pointer,
+        if fault.present { "P" } else { "-" },
+        if fault.write { "W" } else { "R" },
+        if fault.user { "U" } else { "S" },
+        if fault.instruction_fetch { "X" } else { "-" },
+        if fault.protection_key { "K" } else { "-" },
+        if fault.malformed_table { "M" } else { "-" }
+    );
+
+    if let Some(segment) = segment {
+        kprintln!(
+            "  segment {:?} {:?} base {:#018x} allocated {:#x} reserved {:#x} {}{}",
+            segment.segment_type,
+            segment.segment_behavior,
+            segment.base_address.get_address().get_u64(),
+            segment.allocated_size,
+            segment.reserved_size,
+            if segment.kernel_mode { "S" } else { "U" },
+            if segment.global { "G" } else { "-" }
+        );
+
+        let offset = fault.address - segment.base_address.get_address().get_u64();
+        kprintln!(
+            "  offset {:#x} backed by {:?}",
+            offset,
+            segment.phys_pages.lookup(offset)
+        );
+    }
+
+    panic!("VMM ERROR: PAGE FAULT AT {:#x}", fault.address);
+}
+
+impl Segment {
+    fn resolve_fault(&mut self, root: &mut PageRoot, fault: &PageFault) -> Result<(), FaultError> {
+        if fault.malformed_table {
+            return Err(FaultError::Inconsistent);
+        }
+
+        //protection keys are not managed by segments
+        if fault.protection_key
+            || !is_access_allowed(&self.get_type_attributes(self.segment_type), fault)
+        {
+            return Err(FaultError::AccessViolation);
+        }
+
+        let offset = fault.address - self.base_address.get_address().get_u64();
+        let page = self.phys_pages.lookup(offset);
+
+        match page {
+            //only demand segments and cow clones of them have holes
+            Page::None if self.segment_behavior == SegmentBehavior::Normal => {
+                Err(FaultError::Inconsistent)
+            }
+            Page::None if self.segment_type == SegmentsTypes::StackSegment => {
+                self.grow_stack(root, offset)
+            }
+            Page::None => self.demand_zero(root, offset),
+            _ if is_fault_stale(root, fault) => Ok(()),
+            _ if fault.write && self.segment_behavior == SegmentBehavior::Cow => {
+                self.copy_on_write(root, offset, page)
+            }
+            _ => Err(FaultError::Inconsistent),
+        }
+    }
+
+    //a lv2 page is used if the whole lv2 block around the fault belongs to the segment and is still empty, otherwise a lv1 page
+    //lv3 pages are never allocated on demand
+    fn demand_zero(&mut self, root: &mut PageRoot, offset: u64) -> Result<(), FaultError> {
+        let base_address = self.base_address.get_address().get_u64();
+        let address = base_address + offset;
+
+        if *LV2_PAGE_SUPPORTED {
+            let lv2_address = address & *LV2_PAGE_MASK;
+
+            if lv2_address >= base_address
+                && lv2_address - base_address + *LV2_PAGE_SIZE <= self.allocated_size
+                && self
+                    .phys_pages
+                    .is_lv2_block_empty(lv2_address - base_address)
+                && let Some(page) = try_alloc_page(2, true)
+            {
+                return self.map_fault_page(root, lv2_address - base_address, page);
+            }
+        }
+
+        let page = try_alloc_page(1, true).ok_or(FaultError::Segment(SegmentError::OutOfMemory))?;
+        self.map_fault_page(root, (address & *LV1_PAGE_MASK) - base_address, page)
+    }
+
+    //stacks grow down, so every page from the faulting one up to the lowest present page is mapped
+    fn grow_stack(&mut self, root: &mut PageRoot, offset: u64) -> Result<(), FaultError> {
+        let first = offset - offset % *LV1_PAGE_SIZE;
+
+        let mut end = first + *LV1_PAGE_SIZE;
+        while end < self.allocated_size && self.phys_pages.lookup(end) == Page::None {
+            end += *LV1_PAGE_SIZE;
+        }
+
+        for page_offset in (first..end).step_by(*LV1_PAGE_SIZE as usize) {
+            let page =
+                try_alloc_page(1, true).ok_or(FaultError::Segment(SegmentError::OutOfMemory))?;
+            self.map_fault_page(root, page_offset, page)?;
+        }
+
+        Ok(())
+    }
+
+    //the segment gets a private writable copy of the page and drops its reference to the shared one
+    //if the segment holds the only reference left the page is made writable in place
+    fn copy_on_write(
+        &mut self,
+        root: &mut PageRoot,
+        offset: u64,
+        page: Page,
+    ) -> Result<(), FaultError> {
+        let page_offset =
+            offset - (self.base_address.get_address().get_u64() + offset) % page.get_size();
+        let attributes = self.get_type_attributes(self.segment_type);
+
+        //the reference of the segment can not be shared again while the addressspace is locked
+        if get_refcount(page) == Some(1) {
+            return unsafe { self.update_page_attributes(root, page_offset, page, attributes) }
+                .map_err(FaultError::Segment);
+        }
+
+        let copy = try_alloc_page(page.get_level(), false)
+            .ok_or(FaultError::Segment(SegmentError::OutOfMemory))?;
+
+        unsafe {
+            utility::memcpy(
+                get_hhdm_address(copy),
+                get_hhdm_address(page),
+                page.get_size() as usize,
+            );
+        }
+
+        if let Err(error) = unsafe { self.unmap_page(root, page_offset, page) } {
+            free_page(copy);
+            return Err(FaultError::Segment(error));
+        }
+        self.phys_pages.remove(page_offset);
+
+        if let Err(error) = self.map_page_with_attributes(root, page_offset, copy, attributes) {
+            free_page(copy);
+
+            //the segment keeps its reference to the shared page, mapped readonly as before
+            //the tables it needs were freed by the unmap just now, a segment with an unmapped page would be inconsistent
+            if let Err(rollback_error) =
+                self.map_page_with_attributes(root, page_offset, page, self.get_page_attributes())
+            {
+                panic!(
+                    "VMM ERROR: REMAP OF {:?} AT OFFSET {:#x} FAILED: {:?}",
+                    page, page_offset, rollback_error
+                );
+            }
+
+            return Err(FaultError::Segment(error));
+        }
+
+        free_page(page);
+        Ok(())
+    }
+
+    //>page< is new and private to the segment, so it is writable even in a cow segment
+    fn map_fault_page(
+        &mut self,
+        root: &mut PageRoot,
+        offset: u64,
+        page: Page,
+    ) -> Result<(), FaultError> {
+        let attributes = self.get_type_attributes(self.segment_type);
+
+        self.map_page_with_attributes(root, offset, page, attributes)
+            .map_err(|error| {
+                free_page(page);
+                FaultError::Segment(error)
+            })
+    }
+}
+
+fn is_access_allowed(attributes: &PageAttributes, fault: &PageFault) -> bool {
+    attributes.present
+        && (!fault.write || !attributes.readonly)
+        && (!fault.instruction_fetch || attributes.executable)
+        && (!fault.user || !attributes.supervisor)
+}
+
+//the page table allows the access by now, the fault raced with a change of the page
+fn is_fault_stale(root: &PageRoot, fault: &PageFault) -> bool {
+    let Ok(address) = VirtAddress::new(fault.address) else {
+        return false;
+    };
+
+    let attributes = match paging::get_single_page(root, address) {
+        Some(paging::Page::Lv1Page(page)) => page.attributes,
+        Some(paging::Page::Lv2Page(page)) => page.attributes,
+        Some(paging::Page::Lv3Page(page)) => page.attributes,
+        _ => return false,
+    };
+
+    is_access_allowed(&attributes, fault)
+}
+
+fn get_hhdm_address(page: Page) -> VirtAddress {
+    unsafe {
+        match page {
+            Page::None => panic!("VMM ERROR: NO PAGE"),
+            Page::Lv1(page) => page.get_address().to_virt_unchecked(),
+            Page::Lv2(page) => page.get_address().to_virt_unchecked(),
+            Page::Lv3(page) => page.get_address().to_virt_unchecked(),
+        }
+    }
+}
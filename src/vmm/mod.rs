@@ -0,0 +1,1132 @@
+//high level segmentation based memory management over low level paging
+//cs - code segment -> readonly
+//ds - data segment -> readonly/rw + nx
+//ss - stack segment -> rw + nx
+//mmios - mmio segment -> r/rw + nx
+
+mod addressspace;
+mod fault;
+
+use crate::{
+    hal::{
+        memory::*,
+        paging::{self, CachingMode, PageAttributes, PageRoot, PagingErros},
+    },
+    pmm::{self, MemoryZone},
+};
+use alloc::{boxed::Box, vec::Vec};
+
+//the interface of the vmm, the boot code does not use all of it yet
+#[allow(unused_imports)]
+pub use addressspace::{
+    activate_addressspace, get_kernel_addressspace, Addressspace, AddressspaceError,
+    AddressspaceHalf,
+};
+pub use fault::handle_page_fault;
+
+//TODO maybe make it so that lv3 cannot be allocated but only merged when a lv3 page is filled with smaller pages
+
+//The page blocks mirror the page table, a block covers the range of one page of its level
+//The boxes hold LV3_PAGE_SIZE / LV2_PAGE_SIZE and LV2_PAGE_SIZE / LV1_PAGE_SIZE entries, both are only known at runtime
+//A block at the end of a segment has all entries as well, the ones behind the end stay None
+
+enum Lv3PageBlock {
+    Lv3(Lv3Page),
+    Lv2(Box<[Option<Lv2PageBlock>]>),
+}
+
+enum Lv2PageBlock {
+    Lv2(Lv2Page),
+    Lv1(Box<[Option<Lv1Page>]>),
+}
+
+struct Lv1Page {
+    phys_page_index: PhysLv1PageAddress,
+}
+
+struct Lv2Page {
+    phys_page_index: PhysLv2PageAddress,
+}
+
+struct Lv3Page {
+    phys_page_index: PhysLv3PageAddress,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum SegmentsTypes {
+    //Size,     RWX,    Caching,    Demand
+    CodeSegment,         //Fixed,    ROX,    Default,    yes
+    DataSegment,         //Dynamic,  RW,     Default,    yes
+    DataROSegment,       //Fixed,    RO,     Default,    yes
+    StackSegment,        //Fixed,    RW,     Default,    yes (custom algorithm)
+    DeviceMMIOSegment,   //Fixed,    RW,     MMIO,       no
+    DeviceMMIOROSegment, //Fixed,    RO,     MMIO,       no
+    DMASegment,          //Fixed,    RW,     DMA,        no
+    IPCSegment,          //Dynamic,  RW,     Default,    no
+    IPCROSegment,        //Dynamic,  RO,     Default,    no
+    HHDMSegment,         //Fixed,    RW,     Default,    no
+}
+
+#[derive(Debug)]
+pub enum SegmentError {
+    FixedSizeSegmentCantHaveReservedSpace,
+    InvalidBaseAddress,
+    OutOfBounds,
+    InvalidSegmentTypeForConstructor, //
+    OutOfMemory,
+    Paging(PagingErros),
+}
+
+//top level of the page blocks, the base address of the segment is aligned to its page size
+enum HighestPageSize {
+    Lv3(Vec<Option<Lv3PageBlock>>),
+    Lv2(Vec<Option<Lv2PageBlock>>),
+    Lv1(Vec<Option<Lv1Page>>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Page {
+    None,
+    Lv1(PhysLv1PageAddress),
+    Lv2(PhysLv2PageAddress),
+    Lv3(PhysLv3PageAddress),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SegmentBehavior {
+    Normal,
+    Cow,
+    Demand,
+}
+
+impl Page {
+    ///Returns 0 for Page::None
+    pub fn get_size(&self) -> u64 {
+        match self {
+            Page::None => 0,
+            Page::Lv1(_) => *LV1_PAGE_SIZE,
+            Page::Lv2(_) => *LV2_PAGE_SIZE,
+            Page::Lv3(_) => *LV3_PAGE_SIZE,
+        }
+    }
+
+    fn get_level(&self) -> u8 {
+        match self {
+            Page::None => 0,
+            Page::Lv1(_) => 1,
+            Page::Lv2(_) => 2,
+            Page::Lv3(_) => 3,
+        }
+    }
+}
+
+fn get_page_size(level: u8) -> u64 {
+    match level {
+        3 => *LV3_PAGE_SIZE,
+        2 => *LV2_PAGE_SIZE,
+        _ => *LV1_PAGE_SIZE,
+    }
+}
+
+impl HighestPageSize {
+    //the biggest supported level whose pages can start at >base_address< and fit into >size<
+    fn new(base_address: VirtLv1PageAddress, size: u64) -> HighestPageSize {
+        let base_address = base_address.get_address().get_u64();
+        let fits = |page_size: u64| base_address % page_size == 0 && size >= page_size;
+
+        if *LV3_PAGE_SUPPORTED && fits(*LV3_PAGE_SIZE) {
+            HighestPageSize::Lv3(Vec::new())
+        } else if *LV2_PAGE_SUPPORTED && fits(*LV2_PAGE_SIZE) {
+            HighestPageSize::Lv2(Vec::new())
+        } else {
+            HighestPageSize::Lv1(Vec::new())
+        }
+    }
+
+    fn get_level(&self) -> u8 {
+        match self {
+            HighestPageSize::Lv3(_) => 3,
+            HighestPageSize::Lv2(_) => 2,
+            HighestPageSize::Lv1(_) => 1,
+        }
+    }
+
+    //adds empty entries until >size< bytes are covered
+    fn grow(&mut self, size: u64) {
+        let entries = |page_size: u64| size.div_ceil(page_size) as usize;
+
+        match self {
+            HighestPageSize::Lv3(blocks) if blocks.len() < entries(*LV3_PAGE_SIZE) => {
+                blocks.resize_with(entries(*LV3_PAGE_SIZE), || None)
+            }
+            HighestPageSize::Lv2(blocks) if blocks.len() < entries(*LV2_PAGE_SIZE) => {
+                blocks.resize_with(entries(*LV2_PAGE_SIZE), || None)
+            }
+            HighestPageSize::Lv1(pages) if pages.len() < entries(*LV1_PAGE_SIZE) => {
+                pages.resize_with(entries(*LV1_PAGE_SIZE), || None)
+            }
+            _ => {}
+        }
+    }
+
+    ///Panics if >page< overlaps a present page, does not fit into the block at >offset< or is outside of the segment
+    fn insert(&mut self, offset: u64, page: Page) {
+        let inserted = match self {
+            HighestPageSize::Lv3(blocks) => blocks
+                .get_mut((offset / *LV3_PAGE_SIZE) as usize)
+                .is_some_and(|slot| insert_lv3(slot, offset % *LV3_PAGE_SIZE, page)),
+            HighestPageSize::Lv2(blocks) => blocks
+                .get_mut((offset / *LV2_PAGE_SIZE) as usize)
+                .is_some_and(|slot| insert_lv2(slot, offset % *LV2_PAGE_SIZE, page)),
+            HighestPageSize::Lv1(pages) => pages
+                .get_mut((offset / *LV1_PAGE_SIZE) as usize)
+                .is_some_and(|slot| insert_lv1(slot, page)),
+        };
+
+        if !inserted {
+            panic!("VMM ERROR: {:?} DOES NOT FIT AT OFFSET {:#x}", page, offset);
+        }
+    }
+
+    ///Returns the page that contains >offset<
+    fn lookup(&self, offset: u64) -> Page {
+        match self {
+            HighestPageSize::Lv3(blocks) => blocks
+                .get((offset / *LV3_PAGE_SIZE) as usize)
+                .map_or(Page::None, |slot| lookup_lv3(slot, offset % *LV3_PAGE_SIZE)),
+            HighestPageSize::Lv2(blocks) => blocks
+                .get((offset / *LV2_PAGE_SIZE) as usize)
+                .map_or(Page::None, |slot| lookup_lv2(slot, offset % *LV2_PAGE_SIZE)),
+            HighestPageSize::Lv1(pages) => pages
+                .get((offset / *LV1_PAGE_SIZE) as usize)
+                .map_or(Page::None, lookup_lv1),
+        }
+    }
+
+    ///Removes the page that contains >offset< and returns it, the blocks that held it are kept
+    fn remove(&mut self, offset: u64) -> Page {
+        match self {
+            HighestPageSize::Lv3(blocks) => blocks
+                .get_mut((offset / *LV3_PAGE_SIZE) as usize)
+                .map_or(Page::None, |slot| remove_lv3(slot, offset % *LV3_PAGE_SIZE)),
+            HighestPageSize::Lv2(blocks) => blocks
+                .get_mut((offset / *LV2_PAGE_SIZE) as usize)
+                .map_or(Page::None, |slot| remove_lv2(slot, offset % *LV2_PAGE_SIZE)),
+            HighestPageSize::Lv1(pages) => pages
+                .get_mut((offset / *LV1_PAGE_SIZE) as usize)
+                .map_or(Page::None, remove_lv1),
+        }
+    }
+
+    ///Whether no page overlaps the lv2 block at >offset<, false if the top level is Lv1
+    fn is_lv2_block_empty(&self, offset: u64) -> bool {
+        match self {
+            HighestPageSize::Lv3(blocks) => match blocks.get((offset / *LV3_PAGE_SIZE) as usize) {
+                Some(Some(Lv3PageBlock::Lv3(_))) => false,
+                Some(Some(Lv3PageBlock::Lv2(blocks))) => {
+                    blocks[((offset % *LV3_PAGE_SIZE) / *LV2_PAGE_SIZE) as usize].is_none()
+                }
+                _ => true,
+            },
+            HighestPageSize::Lv2(blocks) => !matches!(
+                blocks.get((offset / *LV2_PAGE_SIZE) as usize),
+                Some(Some(_))
+            ),
+            HighestPageSize::Lv1(_) => false,
+        }
+    }
+
+    ///Returns all present pages with their offset, sorted by offset
+    fn get_pages(&self) -> Vec<(u64, Page)> {
+        let mut pages: Vec<(u64, Page)> = Vec::new();
+
+        match self {
+            HighestPageSize::Lv3(blocks) => {
+                for (index, slot) in blocks.iter().enumerate() {
+                    collect_lv3(slot, index as u64 * *LV3_PAGE_SIZE, &mut pages);
+                }
+            }
+            HighestPageSize::Lv2(blocks) => {
+                for (index, slot) in blocks.iter().enumerate() {
+                    collect_lv2(slot, index as u64 * *LV2_PAGE_SIZE, &mut pages);
+                }
+            }
+            HighestPageSize::Lv1(slots) => {
+                for (index, slot) in slots.iter().enumerate() {
+                    collect_lv1(slot, index as u64 * *LV1_PAGE_SIZE, &mut pages);
+                }
+            }
+        }
+
+        pages
+    }
+}
+
+fn new_block<T>(entries: u64) -> Box<[Option<T>]> {
+    (0..entries).map(|_| None).collect()
+}
+
+//the insert_* functions return false if the page does not fit into the slot
+fn insert_lv3(slot: &mut Option<Lv3PageBlock>, offset: u64, page: Page) -> bool {
+    match page {
+        Page::Lv3(phys_page_index) if slot.is_none() => {
+            *slot = Some(Lv3PageBlock::Lv3(Lv3Page { phys_page_index }));
+            true
+        }
+        Page::Lv2(_) | Page::Lv1(_) => {
+            let block = slot.get_or_insert_with(|| {
+                Lv3PageBlock::Lv2(new_block(*LV3_PAGE_SIZE / *LV2_PAGE_SIZE))
+            });
+
+            match block {
+                Lv3PageBlock::Lv2(blocks) => insert_lv2(
+                    &mut blocks[(offset / *LV2_PAGE_SIZE) as usize],
+                    offset % *LV2_PAGE_SIZE,
+                    page,
+                ),
+                Lv3PageBlock::Lv3(_) => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+fn insert_lv2(slot: &mut Option<Lv2PageBlock>, offset: u64, page: Page) -> bool {
+    match page {
+        Page::Lv2(phys_page_index) if slot.is_none() => {
+            *slot = Some(Lv2PageBlock::Lv2(Lv2Page { phys_page_index }));
+            true
+        }
+        Page::Lv1(_) => {
+            let block = slot.get_or_insert_with(|| {
+                Lv2PageBlock::Lv1(new_block(*LV2_PAGE_SIZE / *LV1_PAGE_SIZE))
+            });
+
+            match block {
+                Lv2PageBlock::Lv1(pages) => {
+                    insert_lv1(&mut pages[(offset / *LV1_PAGE_SIZE) as usize], page)
+                }
+                Lv2PageBlock::Lv2(_) => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+fn insert_lv1(slot: &mut Option<Lv1Page>, page: Page) -> bool {
+    match page {
+        Page::Lv1(phys_page_index) if slot.is_none() => {
+            *slot = Some(Lv1Page { phys_page_index });
+            true
+        }
+        _ => false,
+    }
+}
+
+fn lookup_lv3(slot: &Option<Lv3PageBlock>, offset: u64) -> Page {
+    match slot {
+        Some(Lv3PageBlock::Lv3(page)) => Page::Lv3(page.phys_page_index),
+        Some(Lv3PageBlock::Lv2(blocks)) => lookup_lv2(
+            &blocks[(offset / *LV2_PAGE_SIZE) as usize],
+            offset % *LV2_PAGE_SIZE,
+        ),
+        None => Page::None,
+    }
+}
+
+fn lookup_lv2(slot: &Option<Lv2PageBlock>, offset: u64) -> Page {
+    match slot {
+        Some(Lv2PageBlock::Lv2(page)) => Page::Lv2(page.phys_page_index),
+        Some(Lv2PageBlock::Lv1(pages)) => lookup_lv1(&pages[(offset / *LV1_PAGE_SIZE) as usize]),
+        None => Page::None,
+    }
+}
+
+fn lookup_lv1(slot: &Option<Lv1Page>) -> Page {
+    slot.as_ref()
+        .map_or(Page::None, |page| Page::Lv1(page.phys_page_index))
+}
+
+fn collect_lv3(slot: &Option<Lv3PageBlock>, offset: u64, pages: &mut Vec<(u64, Page)>) {
+    match slot {
+        Some(Lv3PageBlock::Lv3(page)) => pages.push((offset, Page::Lv3(page.phys_page_index))),
+        Some(Lv3PageBlock::Lv2(blocks)) => {
+            for (index, slot) in blocks.iter().enumerate() {
+                collect_lv2(slot, offset + index as u64 * *LV2_PAGE_SIZE, pages);
+            }
+        }
+        None => {}
+    }
+}
+
+fn collect_lv2(slot: &Option<Lv2PageBlock>, offset: u64, pages: &mut Vec<(u64, Page)>) {
+    match slot {
+        Some(Lv2PageBlock::Lv2(page)) => pages.push((offset, Page::Lv2(page.phys_page_index))),
+        Some(Lv2PageBlock::Lv1(slots)) => {
+            for (index, slot) in slots.iter().enumerate() {
+                collect_lv1(slot, offset + index as u64 * *LV1_PAGE_SIZE, pages);
+            }
+        }
+        None => {}
+    }
+}
+
+fn collect_lv1(slot: &Option<Lv1Page>, offset: u64, pages: &mut Vec<(u64, Page)>) {
+    if let Some(page) = slot {
+        pages.push((offset, Page::Lv1(page.phys_page_index)));
+    }
+}
+
+fn remove_lv3(slot: &mut Option<Lv3PageBlock>, offset: u64) -> Page {
+    match slot {
+        Some(Lv3PageBlock::Lv3(page)) => {
+            let page = Page::Lv3(page.phys_page_index);
+            *slot = None;
+            page
+        }
+        Some(Lv3PageBlock::Lv2(blocks)) => remove_lv2(
+            &mut blocks[(offset / *LV2_PAGE_SIZE) as usize],
+            offset % *LV2_PAGE_SIZE,
+        ),
+        None => Page::None,
+    }
+}
+
+fn remove_lv2(slot: &mut Option<Lv2PageBlock>, offset: u64) -> Page {
+    match slot {
+        Some(Lv2PageBlock::Lv2(page)) => {
+            let page = Page::Lv2(page.phys_page_index);
+            *slot = None;
+            page
+        }
+        Some(Lv2PageBlock::Lv1(pages)) => {
+            remove_lv1(&mut pages[(offset / *LV1_PAGE_SIZE) as usize])
+        }
+        None => Page::None,
+    }
+}
+
+fn remove_lv1(slot: &mut Option<Lv1Page>) -> Page {
+    slot.take()
+        .map_or(Page::None, |page| Page::Lv1(page.phys_page_index))
+}
+
+fn try_alloc_page(level: u8, zeroed: bool) -> Option<Page> {
+    match level {
+        3 => pmm::try_alloc_lv3(zeroed).map(Page::Lv3),
+        2 => pmm::try_alloc_lv2(zeroed).map(Page::Lv2),
+        _ => pmm::try_alloc_lv1(zeroed).map(Page::Lv1),
+    }
+}
+
+fn multi_alloc_page(page: Page) {
+    match page {
+        Page::None => {}
+        Page::Lv1(page) => pmm::multi_alloc_lv1(page),
+        Page::Lv2(page) => pmm::multi_alloc_lv2(page),
+        Page::Lv3(page) => pmm::multi_alloc_lv3(page),
+    }
+}
+
+//None while the pmm does not track refcounts yet
+fn get_refcount(page: Page) -> Option<u32> {
+    let head = match page {
+        Page::None => return None,
+        Page::Lv1(page) => page,
+        Page::Lv2(page) => page.to_lv1(),
+        Page::Lv3(page) => page.to_lv1(),
+    };
+
+    pmm::get_frame_descriptor(head).map(|descriptor| descriptor.get_refcount())
+}
+
+fn free_page(page: Page) {
+    match page {
+        Page::None => {}
+        Page::Lv1(page) => pmm::free_lv1(page),
+        Page::Lv2(page) => pmm::free_lv2(page),
+        Page::Lv3(page) => pmm::free_lv3(page),
+    }
+}
+
+//let the specialized segments listed in the enum wrap a unspecialized segment to reduce code duplication and complexity
+//maybe just have the segment type with subtypes that enforce specific behavior
+pub struct Segment {
+    segment_type: SegmentsTypes,
+    kernel_mode: bool, //if the segment should require privileged access (ring0)
+    global: bool,
+    base_address: VirtLv1PageAddress,
+    phys_pages: HighestPageSize,
+    allocated_size: u64, //Size of the memory region that is demand backed with pages (some segment types dont support demand paging)
+    reserved_size: u64, //Size of the memory region (starting after allocated_size) that is guraanted to not to intersect with other segments
+    segment_behavior: SegmentBehavior,
+}
+
+impl Segment {
+    ///Creates the specified mappings and allocates the specified amount of memory (NOTE: will round up the segment size to a lv1 page boundary)
+    ///Limited to:
+    ///    DataSegment
+    ///    StackSegment
+    ///    DMASegment (physically contiguous, may not be bigger than the biggest supported page)
+    pub fn new(
+        root: &mut PageRoot,
+        p_segment_type: SegmentsTypes,
+        p_kernel: bool,
+        p_global: bool,
+        p_base_address: VirtLv1PageAddress,
+        p_allocated_size: u64,
+        p_reserved_size: u64,
+    ) -> Result<Segment, SegmentError> {
+        if p_base_address.get_address().get_u64() == 0 {
+            return Err(SegmentError::InvalidBaseAddress);
+        }
+
+        match p_segment_type {
+            SegmentsTypes::DataSegment
+            | SegmentsTypes::StackSegment
+            | SegmentsTypes::DMASegment => {}
+            _ => return Err(SegmentError::InvalidSegmentTypeForConstructor),
+        }
+
+        if p_reserved_size > 0 {
+            match p_segment_type {
+                SegmentsTypes::StackSegment | SegmentsTypes::DMASegment => {
+                    return Err(SegmentError::FixedSizeSegmentCantHaveReservedSpace)
+                }
+                _ => {}
+            }
+        }
+
+        let mut segment = Segment::new_unbacked(
+            p_segment_type,
+            p_kernel,
+            p_global,
+            p_base_address,
+            p_allocated_size,
+            p_reserved_size,
+            SegmentBehavior::Normal,
+        )?;
+
+        let result = match p_segment_type {
+            SegmentsTypes::DMASegment => segment.map_contiguous(root),
+            _ => segment.map_allocated(root),
+        };
+
+        if let Err(error) = result {
+            unsafe { segment.destroy(root) };
+            return Err(error);
+        }
+
+        Ok(segment)
+    }
+
+    ///Creates a segment on the given phys pages and multi allocs them if the segment type indicates usable memory (NOTE: HHDMSegment doesnt multi allocs memory)
+    ///The pages are placed one after another starting at >p_base_address<, which has to be aligned to the biggest page of >p_phys_pages<
+    ///Limited to:
+    ///    CodeSegment
+    ///    DataROSegment
+    ///    DeviceMMIOSegment
+    ///    DeviceMMIOROSegment
+    ///    IPCSegment
+    ///    IPCROSegment
+    ///    HHDMSegment
+    pub fn new_with_memory(
+        root: &mut PageRoot,
+        p_segment_type: SegmentsTypes,
+        p_kernel: bool,
+        p_global: bool,
+        p_base_address: VirtLv1PageAddress,
+        p_phys_pages: &[Page],
+        p_reserved_size: u64,
+    ) -> Result<Segment, SegmentError> {
+        match p_segment_type {
+            SegmentsTypes::CodeSegment
+            | SegmentsTypes::DataROSegment
+            | SegmentsTypes::DeviceMMIOSegment
+            | SegmentsTypes::DeviceMMIOROSegment
+            | SegmentsTypes::IPCSegment
+            | SegmentsTypes::IPCROSegment
+            | SegmentsTypes::HHDMSegment => {}
+            _ => return Err(SegmentError::InvalidSegmentTypeForConstructor),
+        }
+
+        if p_reserved_size > 0 {
+            match p_segment_type {
+                SegmentsTypes::IPCSegment | SegmentsTypes::IPCROSegment => {}
+                _ => return Err(SegmentError::FixedSizeSegmentCantHaveReservedSpace),
+            }
+        }
+
+        Segment::new_on_pages(
+            root,
+            p_segment_type,
+            p_kernel,
+            p_global,
+            p_base_address,
+            p_phys_pages,
+            p_reserved_size,
+            SegmentBehavior::Normal,
+        )
+    }
+
+    ///Creates a segment on the given phys pages with RO permissions and does cow
+    ///The pages are multi allocated and placed like in new_with_memory
+    ///Limited to:
+    ///    DataSegment
+    pub fn new_cow(
+        root: &mut PageRoot,
+        p_kernel: bool,
+        p_global: bool,
+        p_base_address: VirtLv1PageAddress,
+        p_phys_pages: &[Page],
+        p_reserved_size: u64,
+    ) -> Result<Segment, SegmentError> {
+        Segment::new_on_pages(
+            root,
+            SegmentsTypes::DataSegment,
+            p_kernel,
+            p_global,
+            p_base_address,
+            p_phys_pages,
+            p_reserved_size,
+            SegmentBehavior::Cow,
+        )
+    }
+
+    ///Creates the specified mappings and demand allocates the specified amount of zeroed memory (NOTE: will round up the segment size to a lv1 page boundary)
+    ///Nothing is mapped until the first access to a page
+    ///Limited to:
+    ///    CodeSegment
+    ///    DataSegment
+    ///    DataROSegment
+    ///    StackSegment
+    pub fn new_demand(
+        p_segment_type: SegmentsTypes,
+        p_kernel: bool,
+        p_global: bool,
+        p_base_address: VirtLv1PageAddress,
+        p_allocated_size: u64,
+        p_reserved_size: u64,
+    ) -> Result<Segment, SegmentError> {
+        match p_segment_type {
+            SegmentsTypes::CodeSegment
+            | SegmentsTypes::DataSegment
+            | SegmentsTypes::DataROSegment
+            | SegmentsTypes::StackSegment => {}
+            _ => return Err(SegmentError::InvalidSegmentTypeForConstructor),
+        }
+
+        if p_reserved_size > 0 && p_segment_type != SegmentsTypes::DataSegment {
+            return Err(SegmentError::FixedSizeSegmentCantHaveReservedSpace);
+        }
+
+        Segment::new_unbacked(
+            p_segment_type,
+            p_kernel,
+            p_global,
+            p_base_address,
+            p_allocated_size,
+            p_reserved_size,
+            SegmentBehavior::Demand,
+        )
+    }
+
+    ///Used to create certain Segments without using demand paging
+    ///The new type is fixed size, so the reserved space has to be empty
+    ///Limited to:
+    ///    DataSegment -> CodeSegment
+    ///    DataSegment -> DataROSegment
+    pub fn change_segment_type(
+        &mut self,
+        root: &mut PageRoot,
+        p_segment_type: SegmentsTypes,
+    ) -> Result<(), SegmentError> {
+        match (self.segment_type, p_segment_type) {
+            (SegmentsTypes::DataSegment, SegmentsTypes::CodeSegment)
+            | (SegmentsTypes::DataSegment, SegmentsTypes::DataROSegment)
+                if self.segment_behavior != SegmentBehavior::Cow => {}
+            _ => return Err(SegmentError::InvalidSegmentTypeForConstructor),
+        }
+
+        if self.reserved_size > 0 {
+            return Err(SegmentError::FixedSizeSegmentCantHaveReservedSpace);
+        }
+
+        let attributes = self.get_type_attributes(p_segment_type);
+
+        for (offset, page) in self.phys_pages.get_pages() {
+            unsafe { self.update_page_attributes(root, offset, page, attributes)? };
+        }
+
+        self.segment_type = p_segment_type;
+        Ok(())
+    }
+
+    ///Shares the pages of a DataSegment with a new segment at the same address in >clone_root< \
+    ///Both segments are cow afterwards, the pages are readonly in both roots until they are written \
+    ///Holes of a demand segment stay demand zero in both segments
+    pub fn clone_cow(
+        &mut self,
+        root: &mut PageRoot,
+        clone_root: &mut PageRoot,
+    ) -> Result<Segment, SegmentError> {
+        if self.segment_type != SegmentsTypes::DataSegment {
+            return Err(SegmentError::InvalidSegmentTypeForConstructor);
+        }
+
+        self.segment_behavior = SegmentBehavior::Cow;
+        let attributes = self.get_page_attributes();
+        let pages = self.phys_pages.get_pages();
+
+        //pages copied by earlier cow faults are writable, they are shared again from now on
+        for (offset, page) in &pages {
+            unsafe { self.update_page_attributes(root, *offset, *page, attributes)? };
+        }
+
+        let mut clone = Segment::new_unbacked(
+            self.segment_type,
+            self.kernel_mode,
+            self.global,
+            self.base_address,
+            self.allocated_size,
+            self.reserved_size,
+            SegmentBehavior::Cow,
+        )?;
+
+        for (offset, page) in pages {
+            if let Err(error) = clone.map_page(clone_root, offset, page) {
+                unsafe { clone.destroy(clone_root) };
+                return Err(error);
+            }
+
+            //the reference is dropped again by the copy or by destroy
+            multi_alloc_page(page);
+        }
+
+        Ok(clone)
+    }
+
+    ///Unmaps all pages of the segment and drops its references to them \
+    ///Safety: nothing may access the memory of the segment anymore
+    pub unsafe fn destroy(self, root: &mut PageRoot) {
+        let refcounted = self.is_refcounted();
+
+        for (offset, page) in self.phys_pages.get_pages() {
+            if let Err(error) = self.unmap_page(root, offset, page) {
+                panic!(
+                    "VMM ERROR: UNMAP OF {:?} AT OFFSET {:#x} FAILED: {:?}",
+                    page, offset, error
+                );
+            }
+
+            if refcounted {
+                free_page(page);
+            }
+        }
+    }
+
+    pub fn get_segment_type(&self) -> SegmentsTypes {
+        self.segment_type
+    }
+
+    pub fn is_kernel(&self) -> bool {
+        self.kernel_mode
+    }
+
+    pub fn is_global(&self) -> bool {
+        self.global
+    }
+
+    pub fn get_segment_base_address(&self) -> VirtLv1PageAddress {
+        self.base_address
+    }
+
+    pub fn get_allocated_size(&self) -> u64 {
+        self.allocated_size
+    }
+
+    pub fn get_reserved_size(&self) -> u64 {
+        self.reserved_size
+    }
+
+    ///Attributes of all pages of the segment, derived from the segment type \
+    ///Pages of a cow segment are readonly until they are copied
+    pub fn get_page_attributes(&self) -> PageAttributes {
+        let mut attributes = self.get_type_attributes(self.segment_type);
+        attributes.readonly |= self.segment_behavior == SegmentBehavior::Cow;
+        attributes
+    }
+
+    ///Range that no other segment may overlap, the allocated size followed by the reserved size
+    pub fn get_occupied_range(&self) -> VirtRange {
+        //checked when the segment was created
+        unsafe {
+            VirtRange::with_size_unchecked(
+                self.base_address.get_address().get_u64(),
+                self.allocated_size + self.reserved_size,
+            )
+        }
+    }
+
+    pub fn contains_address(&self, address: u64) -> bool {
+        let base_address = self.base_address.get_address().get_u64();
+        address >= base_address && address - base_address < self.allocated_size
+    }
+
+    ///Returns the page that backs >address<, Page::None if it is not backed yet
+    pub fn virt_to_phys_translation(&self, address: VirtAddress) -> Result<Page, SegmentError> {
+        if address < self.base_address.get_address()
+            || address.get_u64()
+                >= (self.base_address.get_address().get_u64() + self.allocated_size)
+        {
+            return Err(SegmentError::OutOfBounds);
+        }
+
+        Ok(self
+            .phys_pages
+            .lookup(address.get_u64() - self.base_address.get_address().get_u64()))
+    }
+
+    //checks the range of the segment and creates it without any pages
+    fn new_unbacked(
+        segment_type: SegmentsTypes,
+        kernel_mode: bool,
+        global: bool,
+        base_address: VirtLv1PageAddress,
+        allocated_size: u64,
+        reserved_size: u64,
+        segment_behavior: SegmentBehavior,
+    ) -> Result<Segment, SegmentError> {
+        if base_address.get_address().get_u64() == 0 {
+            return Err(SegmentError::InvalidBaseAddress);
+        }
+
+        let allocated_size = allocated_size
+            .checked_next_multiple_of(*LV1_PAGE_SIZE)
+            .ok_or(SegmentError::OutOfBounds)?;
+
+        //the reserved space has to be addressable as well
+        allocated_size
+            .checked_add(reserved_size)
+            .and_then(|size| VirtRange::with_size(base_address.get_address().get_u64(), size).ok())
+            .ok_or(SegmentError::OutOfBounds)?;
+
+        let mut phys_pages = HighestPageSize::new(base_address, allocated_size);
+        phys_pages.grow(allocated_size);
+
+        Ok(Segment {
+            segment_type,
+            kernel_mode,
+            global,
+            base_address,
+            phys_pages,
+            allocated_size,
+            reserved_size,
+            segment_behavior,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn new_on_pages(
+        root: &mut PageRoot,
+        segment_type: SegmentsTypes,
+        kernel_mode: bool,
+        global: bool,
+        base_address: VirtLv1PageAddress,
+        phys_pages: &[Page],
+        reserved_size: u64,
+        segment_behavior: SegmentBehavior,
+    ) -> Result<Segment, SegmentError> {
+        let allocated_size = phys_pages
+            .iter()
+            .try_fold(0u64, |size, page| size.checked_add(page.get_size()))
+            .ok_or(SegmentError::OutOfBounds)?;
+
+        let mut segment = Segment::new_unbacked(
+            segment_type,
+            kernel_mode,
+            global,
+            base_address,
+            allocated_size,
+            reserved_size,
+            segment_behavior,
+        )?;
+
+        let mut offset = 0;
+        for page in phys_pages {
+            let address = base_address.get_address().get_u64() + offset;
+
+            let result = if page.get_level() > segment.phys_pages.get_level()
+                || address % page.get_size().max(1) != 0
+            {
+                Err(SegmentError::InvalidBaseAddress)
+            } else {
+                segment.map_page(root, offset, *page)
+            };
+
+            if let Err(error) = result {
+                unsafe { segment.destroy(root) };
+                return Err(error);
+            }
+
+            //the reference is dropped again by destroy
+            if segment.is_refcounted() {
+                multi_alloc_page(*page);
+            }
+
+            offset += page.get_size();
+        }
+
+        Ok(segment)
+    }
+
+    //backs the allocated size with new zeroed pages, as big as the alignment allows
+    //a smaller page is tried if the pmm has no page of the level left
+    fn map_allocated(&mut self, root: &mut PageRoot) -> Result<(), SegmentError> {
+        let mut offset = 0;
+
+        while offset < self.allocated_size {
+            let mut level = self.get_fitting_level(offset);
+
+            let page = loop {
+                match try_alloc_page(level, true) {
+                    Some(page) => break page,
+                    None if level == 1 => return Err(SegmentError::OutOfMemory),
+                    None => level -= 1,
+                }
+            };
+
+            if let Err(error) = self.map_page(root, offset, page) {
+                free_page(page);
+                return Err(error);
+            }
+
+            offset += page.get_size();
+        }
+
+        Ok(())
+    }
+
+    //DMA buffers have to be physically contiguous, every page is its own lv1 allocation
+    //they are placed below 4GiB so that devices with 32bit DMA can reach them as well
+    fn map_contiguous(&mut self, root: &mut PageRoot) -> Result<(), SegmentError> {
+        if self.allocated_size == 0 {
+            return Ok(());
+        }
+
+        let pages = pmm::alloc_contiguous(
+            self.allocated_size / *LV1_PAGE_SIZE,
+            *LV1_PAGE_SIZE,
+            MemoryZone::Below4GiB,
+            true,
+        )
+        .ok_or(SegmentError::OutOfMemory)?;
+
+        let mut page = pages.start;
+        let mut offset = 0;
+
+        while page < pages.end {
+            //the pages that are mapped already are freed by destroy
+            if let Err(error) = self.map_page(root, offset, Page::Lv1(page)) {
+                pmm::free_contiguous(page..pages.end);
+                return Err(error);
+            }
+
+            page = unsafe { page.offset_unchecked(1) };
+            offset += *LV1_PAGE_SIZE;
+        }
+
+        Ok(())
+    }
+
+    //maps >page< at >offset< and adds it to the page blocks, the reference of >page< is taken over by the segment
+    fn map_page(
+        &mut self,
+        root: &mut PageRoot,
+        offset: u64,
+        page: Page,
+    ) -> Result<(), SegmentError> {
+        let attributes = self.get_page_attributes();
+        self.map_page_with_attributes(root, offset, page, attributes)
+    }
+
+    fn map_page_with_attributes(
+        &mut self,
+        root: &mut PageRoot,
+        offset: u64,
+        page: Page,
+        attributes: PageAttributes,
+    ) -> Result<(), SegmentError> {
+        let address = self.base_address.get_address().get_u64() + offset;
+
+        let result = unsafe {
+            match page {
+                Page::None => return Ok(()),
+                Page::Lv1(mut phys_page) => {
+                    let virt_page = VirtLv1PageAddress::new_unchecked(address);
+                    pmm::map_with_pt_pages(
+                        paging::needed_pt_pages_lv1(root, virt_page, 1),
+                        |pt_pages| {
+                            paging::map_slice_lv1_page(
+                                root,
+                                pt_pages,
+                                core::slice::from_mut(&mut phys_page),
+                                virt_page,
+                                attributes,
+                            )
+                        },
+                    )
+                }
+                Page::Lv2(mut phys_page) => {
+                    let virt_page = VirtLv2PageAddress::new_unchecked(address);
+                    pmm::map_with_pt_pages(
+                        paging::needed_pt_pages_lv2(root, virt_page, 1),
+                        |pt_pages| {
+                            paging::map_slice_lv2_page(
+                                root,
+                                pt_pages,
+                                core::slice::from_mut(&mut phys_page),
+                                virt_page,
+                                attributes,
+                            )
+                        },
+                    )
+                }
+                Page::Lv3(mut phys_page) => {
+                    let virt_page = VirtLv3PageAddress::new_unchecked(address);
+                    pmm::map_with_pt_pages(
+                        paging::needed_pt_pages_lv3(root, virt_page, 1),
+                        |pt_pages| {
+                            paging::map_slice_lv3_page(
+                                root,
+                                pt_pages,
+                                core::slice::from_mut(&mut phys_page),
+                                virt_page,
+                                attributes,
+                            )
+                        },
+                    )
+                }
+            }
+        };
+
+        result
+            .ok_or(SegmentError::OutOfMemory)?
+            .map_err(SegmentError::Paging)?;
+
+        self.phys_pages.insert(offset, page);
+        Ok(())
+    }
+
+    //changes the attributes of the mapping of >page< at >offset<
+    unsafe fn update_page_attributes(
+        &self,
+        root: &mut PageRoot,
+        offset: u64,
+        page: Page,
+        attributes: PageAttributes,
+    ) -> Result<(), SegmentError> {
+        let range = VirtRange::with_size_unchecked(
+            self.base_address.get_address().get_u64() + offset,
+            page.get_size(),
+        );
+
+        //whole pages are never split, so this is 0 unless the page table does not match the segment
+        let mut pt_pages = pmm::try_alloc_pt_pages(paging::needed_pt_pages_update(root, range))
+            .ok_or(SegmentError::OutOfMemory)?;
+
+        let result = paging::update_page_attributes(root, &mut pt_pages, range, attributes, false);
+
+        for pt_page in pt_pages {
+            pmm::free_lv1(pt_page);
+        }
+
+        for table in result.map_err(SegmentError::Paging)? {
+            pmm::free_lv1(table);
+        }
+
+        Ok(())
+    }
+
+    //removes the mapping of >page< at >offset< from >root<, the page blocks and the page itself are not changed
+    unsafe fn unmap_page(
+        &self,
+        root: &mut PageRoot,
+        offset: u64,
+        page: Page,
+    ) -> Result<(), SegmentError> {
+        let address = self.base_address.get_address().get_u64() + offset;
+
+        let freed_tables = match page {
+            Page::None => return Ok(()),
+            Page::Lv1(_) => {
+                paging::unmap_lv1_page(root, VirtLv1PageAddress::new_unchecked(address), 1)
+            }
+            Page::Lv2(_) => {
+                paging::unmap_lv2_page(root, VirtLv2PageAddress::new_unchecked(address), 1)
+            }
+            Page::Lv3(_) => {
+                paging::unmap_lv3_page(root, VirtLv3PageAddress::new_unchecked(address), 1)
+            }
+        }
+        .map_err(SegmentError::Paging)?;
+
+        //all entries of an emptied table are cleared
+        for table in freed_tables {
+            pmm::free_zeroed_lv1(table);
+        }
+
+        Ok(())
+    }
+
+    //the biggest page that starts at >offset< and ends inside of the allocated size
+    fn get_fitting_level(&self, offset: u64) -> u8 {
+        let address = self.base_address.get_address().get_u64() + offset;
+        let remaining = self.allocated_size - offset;
+
+        (2..=self.phys_pages.get_level())
+            .rev()
+            .find(|level| {
+                let page_size = get_page_size(*level);
+                address % page_size == 0 && remaining >= page_size
+            })
+            .unwrap_or(1)
+    }
+
+    fn get_type_attributes(&self, segment_type: SegmentsTypes) -> PageAttributes {
+        let (readonly, executable, caching_mode) = match segment_type {
+            SegmentsTypes::CodeSegment => (true, true, CachingMode::Default),
+            SegmentsTypes::DataROSegment | SegmentsTypes::IPCROSegment => {
+                (true, false, CachingMode::Default)
+            }
+            SegmentsTypes::DataSegment
+            | SegmentsTypes::StackSegment
+            | SegmentsTypes::IPCSegment
+            | SegmentsTypes::HHDMSegment => (false, false, CachingMode::Default),
+            SegmentsTypes::DeviceMMIOSegment => (false, false, CachingMode::MMIO),
+            SegmentsTypes::DeviceMMIOROSegment => (true, false, CachingMode::MMIO),
+            SegmentsTypes::DMASegment => (false, false, CachingMode::DMA),
+        };
+
+        PageAttributes {
+            present: true,
+            readonly,
+            executable,
+            supervisor: self.kernel_mode,
+            global: self.global,
+            protection_key: 0,
+            caching_mode,
+        }
+    }
+
+    //MMIO is not memory of the pmm and the HHDM maps memory that belongs to someone else
+    fn is_refcounted(&self) -> bool {
+        !matches!(
+            self.segment_type,
+            SegmentsTypes::DeviceMMIOSegment
+                | SegmentsTypes::DeviceMMIOROSegment
+                | SegmentsTypes::HHDMSegment
+        )
+    }
+}